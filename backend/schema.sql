@@ -39,6 +39,12 @@ ALTER SCHEMA forum OWNER TO postgres;
 SET search_path TO pg_catalog,public,forum;
 -- ddl-end --
 
+-- object: pg_trgm | type: EXTENSION --
+-- DROP EXTENSION IF EXISTS pg_trgm CASCADE;
+CREATE EXTENSION pg_trgm
+WITH SCHEMA public;
+-- ddl-end --
+
 -- object: forum.user_role | type: TYPE --
 -- DROP TYPE IF EXISTS forum.user_role CASCADE;
 CREATE TYPE forum.user_role AS
@@ -55,6 +61,38 @@ ENUM ('active','inactive','banned');
 ALTER TYPE forum.user_status OWNER TO postgres;
 -- ddl-end --
 
+-- object: forum.chat_room_access | type: TYPE --
+-- DROP TYPE IF EXISTS forum.chat_room_access CASCADE;
+CREATE TYPE forum.chat_room_access AS
+ENUM ('public','section','roles');
+-- ddl-end --
+ALTER TYPE forum.chat_room_access OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.report_target | type: TYPE --
+-- DROP TYPE IF EXISTS forum.report_target CASCADE;
+CREATE TYPE forum.report_target AS
+ENUM ('post','thread','chat','pm');
+-- ddl-end --
+ALTER TYPE forum.report_target OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.report_reason | type: TYPE --
+-- DROP TYPE IF EXISTS forum.report_reason CASCADE;
+CREATE TYPE forum.report_reason AS
+ENUM ('spam','harassment','hate','illegal','off_topic','other');
+-- ddl-end --
+ALTER TYPE forum.report_reason OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.report_status | type: TYPE --
+-- DROP TYPE IF EXISTS forum.report_status CASCADE;
+CREATE TYPE forum.report_status AS
+ENUM ('open','resolved','dismissed');
+-- ddl-end --
+ALTER TYPE forum.report_status OWNER TO postgres;
+-- ddl-end --
+
 -- object: forum.section_id_seq | type: SEQUENCE --
 -- DROP SEQUENCE IF EXISTS forum.section_id_seq CASCADE;
 CREATE SEQUENCE forum.section_id_seq
@@ -91,12 +129,26 @@ CREATE TABLE forum.sections (
 	id int8 NOT NULL DEFAULT nextval('forum.section_id_seq'::regclass),
 	name varchar(100) NOT NULL,
 	description varchar(255),
-	CONSTRAINT id_pkc PRIMARY KEY (id)
+	parent_id int8,
+	"position" int4 NOT NULL DEFAULT 0,
+	category boolean NOT NULL DEFAULT false,
+	CONSTRAINT id_pkc PRIMARY KEY (id),
+	CONSTRAINT section_not_own_parent CHECK (parent_id <> id)
 );
 -- ddl-end --
 ALTER TABLE forum.sections OWNER TO postgres;
 -- ddl-end --
 
+-- object: sections_parent | type: INDEX --
+-- DROP INDEX IF EXISTS forum.sections_parent CASCADE;
+CREATE INDEX sections_parent ON forum.sections
+USING btree
+(
+	parent_id,
+	"position"
+);
+-- ddl-end --
+
 -- object: forum.threads | type: TABLE --
 -- DROP TABLE IF EXISTS forum.threads CASCADE;
 CREATE TABLE forum.threads (
@@ -105,15 +157,72 @@ CREATE TABLE forum.threads (
 	created_at timestamptz NOT NULL DEFAULT NOW(),
 	content text NOT NULL,
 	author uuid NOT NULL,
-	section int8 NOT NULL,
+	section_id int8 NOT NULL,
 	locked boolean NOT NULL DEFAULT false,
 	sticky boolean NOT NULL DEFAULT false,
+	content_html text,
+	announcement boolean NOT NULL DEFAULT false,
+	redirect_to int8,
+	modified_at timestamptz,
+	edit_count int4 NOT NULL DEFAULT 0,
+	deleted_at timestamptz,
+	deleted_by uuid,
+	search_vector tsvector GENERATED ALWAYS AS (setweight(to_tsvector('simple', title), 'A') || setweight(to_tsvector('simple', content), 'B')) STORED,
 	CONSTRAINT id_pk PRIMARY KEY (id)
 );
 -- ddl-end --
 ALTER TABLE forum.threads OWNER TO postgres;
 -- ddl-end --
 
+-- object: threads_section | type: INDEX --
+-- DROP INDEX IF EXISTS forum.threads_section CASCADE;
+CREATE INDEX threads_section ON forum.threads
+USING btree
+(
+	section_id,
+	created_at
+);
+-- ddl-end --
+
+-- object: threads_redirect | type: INDEX --
+-- DROP INDEX IF EXISTS forum.threads_redirect CASCADE;
+CREATE INDEX threads_redirect ON forum.threads
+USING btree
+(
+	redirect_to
+)
+WHERE (redirect_to IS NOT NULL);
+-- ddl-end --
+
+-- object: threads_deleted | type: INDEX --
+-- DROP INDEX IF EXISTS forum.threads_deleted CASCADE;
+CREATE INDEX threads_deleted ON forum.threads
+USING btree
+(
+	deleted_at
+)
+WHERE (deleted_at IS NOT NULL);
+-- ddl-end --
+
+-- object: threads_announcement | type: INDEX --
+-- DROP INDEX IF EXISTS forum.threads_announcement CASCADE;
+CREATE INDEX threads_announcement ON forum.threads
+USING btree
+(
+	created_at
+)
+WHERE (announcement);
+-- ddl-end --
+
+-- object: threads_search | type: INDEX --
+-- DROP INDEX IF EXISTS forum.threads_search CASCADE;
+CREATE INDEX threads_search ON forum.threads
+USING gin
+(
+	search_vector
+);
+-- ddl-end --
+
 -- object: forum.seq_s | type: SEQUENCE --
 -- DROP SEQUENCE IF EXISTS forum.seq_s CASCADE;
 CREATE SEQUENCE forum.seq_s
@@ -148,6 +257,7 @@ CREATE TABLE forum.users (
 	x_id varchar(100),
 	banned_until timestamptz,
 	last_online timestamptz,
+	received_likes int8 NOT NULL DEFAULT 0,
 	CONSTRAINT users_pk PRIMARY KEY (id),
 	CONSTRAINT ban_until_check CHECK (banned_until > NOW()),
 	CONSTRAINT name_unique UNIQUE (name),
@@ -157,6 +267,24 @@ CREATE TABLE forum.users (
 ALTER TABLE forum.users OWNER TO postgres;
 -- ddl-end --
 
+-- object: users_name_trgm | type: INDEX --
+-- DROP INDEX IF EXISTS forum.users_name_trgm CASCADE;
+CREATE INDEX users_name_trgm ON forum.users
+USING gin
+(
+	name gin_trgm_ops
+);
+-- ddl-end --
+
+-- object: users_email_lower | type: INDEX --
+-- DROP INDEX IF EXISTS forum.users_email_lower CASCADE;
+CREATE INDEX users_email_lower ON forum.users
+USING btree
+(
+	lower(email)
+);
+-- ddl-end --
+
 -- object: forum.posts | type: TABLE --
 -- DROP TABLE IF EXISTS forum.posts CASCADE;
 CREATE TABLE forum.posts (
@@ -168,12 +296,56 @@ CREATE TABLE forum.posts (
 	created_at timestamptz NOT NULL DEFAULT NOW(),
 	modified_at timestamptz,
 	likes int4 NOT NULL DEFAULT 0,
+	content_html text,
+	edit_count int4 NOT NULL DEFAULT 0,
+	deleted_at timestamptz,
+	deleted_by uuid,
+	search_vector tsvector GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED,
 	CONSTRAINT post_pk PRIMARY KEY (id)
 );
 -- ddl-end --
 ALTER TABLE forum.posts OWNER TO postgres;
 -- ddl-end --
 
+-- object: posts_deleted | type: INDEX --
+-- DROP INDEX IF EXISTS forum.posts_deleted CASCADE;
+CREATE INDEX posts_deleted ON forum.posts
+USING btree
+(
+	deleted_at
+)
+WHERE (deleted_at IS NOT NULL);
+-- ddl-end --
+
+-- object: posts_search | type: INDEX --
+-- DROP INDEX IF EXISTS forum.posts_search CASCADE;
+CREATE INDEX posts_search ON forum.posts
+USING gin
+(
+	search_vector
+);
+-- ddl-end --
+
+-- object: posts_topic_comments | type: INDEX --
+-- DROP INDEX IF EXISTS forum.posts_topic_comments CASCADE;
+CREATE INDEX posts_topic_comments ON forum.posts
+USING btree
+(
+	topic,
+	comments,
+	id
+);
+-- ddl-end --
+
+-- object: posts_comments | type: INDEX --
+-- DROP INDEX IF EXISTS forum.posts_comments CASCADE;
+CREATE INDEX posts_comments ON forum.posts
+USING btree
+(
+	comments
+);
+-- ddl-end --
+
 -- object: forum.allowed_id | type: SEQUENCE --
 -- DROP SEQUENCE IF EXISTS forum.allowed_id CASCADE;
 CREATE SEQUENCE forum.allowed_id
@@ -193,9 +365,14 @@ ALTER SEQUENCE forum.allowed_id OWNER TO postgres;
 -- DROP TABLE IF EXISTS forum.sections_allowed CASCADE;
 CREATE TABLE forum.sections_allowed (
 	id int4 NOT NULL DEFAULT nextval('forum.allowed_id'::regclass),
-	section int8 NOT NULL,
+	section_id int8 NOT NULL,
 	role forum.user_role NOT NULL,
-	CONSTRAINT allowed_pk PRIMARY KEY (id)
+	can_view boolean NOT NULL DEFAULT true,
+	can_create_thread boolean NOT NULL DEFAULT true,
+	can_reply boolean NOT NULL DEFAULT true,
+	can_moderate boolean NOT NULL DEFAULT false,
+	CONSTRAINT allowed_pk PRIMARY KEY (id),
+	CONSTRAINT allowed_role_unique UNIQUE (section_id,role)
 );
 -- ddl-end --
 ALTER TABLE forum.sections_allowed OWNER TO postgres;
@@ -229,6 +406,85 @@ CREATE TABLE forum.hashtags (
 ALTER TABLE forum.hashtags OWNER TO postgres;
 -- ddl-end --
 
+-- object: hashtags_tag_prefix | type: INDEX --
+-- DROP INDEX IF EXISTS forum.hashtags_tag_prefix CASCADE;
+CREATE INDEX hashtags_tag_prefix ON forum.hashtags
+USING btree
+(
+	tag varchar_pattern_ops
+);
+-- ddl-end --
+
+-- object: hashtags_topic | type: INDEX --
+-- DROP INDEX IF EXISTS forum.hashtags_topic CASCADE;
+CREATE INDEX hashtags_topic ON forum.hashtags
+USING btree
+(
+	topic
+);
+-- ddl-end --
+
+-- object: forum.chat_room_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.chat_room_seq CASCADE;
+CREATE SEQUENCE forum.chat_room_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.chat_room_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.chat_rooms | type: TABLE --
+-- DROP TABLE IF EXISTS forum.chat_rooms CASCADE;
+CREATE TABLE forum.chat_rooms (
+	id int4 NOT NULL DEFAULT nextval('forum.chat_room_seq'::regclass),
+	name varchar(50) NOT NULL,
+	description varchar(255),
+	access forum.chat_room_access NOT NULL DEFAULT 'public',
+	section_id int8,
+	created_by uuid,
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	archived_at timestamptz,
+	CONSTRAINT chat_room_pk PRIMARY KEY (id),
+	CONSTRAINT chat_room_name_unique UNIQUE (name),
+	CONSTRAINT chat_room_section_check CHECK ((access = 'section') = (section_id IS NOT NULL))
+);
+-- ddl-end --
+ALTER TABLE forum.chat_rooms OWNER TO postgres;
+-- ddl-end --
+
+-- The default room takes over the old global shoutbox, the backend expects it to have id 1
+INSERT INTO forum.chat_rooms (name, description) VALUES (E'general', E'Shoutbox for everyone');
+-- ddl-end --
+
+-- object: forum.chat_rooms_allowed | type: TABLE --
+-- DROP TABLE IF EXISTS forum.chat_rooms_allowed CASCADE;
+CREATE TABLE forum.chat_rooms_allowed (
+	room_id int4 NOT NULL,
+	role forum.user_role NOT NULL,
+	CONSTRAINT chat_rooms_allowed_pk PRIMARY KEY (room_id,role)
+);
+-- ddl-end --
+ALTER TABLE forum.chat_rooms_allowed OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.chat_room_members | type: TABLE --
+-- DROP TABLE IF EXISTS forum.chat_room_members CASCADE;
+CREATE TABLE forum.chat_room_members (
+	room_id int4 NOT NULL,
+	user_id uuid NOT NULL,
+	joined_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT chat_room_members_pk PRIMARY KEY (room_id,user_id)
+);
+-- ddl-end --
+ALTER TABLE forum.chat_room_members OWNER TO postgres;
+-- ddl-end --
+
 -- object: forum.chat_post_ids | type: SEQUENCE --
 -- DROP SEQUENCE IF EXISTS forum.chat_post_ids CASCADE;
 CREATE SEQUENCE forum.chat_post_ids
@@ -251,12 +507,24 @@ CREATE TABLE forum.chat_posts (
 	added timestamptz NOT NULL DEFAULT NOW(),
 	author uuid NOT NULL,
 	content varchar(255) NOT NULL,
+	room_id int4 NOT NULL DEFAULT 1,
+	hidden_at timestamptz,
 	CONSTRAINT chat_pk PRIMARY KEY (id)
 );
 -- ddl-end --
 ALTER TABLE forum.chat_posts OWNER TO postgres;
 -- ddl-end --
 
+-- object: chat_posts_room | type: INDEX --
+-- DROP INDEX IF EXISTS forum.chat_posts_room CASCADE;
+CREATE INDEX chat_posts_room ON forum.chat_posts
+USING btree
+(
+	room_id,
+	id
+);
+-- ddl-end --
+
 -- object: forum.user_warning | type: TABLE --
 -- DROP TABLE IF EXISTS forum.user_warning CASCADE;
 CREATE TABLE forum.user_warning (
@@ -295,6 +563,7 @@ CREATE TABLE forum.private_messages (
 	author uuid,
 	receiver uuid NOT NULL,
 	content varchar(255) NOT NULL,
+	hidden_at timestamptz,
 	CONSTRAINT pm_pk PRIMARY KEY (id),
 	CONSTRAINT pm_author_recv CHECK (author <> receiver)
 );
@@ -302,6 +571,547 @@ CREATE TABLE forum.private_messages (
 ALTER TABLE forum.private_messages OWNER TO postgres;
 -- ddl-end --
 
+-- object: forum.sessions | type: TABLE --
+-- DROP TABLE IF EXISTS forum.sessions CASCADE;
+CREATE TABLE forum.sessions (
+	id uuid NOT NULL DEFAULT uuid_generate_v4(),
+	user_id uuid NOT NULL,
+	refresh_token varchar(64) NOT NULL,
+	previous_token varchar(64),
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	last_used_at timestamptz NOT NULL DEFAULT NOW(),
+	expires_at timestamptz NOT NULL,
+	revoked_at timestamptz,
+	CONSTRAINT session_pk PRIMARY KEY (id),
+	CONSTRAINT session_token_unique UNIQUE (refresh_token)
+);
+-- ddl-end --
+ALTER TABLE forum.sessions OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.user_totp | type: TABLE --
+-- DROP TABLE IF EXISTS forum.user_totp CASCADE;
+CREATE TABLE forum.user_totp (
+	user_id uuid NOT NULL,
+	secret varchar(64) NOT NULL,
+	enabled boolean NOT NULL DEFAULT false,
+	last_used_step int8,
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	confirmed_at timestamptz,
+	CONSTRAINT user_totp_pk PRIMARY KEY (user_id)
+);
+-- ddl-end --
+ALTER TABLE forum.user_totp OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.recovery_code_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.recovery_code_seq CASCADE;
+CREATE SEQUENCE forum.recovery_code_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.recovery_code_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.recovery_codes | type: TABLE --
+-- DROP TABLE IF EXISTS forum.recovery_codes CASCADE;
+CREATE TABLE forum.recovery_codes (
+	id int8 NOT NULL DEFAULT nextval('forum.recovery_code_seq'::regclass),
+	user_id uuid NOT NULL,
+	code_hash varchar(100) NOT NULL,
+	used_at timestamptz,
+	CONSTRAINT recovery_code_pk PRIMARY KEY (id)
+);
+-- ddl-end --
+ALTER TABLE forum.recovery_codes OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.oidc_states | type: TABLE --
+-- DROP TABLE IF EXISTS forum.oidc_states CASCADE;
+CREATE TABLE forum.oidc_states (
+	state varchar(64) NOT NULL,
+	provider varchar(50) NOT NULL,
+	nonce varchar(64) NOT NULL,
+	code_verifier varchar(128) NOT NULL,
+	link_user uuid,
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	expires_at timestamptz NOT NULL,
+	CONSTRAINT oidc_state_pk PRIMARY KEY (state)
+);
+-- ddl-end --
+ALTER TABLE forum.oidc_states OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.identity_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.identity_seq CASCADE;
+CREATE SEQUENCE forum.identity_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.identity_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.user_identities | type: TABLE --
+-- DROP TABLE IF EXISTS forum.user_identities CASCADE;
+CREATE TABLE forum.user_identities (
+	id int8 NOT NULL DEFAULT nextval('forum.identity_seq'::regclass),
+	user_id uuid NOT NULL,
+	provider varchar(50) NOT NULL,
+	subject varchar(255) NOT NULL,
+	email varchar(100),
+	linked_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT identity_pk PRIMARY KEY (id),
+	CONSTRAINT identity_subject_unique UNIQUE (provider,subject),
+	CONSTRAINT identity_user_provider_unique UNIQUE (user_id,provider)
+);
+-- ddl-end --
+ALTER TABLE forum.user_identities OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.login_throttle | type: TABLE --
+-- DROP TABLE IF EXISTS forum.login_throttle CASCADE;
+CREATE TABLE forum.login_throttle (
+	key varchar(150) NOT NULL,
+	failures int4 NOT NULL DEFAULT 0,
+	last_failure timestamptz NOT NULL DEFAULT NOW(),
+	locked_until timestamptz,
+	CONSTRAINT login_throttle_pk PRIMARY KEY (key)
+);
+-- ddl-end --
+ALTER TABLE forum.login_throttle OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.profile_history_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.profile_history_seq CASCADE;
+CREATE SEQUENCE forum.profile_history_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.profile_history_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.profile_history | type: TABLE --
+-- DROP TABLE IF EXISTS forum.profile_history CASCADE;
+CREATE TABLE forum.profile_history (
+	id int8 NOT NULL DEFAULT nextval('forum.profile_history_seq'::regclass),
+	user_id uuid NOT NULL,
+	changed_by uuid,
+	field varchar(30) NOT NULL,
+	old_value varchar(255),
+	new_value varchar(255),
+	changed_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT profile_history_pk PRIMARY KEY (id)
+);
+-- ddl-end --
+ALTER TABLE forum.profile_history OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.email_changes | type: TABLE --
+-- DROP TABLE IF EXISTS forum.email_changes CASCADE;
+CREATE TABLE forum.email_changes (
+	id uuid NOT NULL DEFAULT uuid_generate_v4(),
+	user_id uuid NOT NULL,
+	old_email varchar(100) NOT NULL,
+	new_email varchar(100) NOT NULL,
+	confirm_token varchar(255) NOT NULL,
+	revert_token varchar(255) NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	expires_at timestamptz NOT NULL,
+	confirmed_at timestamptz,
+	revert_expires_at timestamptz NOT NULL,
+	reverted_at timestamptz,
+	CONSTRAINT email_changes_pk PRIMARY KEY (id),
+	CONSTRAINT email_change_confirm_unique UNIQUE (confirm_token),
+	CONSTRAINT email_change_revert_unique UNIQUE (revert_token)
+);
+-- ddl-end --
+ALTER TABLE forum.email_changes OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.account_deletions | type: TABLE --
+-- DROP TABLE IF EXISTS forum.account_deletions CASCADE;
+CREATE TABLE forum.account_deletions (
+	user_id uuid NOT NULL,
+	requested_at timestamptz NOT NULL DEFAULT NOW(),
+	delete_after timestamptz NOT NULL,
+	completed_at timestamptz,
+	CONSTRAINT account_deletions_pk PRIMARY KEY (user_id)
+);
+-- ddl-end --
+ALTER TABLE forum.account_deletions OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.post_reactions | type: TABLE --
+-- DROP TABLE IF EXISTS forum.post_reactions CASCADE;
+CREATE TABLE forum.post_reactions (
+	post_id int8 NOT NULL,
+	user_id uuid NOT NULL,
+	emoji varchar(32) NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT post_reactions_pk PRIMARY KEY (post_id,user_id,emoji)
+);
+-- ddl-end --
+ALTER TABLE forum.post_reactions OWNER TO postgres;
+-- ddl-end --
+
+-- object: post_reactions_user | type: INDEX --
+-- DROP INDEX IF EXISTS forum.post_reactions_user CASCADE;
+CREATE INDEX post_reactions_user ON forum.post_reactions
+USING btree
+(
+	user_id
+);
+-- ddl-end --
+
+-- object: forum.user_group_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.user_group_seq CASCADE;
+CREATE SEQUENCE forum.user_group_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.user_group_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.user_groups | type: TABLE --
+-- DROP TABLE IF EXISTS forum.user_groups CASCADE;
+CREATE TABLE forum.user_groups (
+	id int4 NOT NULL DEFAULT nextval('forum.user_group_seq'::regclass),
+	name varchar(50) NOT NULL,
+	description varchar(255),
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT user_group_pk PRIMARY KEY (id),
+	CONSTRAINT user_group_name_unique UNIQUE (name)
+);
+-- ddl-end --
+ALTER TABLE forum.user_groups OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.user_group_members | type: TABLE --
+-- DROP TABLE IF EXISTS forum.user_group_members CASCADE;
+CREATE TABLE forum.user_group_members (
+	group_id int4 NOT NULL,
+	user_id uuid NOT NULL,
+	added_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT user_group_member_pk PRIMARY KEY (group_id,user_id)
+);
+-- ddl-end --
+ALTER TABLE forum.user_group_members OWNER TO postgres;
+-- ddl-end --
+
+-- object: user_group_members_user | type: INDEX --
+-- DROP INDEX IF EXISTS forum.user_group_members_user CASCADE;
+CREATE INDEX user_group_members_user ON forum.user_group_members
+USING btree
+(
+	user_id
+);
+-- ddl-end --
+
+-- object: forum.sections_allowed_groups | type: TABLE --
+-- DROP TABLE IF EXISTS forum.sections_allowed_groups CASCADE;
+CREATE TABLE forum.sections_allowed_groups (
+	section_id int8 NOT NULL,
+	group_id int4 NOT NULL,
+	can_view boolean NOT NULL DEFAULT true,
+	can_create_thread boolean NOT NULL DEFAULT true,
+	can_reply boolean NOT NULL DEFAULT true,
+	can_moderate boolean NOT NULL DEFAULT false,
+	CONSTRAINT allowed_group_pk PRIMARY KEY (section_id,group_id)
+);
+-- ddl-end --
+ALTER TABLE forum.sections_allowed_groups OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.section_moderators | type: TABLE --
+-- DROP TABLE IF EXISTS forum.section_moderators CASCADE;
+CREATE TABLE forum.section_moderators (
+	section_id int8 NOT NULL,
+	user_id uuid NOT NULL,
+	added_by uuid,
+	added_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT section_moderator_pk PRIMARY KEY (section_id,user_id)
+);
+-- ddl-end --
+ALTER TABLE forum.section_moderators OWNER TO postgres;
+-- ddl-end --
+
+-- object: section_moderators_user | type: INDEX --
+-- DROP INDEX IF EXISTS forum.section_moderators_user CASCADE;
+CREATE INDEX section_moderators_user ON forum.section_moderators
+USING btree
+(
+	user_id
+);
+-- ddl-end --
+
+-- object: forum.section_access | type: VIEW --
+-- DROP VIEW IF EXISTS forum.section_access CASCADE;
+CREATE VIEW forum.section_access
+AS
+SELECT a.user_id, a.section_id,
+	bool_or(a.can_view) AS can_view,
+	bool_or(a.can_create_thread) AS can_create_thread,
+	bool_or(a.can_reply) AS can_reply,
+	bool_or(a.can_moderate) AS can_moderate
+FROM (
+	SELECT u.id AS user_id, sa.section_id, sa.can_view, sa.can_create_thread, sa.can_reply, sa.can_moderate
+	FROM forum.users u
+	INNER JOIN forum.sections_allowed sa ON sa.role = u.role
+	UNION ALL
+	SELECT m.user_id, g.section_id, g.can_view, g.can_create_thread, g.can_reply, g.can_moderate
+	FROM forum.user_group_members m
+	INNER JOIN forum.sections_allowed_groups g ON g.group_id = m.group_id
+	UNION ALL
+	SELECT sm.user_id, sm.section_id, true, true, true, true
+	FROM forum.section_moderators sm
+) a
+GROUP BY a.user_id, a.section_id;
+-- ddl-end --
+ALTER VIEW forum.section_access OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.revision_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.revision_seq CASCADE;
+CREATE SEQUENCE forum.revision_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.revision_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.revisions | type: TABLE --
+-- DROP TABLE IF EXISTS forum.revisions CASCADE;
+CREATE TABLE forum.revisions (
+	id int8 NOT NULL DEFAULT nextval('forum.revision_seq'::regclass),
+	post_id int8,
+	thread_id int8,
+	title varchar(255),
+	content text NOT NULL,
+	editor uuid,
+	edited_at timestamptz NOT NULL DEFAULT NOW(),
+	reason varchar(255),
+	CONSTRAINT revision_pk PRIMARY KEY (id),
+	CONSTRAINT revision_target CHECK ((post_id IS NULL) <> (thread_id IS NULL))
+);
+-- ddl-end --
+ALTER TABLE forum.revisions OWNER TO postgres;
+-- ddl-end --
+
+-- object: revisions_post | type: INDEX --
+-- DROP INDEX IF EXISTS forum.revisions_post CASCADE;
+CREATE INDEX revisions_post ON forum.revisions
+USING btree
+(
+	post_id
+)
+WHERE (post_id IS NOT NULL);
+-- ddl-end --
+
+-- object: revisions_thread | type: INDEX --
+-- DROP INDEX IF EXISTS forum.revisions_thread CASCADE;
+CREATE INDEX revisions_thread ON forum.revisions
+USING btree
+(
+	thread_id
+)
+WHERE (thread_id IS NOT NULL);
+-- ddl-end --
+
+-- object: forum.report_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.report_seq CASCADE;
+CREATE SEQUENCE forum.report_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.report_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.reports | type: TABLE --
+-- DROP TABLE IF EXISTS forum.reports CASCADE;
+CREATE TABLE forum.reports (
+	id int8 NOT NULL DEFAULT nextval('forum.report_seq'::regclass),
+	target_type forum.report_target NOT NULL,
+	target_id int8 NOT NULL,
+	target_author uuid,
+	content text NOT NULL,
+	status forum.report_status NOT NULL DEFAULT 'open',
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	claimed_by uuid,
+	claimed_at timestamptz,
+	resolved_by uuid,
+	resolved_at timestamptz,
+	resolution varchar(255),
+	hidden_at timestamptz,
+	CONSTRAINT report_pk PRIMARY KEY (id)
+);
+-- ddl-end --
+ALTER TABLE forum.reports OWNER TO postgres;
+-- ddl-end --
+
+-- object: reports_open_target | type: INDEX --
+-- DROP INDEX IF EXISTS forum.reports_open_target CASCADE;
+CREATE UNIQUE INDEX reports_open_target ON forum.reports
+USING btree
+(
+	target_type,
+	target_id
+)
+WHERE (status = 'open');
+-- ddl-end --
+
+-- object: reports_status | type: INDEX --
+-- DROP INDEX IF EXISTS forum.reports_status CASCADE;
+CREATE INDEX reports_status ON forum.reports
+USING btree
+(
+	status,
+	created_at
+);
+-- ddl-end --
+
+-- object: forum.report_entries | type: TABLE --
+-- DROP TABLE IF EXISTS forum.report_entries CASCADE;
+CREATE TABLE forum.report_entries (
+	report_id int8 NOT NULL,
+	reporter uuid NOT NULL,
+	reason forum.report_reason NOT NULL,
+	comment varchar(255),
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT report_entry_pk PRIMARY KEY (report_id,reporter)
+);
+-- ddl-end --
+ALTER TABLE forum.report_entries OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.audit_seq | type: SEQUENCE --
+-- DROP SEQUENCE IF EXISTS forum.audit_seq CASCADE;
+CREATE SEQUENCE forum.audit_seq
+	INCREMENT BY 1
+	MINVALUE 0
+	MAXVALUE 2147483647
+	START WITH 1
+	CACHE 1
+	NO CYCLE
+	OWNED BY NONE;
+
+-- ddl-end --
+ALTER SEQUENCE forum.audit_seq OWNER TO postgres;
+-- ddl-end --
+
+-- object: forum.audit_log | type: TABLE --
+-- DROP TABLE IF EXISTS forum.audit_log CASCADE;
+CREATE TABLE forum.audit_log (
+	id int8 NOT NULL DEFAULT nextval('forum.audit_seq'::regclass),
+	actor uuid NOT NULL,
+	action varchar(50) NOT NULL,
+	target_type varchar(20) NOT NULL,
+	target_id varchar(255) NOT NULL,
+	before jsonb,
+	after jsonb,
+	ip varchar(45),
+	created_at timestamptz NOT NULL DEFAULT NOW(),
+	CONSTRAINT audit_pk PRIMARY KEY (id)
+);
+-- ddl-end --
+ALTER TABLE forum.audit_log OWNER TO postgres;
+-- ddl-end --
+
+-- object: audit_log_actor | type: INDEX --
+-- DROP INDEX IF EXISTS forum.audit_log_actor CASCADE;
+CREATE INDEX audit_log_actor ON forum.audit_log
+USING btree
+(
+	actor,
+	created_at
+);
+-- ddl-end --
+
+-- object: audit_log_target | type: INDEX --
+-- DROP INDEX IF EXISTS forum.audit_log_target CASCADE;
+CREATE INDEX audit_log_target ON forum.audit_log
+USING btree
+(
+	target_type,
+	target_id,
+	created_at
+);
+-- ddl-end --
+
+-- object: audit_log_action | type: INDEX --
+-- DROP INDEX IF EXISTS forum.audit_log_action CASCADE;
+CREATE INDEX audit_log_action ON forum.audit_log
+USING btree
+(
+	action,
+	created_at
+);
+-- ddl-end --
+
+-- object: forum.audit_log_append_only | type: FUNCTION --
+-- DROP FUNCTION IF EXISTS forum.audit_log_append_only() CASCADE;
+CREATE OR REPLACE FUNCTION forum.audit_log_append_only ()
+	RETURNS trigger
+	LANGUAGE plpgsql
+	VOLATILE 
+	CALLED ON NULL INPUT
+	SECURITY INVOKER
+	PARALLEL UNSAFE
+	COST 1
+	AS $$
+BEGIN
+    -- Entries are only ever added, nothing may rewrite what a moderator did
+    RAISE EXCEPTION 'forum.audit_log is append-only';
+END;
+$$;
+-- ddl-end --
+ALTER FUNCTION forum.audit_log_append_only() OWNER TO postgres;
+-- ddl-end --
+
+-- object: tr_audit_log_append_only | type: TRIGGER --
+-- DROP TRIGGER IF EXISTS tr_audit_log_append_only ON forum.audit_log CASCADE;
+CREATE OR REPLACE TRIGGER tr_audit_log_append_only
+	BEFORE UPDATE OR DELETE 
+	ON forum.audit_log
+	FOR EACH ROW
+	EXECUTE PROCEDURE forum.audit_log_append_only();
+-- ddl-end --
+
 -- object: forum.delete_related_threads | type: FUNCTION --
 -- DROP FUNCTION IF EXISTS forum.delete_related_threads() CASCADE;
 CREATE OR REPLACE FUNCTION forum.delete_related_threads ()
@@ -313,20 +1123,17 @@ CREATE OR REPLACE FUNCTION forum.delete_related_threads ()
 	PARALLEL UNSAFE
 	COST 1
 	AS $$
-CREATE OR REPLACE FUNCTION delete_related_threads()
-RETURNS TRIGGER AS $$
 BEGIN
     -- Delete rows from the "thread" table where section_id matches the deleted row
     DELETE FROM forum.threads
-    WHERE section_id = OLD.section_id;
+    WHERE section_id = OLD.id;
     -- Delete permissions for deleted section
     DELETE FROM forum.sections_allowed
-    WHERE section = OLD.section_id;
+    WHERE section_id = OLD.id;
 
     -- Return the deleted row (required for BEFORE DELETE triggers)
     RETURN OLD;
 END;
-$$ LANGUAGE plpgsql;
 $$;
 -- ddl-end --
 ALTER FUNCTION forum.delete_related_threads() OWNER TO postgres;
@@ -352,17 +1159,14 @@ CREATE OR REPLACE FUNCTION forum.delete_related_posts ()
 	PARALLEL UNSAFE
 	COST 1
 	AS $$
-CREATE OR REPLACE FUNCTION delete_related_posts()
-RETURNS TRIGGER AS $$
 BEGIN
-    -- Delete rows from the "thread" table where section_id matches the deleted row
+    -- Delete rows from the "post" table belonging to the deleted thread
     DELETE FROM forum.posts
-    WHERE topic = OLD.topic;
+    WHERE topic = OLD.id;
 
     -- Return the deleted row (required for BEFORE DELETE triggers)
     RETURN OLD;
 END;
-$$ LANGUAGE plpgsql;
 $$;
 -- ddl-end --
 ALTER FUNCTION forum.delete_related_posts() OWNER TO postgres;
@@ -373,13 +1177,13 @@ ALTER FUNCTION forum.delete_related_posts() OWNER TO postgres;
 CREATE OR REPLACE TRIGGER tr_delete_threads_posts
 	BEFORE DELETE 
 	ON forum.threads
-	FOR EACH STATEMENT
+	FOR EACH ROW
 	EXECUTE PROCEDURE forum.delete_related_posts();
 -- ddl-end --
 
 -- object: topics_sections | type: CONSTRAINT --
 -- ALTER TABLE forum.threads DROP CONSTRAINT IF EXISTS topics_sections CASCADE;
-ALTER TABLE forum.threads ADD CONSTRAINT topics_sections FOREIGN KEY (section)
+ALTER TABLE forum.threads ADD CONSTRAINT topics_sections FOREIGN KEY (section_id)
 REFERENCES forum.sections (id) MATCH SIMPLE
 ON DELETE NO ACTION ON UPDATE NO ACTION;
 -- ddl-end --
@@ -414,7 +1218,7 @@ ON DELETE NO ACTION ON UPDATE NO ACTION;
 
 -- object: allowed_in_sec | type: CONSTRAINT --
 -- ALTER TABLE forum.sections_allowed DROP CONSTRAINT IF EXISTS allowed_in_sec CASCADE;
-ALTER TABLE forum.sections_allowed ADD CONSTRAINT allowed_in_sec FOREIGN KEY (section)
+ALTER TABLE forum.sections_allowed ADD CONSTRAINT allowed_in_sec FOREIGN KEY (section_id)
 REFERENCES forum.sections (id) MATCH SIMPLE
 ON DELETE NO ACTION ON UPDATE NO ACTION;
 -- ddl-end --
@@ -423,7 +1227,7 @@ ON DELETE NO ACTION ON UPDATE NO ACTION;
 -- ALTER TABLE forum.hashtags DROP CONSTRAINT IF EXISTS tag_topic CASCADE;
 ALTER TABLE forum.hashtags ADD CONSTRAINT tag_topic FOREIGN KEY (topic)
 REFERENCES forum.threads (id) MATCH SIMPLE
-ON DELETE NO ACTION ON UPDATE NO ACTION;
+ON DELETE CASCADE ON UPDATE NO ACTION;
 -- ddl-end --
 
 -- object: author_uuid | type: CONSTRAINT --
@@ -461,4 +1265,263 @@ REFERENCES forum.users (id) MATCH SIMPLE
 ON DELETE NO ACTION ON UPDATE NO ACTION;
 -- ddl-end --
 
+-- object: session_owner | type: CONSTRAINT --
+-- ALTER TABLE forum.sessions DROP CONSTRAINT IF EXISTS session_owner CASCADE;
+ALTER TABLE forum.sessions ADD CONSTRAINT session_owner FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: totp_user | type: CONSTRAINT --
+-- ALTER TABLE forum.user_totp DROP CONSTRAINT IF EXISTS totp_user CASCADE;
+ALTER TABLE forum.user_totp ADD CONSTRAINT totp_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: recovery_code_user | type: CONSTRAINT --
+-- ALTER TABLE forum.recovery_codes DROP CONSTRAINT IF EXISTS recovery_code_user CASCADE;
+ALTER TABLE forum.recovery_codes ADD CONSTRAINT recovery_code_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: oidc_state_user | type: CONSTRAINT --
+-- ALTER TABLE forum.oidc_states DROP CONSTRAINT IF EXISTS oidc_state_user CASCADE;
+ALTER TABLE forum.oidc_states ADD CONSTRAINT oidc_state_user FOREIGN KEY (link_user)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: identity_user | type: CONSTRAINT --
+-- ALTER TABLE forum.user_identities DROP CONSTRAINT IF EXISTS identity_user CASCADE;
+ALTER TABLE forum.user_identities ADD CONSTRAINT identity_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: profile_history_user | type: CONSTRAINT --
+-- ALTER TABLE forum.profile_history DROP CONSTRAINT IF EXISTS profile_history_user CASCADE;
+ALTER TABLE forum.profile_history ADD CONSTRAINT profile_history_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: profile_history_changed_by | type: CONSTRAINT --
+-- ALTER TABLE forum.profile_history DROP CONSTRAINT IF EXISTS profile_history_changed_by CASCADE;
+ALTER TABLE forum.profile_history ADD CONSTRAINT profile_history_changed_by FOREIGN KEY (changed_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: email_change_user | type: CONSTRAINT --
+-- ALTER TABLE forum.email_changes DROP CONSTRAINT IF EXISTS email_change_user CASCADE;
+ALTER TABLE forum.email_changes ADD CONSTRAINT email_change_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: account_deletion_user | type: CONSTRAINT --
+-- ALTER TABLE forum.account_deletions DROP CONSTRAINT IF EXISTS account_deletion_user CASCADE;
+ALTER TABLE forum.account_deletions ADD CONSTRAINT account_deletion_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: chat_room_section | type: CONSTRAINT --
+-- ALTER TABLE forum.chat_rooms DROP CONSTRAINT IF EXISTS chat_room_section CASCADE;
+ALTER TABLE forum.chat_rooms ADD CONSTRAINT chat_room_section FOREIGN KEY (section_id)
+REFERENCES forum.sections (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: chat_room_creator | type: CONSTRAINT --
+-- ALTER TABLE forum.chat_rooms DROP CONSTRAINT IF EXISTS chat_room_creator CASCADE;
+ALTER TABLE forum.chat_rooms ADD CONSTRAINT chat_room_creator FOREIGN KEY (created_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: chat_room_allowed_room | type: CONSTRAINT --
+-- ALTER TABLE forum.chat_rooms_allowed DROP CONSTRAINT IF EXISTS chat_room_allowed_room CASCADE;
+ALTER TABLE forum.chat_rooms_allowed ADD CONSTRAINT chat_room_allowed_room FOREIGN KEY (room_id)
+REFERENCES forum.chat_rooms (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: chat_member_room | type: CONSTRAINT --
+-- ALTER TABLE forum.chat_room_members DROP CONSTRAINT IF EXISTS chat_member_room CASCADE;
+ALTER TABLE forum.chat_room_members ADD CONSTRAINT chat_member_room FOREIGN KEY (room_id)
+REFERENCES forum.chat_rooms (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: chat_member_user | type: CONSTRAINT --
+-- ALTER TABLE forum.chat_room_members DROP CONSTRAINT IF EXISTS chat_member_user CASCADE;
+ALTER TABLE forum.chat_room_members ADD CONSTRAINT chat_member_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: chat_post_room | type: CONSTRAINT --
+-- ALTER TABLE forum.chat_posts DROP CONSTRAINT IF EXISTS chat_post_room CASCADE;
+ALTER TABLE forum.chat_posts ADD CONSTRAINT chat_post_room FOREIGN KEY (room_id)
+REFERENCES forum.chat_rooms (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: reaction_post | type: CONSTRAINT --
+-- ALTER TABLE forum.post_reactions DROP CONSTRAINT IF EXISTS reaction_post CASCADE;
+ALTER TABLE forum.post_reactions ADD CONSTRAINT reaction_post FOREIGN KEY (post_id)
+REFERENCES forum.posts (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: reaction_user | type: CONSTRAINT --
+-- ALTER TABLE forum.post_reactions DROP CONSTRAINT IF EXISTS reaction_user CASCADE;
+ALTER TABLE forum.post_reactions ADD CONSTRAINT reaction_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: section_parent | type: CONSTRAINT --
+-- ALTER TABLE forum.sections DROP CONSTRAINT IF EXISTS section_parent CASCADE;
+ALTER TABLE forum.sections ADD CONSTRAINT section_parent FOREIGN KEY (parent_id)
+REFERENCES forum.sections (id) MATCH SIMPLE
+ON DELETE NO ACTION ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: user_group_member_group | type: CONSTRAINT --
+-- ALTER TABLE forum.user_group_members DROP CONSTRAINT IF EXISTS user_group_member_group CASCADE;
+ALTER TABLE forum.user_group_members ADD CONSTRAINT user_group_member_group FOREIGN KEY (group_id)
+REFERENCES forum.user_groups (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: user_group_member_user | type: CONSTRAINT --
+-- ALTER TABLE forum.user_group_members DROP CONSTRAINT IF EXISTS user_group_member_user CASCADE;
+ALTER TABLE forum.user_group_members ADD CONSTRAINT user_group_member_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: allowed_group_section | type: CONSTRAINT --
+-- ALTER TABLE forum.sections_allowed_groups DROP CONSTRAINT IF EXISTS allowed_group_section CASCADE;
+ALTER TABLE forum.sections_allowed_groups ADD CONSTRAINT allowed_group_section FOREIGN KEY (section_id)
+REFERENCES forum.sections (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: allowed_group_group | type: CONSTRAINT --
+-- ALTER TABLE forum.sections_allowed_groups DROP CONSTRAINT IF EXISTS allowed_group_group CASCADE;
+ALTER TABLE forum.sections_allowed_groups ADD CONSTRAINT allowed_group_group FOREIGN KEY (group_id)
+REFERENCES forum.user_groups (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: section_moderator_section | type: CONSTRAINT --
+-- ALTER TABLE forum.section_moderators DROP CONSTRAINT IF EXISTS section_moderator_section CASCADE;
+ALTER TABLE forum.section_moderators ADD CONSTRAINT section_moderator_section FOREIGN KEY (section_id)
+REFERENCES forum.sections (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: section_moderator_user | type: CONSTRAINT --
+-- ALTER TABLE forum.section_moderators DROP CONSTRAINT IF EXISTS section_moderator_user CASCADE;
+ALTER TABLE forum.section_moderators ADD CONSTRAINT section_moderator_user FOREIGN KEY (user_id)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: section_moderator_added_by | type: CONSTRAINT --
+-- ALTER TABLE forum.section_moderators DROP CONSTRAINT IF EXISTS section_moderator_added_by CASCADE;
+ALTER TABLE forum.section_moderators ADD CONSTRAINT section_moderator_added_by FOREIGN KEY (added_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: thread_redirect | type: CONSTRAINT --
+-- ALTER TABLE forum.threads DROP CONSTRAINT IF EXISTS thread_redirect CASCADE;
+ALTER TABLE forum.threads ADD CONSTRAINT thread_redirect FOREIGN KEY (redirect_to)
+REFERENCES forum.threads (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: revision_post | type: CONSTRAINT --
+-- ALTER TABLE forum.revisions DROP CONSTRAINT IF EXISTS revision_post CASCADE;
+ALTER TABLE forum.revisions ADD CONSTRAINT revision_post FOREIGN KEY (post_id)
+REFERENCES forum.posts (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: revision_thread | type: CONSTRAINT --
+-- ALTER TABLE forum.revisions DROP CONSTRAINT IF EXISTS revision_thread CASCADE;
+ALTER TABLE forum.revisions ADD CONSTRAINT revision_thread FOREIGN KEY (thread_id)
+REFERENCES forum.threads (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: revision_editor | type: CONSTRAINT --
+-- ALTER TABLE forum.revisions DROP CONSTRAINT IF EXISTS revision_editor CASCADE;
+ALTER TABLE forum.revisions ADD CONSTRAINT revision_editor FOREIGN KEY (editor)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: thread_deleted_by | type: CONSTRAINT --
+-- ALTER TABLE forum.threads DROP CONSTRAINT IF EXISTS thread_deleted_by CASCADE;
+ALTER TABLE forum.threads ADD CONSTRAINT thread_deleted_by FOREIGN KEY (deleted_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: post_deleted_by | type: CONSTRAINT --
+-- ALTER TABLE forum.posts DROP CONSTRAINT IF EXISTS post_deleted_by CASCADE;
+ALTER TABLE forum.posts ADD CONSTRAINT post_deleted_by FOREIGN KEY (deleted_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: report_target_author | type: CONSTRAINT --
+-- ALTER TABLE forum.reports DROP CONSTRAINT IF EXISTS report_target_author CASCADE;
+ALTER TABLE forum.reports ADD CONSTRAINT report_target_author FOREIGN KEY (target_author)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: report_claimed_by | type: CONSTRAINT --
+-- ALTER TABLE forum.reports DROP CONSTRAINT IF EXISTS report_claimed_by CASCADE;
+ALTER TABLE forum.reports ADD CONSTRAINT report_claimed_by FOREIGN KEY (claimed_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: report_resolved_by | type: CONSTRAINT --
+-- ALTER TABLE forum.reports DROP CONSTRAINT IF EXISTS report_resolved_by CASCADE;
+ALTER TABLE forum.reports ADD CONSTRAINT report_resolved_by FOREIGN KEY (resolved_by)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE SET NULL ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: report_entry_report | type: CONSTRAINT --
+-- ALTER TABLE forum.report_entries DROP CONSTRAINT IF EXISTS report_entry_report CASCADE;
+ALTER TABLE forum.report_entries ADD CONSTRAINT report_entry_report FOREIGN KEY (report_id)
+REFERENCES forum.reports (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: report_entry_reporter | type: CONSTRAINT --
+-- ALTER TABLE forum.report_entries DROP CONSTRAINT IF EXISTS report_entry_reporter CASCADE;
+ALTER TABLE forum.report_entries ADD CONSTRAINT report_entry_reporter FOREIGN KEY (reporter)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE CASCADE ON UPDATE NO ACTION;
+-- ddl-end --
+
+-- object: audit_actor | type: CONSTRAINT --
+-- ALTER TABLE forum.audit_log DROP CONSTRAINT IF EXISTS audit_actor CASCADE;
+ALTER TABLE forum.audit_log ADD CONSTRAINT audit_actor FOREIGN KEY (actor)
+REFERENCES forum.users (id) MATCH SIMPLE
+ON DELETE NO ACTION ON UPDATE NO ACTION;
+-- ddl-end --
+
 
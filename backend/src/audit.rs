@@ -0,0 +1,125 @@
+use std::{cell::Cell, net::SocketAddr, sync::Arc};
+
+use axum::{extract::{ConnectInfo, OriginalUri, Request}, http::Method, middleware::Next, response::Response, Extension};
+use serde::Serialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{db::audit::AuditExt,
+    middleware::JWTAuthMiddeware,
+    models::{AuditAction, AuditTarget},
+    AppState};
+
+/// A moderator action as its handler describes it. Handlers return it as a response extension
+/// and `audited` writes it along with who sent the request and from where.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub action: AuditAction,
+    pub target_type: AuditTarget,
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl AuditEntry {
+    pub fn new(action: AuditAction, target_type: AuditTarget, target_id: impl ToString) -> Self {
+        AuditEntry {
+            action,
+            target_type,
+            target_id: target_id.to_string(),
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn before(mut self, state: impl Serialize) -> Self {
+        self.before = serde_json::to_value(state).ok();
+        self
+    }
+
+    pub fn after(mut self, state: impl Serialize) -> Self {
+        self.after = serde_json::to_value(state).ok();
+        self
+    }
+}
+
+tokio::task_local! {
+    /// Whether the request `audited` is running was let through on moderator or admin rights.
+    static PRIVILEGED: Cell<bool>;
+}
+
+/// Called by `middleware::role_check` and by `policy` once a moderator right is granted. Outside of
+/// `audited`, like for chat socket commands, there is nothing to mark.
+pub fn mark_privileged() {
+    let _ = PRIVILEGED.try_with(|privileged| privileged.set(true));
+}
+
+/// Writes the `AuditEntry` a successful response carries. A request let through on Mod, Admin or section
+/// moderator rights that changed something without describing it is still logged as `AuditAction::Other`
+/// with its method and path.
+pub async fn audited(
+    Extension(app_state): Extension<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let actor = req.extensions().get::<JWTAuthMiddeware>().map(|auth| auth.user.id);
+    let ip = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip().to_string());
+    let path = req.extensions().get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let method = req.method().clone();
+
+    let (privileged, mut response) = PRIVILEGED.scope(Cell::new(false), async {
+        let response = next.run(req).await;
+        (PRIVILEGED.with(Cell::get), response)
+    }).await;
+
+    let Some(actor) = actor else {
+        return response;
+    };
+
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let entry = match response.extensions_mut().remove::<AuditEntry>() {
+        Some(entry) => entry,
+        None if method != Method::GET && privileged =>
+            AuditEntry::new(AuditAction::Other, AuditTarget::Route, path).after(json!({ "method": method.as_str() })),
+        None => return response,
+    };
+
+    record(&app_state, actor, ip.as_deref(), &entry).await;
+
+    response
+}
+
+/// The write behind `audited`, for moderator actions that don't arrive as a request of their own like chat socket commands.
+pub async fn record(app_state: &AppState, actor: Uuid, ip: Option<&str>, entry: &AuditEntry) {
+    // The action already happened, failing the response now would only hide that it did
+    if let Err(e) = app_state.db_client.add_audit_entry(actor, ip, entry).await {
+        eprintln!("Failed to write audit entry {:?} for {}: {}", entry.action, actor, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn marks_only_the_request_being_run() {
+        // Outside of `audited` there is no request to mark
+        mark_privileged();
+
+        let run = |mark: bool| PRIVILEGED.scope(Cell::new(false), async move {
+            tokio::task::yield_now().await;
+            if mark {
+                mark_privileged();
+            }
+            PRIVILEGED.with(Cell::get)
+        });
+
+        assert!(run(true).await);
+        assert!(!run(false).await);
+    }
+}
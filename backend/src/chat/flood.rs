@@ -0,0 +1,124 @@
+use std::{collections::{HashMap, VecDeque}, sync::Mutex, time::{Duration, Instant}};
+
+use uuid::Uuid;
+
+/// Messages a user may send within `FLOOD_WINDOW`, shared by all of their connections.
+pub const FLOOD_BURST: usize = 5;
+pub const FLOOD_WINDOW: Duration = Duration::from_secs(10);
+/// Below this many tracked users idle entries are left alone.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Sliding window rate limit per user. It only sees this instance,
+/// with several instances a user gets the burst on each of them.
+#[derive(Debug, Default)]
+pub struct FloodGuard {
+    sent: Mutex<HashMap<Uuid, VecDeque<Instant>>>,
+}
+
+impl FloodGuard {
+    /// Records a message and returns `None`, or how long to wait when the user is over the limit.
+    pub fn check(&self, user_id: Uuid) -> Option<Duration> {
+        self.check_at(user_id, Instant::now())
+    }
+
+    fn check_at(&self, user_id: Uuid, now: Instant) -> Option<Duration> {
+        let mut sent = self.sent.lock().unwrap();
+
+        if sent.len() > PRUNE_THRESHOLD {
+            sent.retain(|_, times| times.back().is_some_and(|t| now.duration_since(*t) < FLOOD_WINDOW));
+        }
+
+        let times = sent.entry(user_id).or_default();
+        while times.front().is_some_and(|t| now.duration_since(*t) >= FLOOD_WINDOW) {
+            times.pop_front();
+        }
+
+        if times.len() >= FLOOD_BURST {
+            let oldest = *times.front().unwrap();
+            return Some(FLOOD_WINDOW - now.duration_since(oldest));
+        }
+
+        times.push_back(now);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn allows_a_burst_then_asks_to_wait() {
+        let guard = FloodGuard::default();
+        let user = Uuid::new_v4();
+        let start = Instant::now();
+
+        for i in 0..FLOOD_BURST {
+            assert_eq!(guard.check_at(user, start + SECOND * i as u32), None, "message {}", i);
+        }
+
+        let now = start + SECOND * FLOOD_BURST as u32;
+        assert_eq!(guard.check_at(user, now), Some(FLOOD_WINDOW - SECOND * FLOOD_BURST as u32));
+
+        // A refused message is not recorded, so it doesn't push the wait further out
+        assert_eq!(guard.check_at(user, now), Some(FLOOD_WINDOW - SECOND * FLOOD_BURST as u32));
+    }
+
+    #[test]
+    fn window_slides_past_the_oldest_message() {
+        let guard = FloodGuard::default();
+        let user = Uuid::new_v4();
+        let start = Instant::now();
+
+        for _ in 0..FLOOD_BURST {
+            assert_eq!(guard.check_at(user, start), None);
+        }
+
+        let almost = start + FLOOD_WINDOW - Duration::from_millis(1);
+        assert_eq!(guard.check_at(user, almost), Some(Duration::from_millis(1)));
+
+        // The whole burst leaves the window at once, then the next one is counted from scratch
+        let later = start + FLOOD_WINDOW;
+        for _ in 0..FLOOD_BURST {
+            assert_eq!(guard.check_at(user, later), None);
+        }
+        assert!(guard.check_at(user, later).is_some());
+    }
+
+    #[test]
+    fn users_are_limited_separately() {
+        let guard = FloodGuard::default();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Instant::now();
+
+        for _ in 0..FLOOD_BURST {
+            guard.check_at(first, now);
+        }
+
+        assert!(guard.check_at(first, now).is_some());
+        assert_eq!(guard.check_at(second, now), None);
+    }
+
+    #[test]
+    fn prunes_idle_users_past_the_threshold() {
+        let guard = FloodGuard::default();
+        let start = Instant::now();
+        let active = Uuid::new_v4();
+
+        for _ in 0..PRUNE_THRESHOLD {
+            guard.check_at(Uuid::new_v4(), start);
+        }
+        guard.check_at(active, start + FLOOD_WINDOW - SECOND);
+        assert_eq!(guard.sent.lock().unwrap().len(), PRUNE_THRESHOLD + 1);
+
+        // Nothing was pruned so far, now that there are more users than the threshold the idle ones go
+        let now = start + FLOOD_WINDOW;
+        guard.check_at(Uuid::new_v4(), now);
+
+        let sent = guard.sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.contains_key(&active));
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+
+use super::{BusError, ChatBus, ChatEvent};
+
+/// Events buffered per subscriber before a slow client starts missing them.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// In process bus, enough as long as the API runs as a single instance.
+#[derive(Debug, Clone)]
+pub struct LocalBus {
+    sender: broadcast::Sender<ChatEvent>,
+}
+
+impl Default for LocalBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        LocalBus { sender }
+    }
+}
+
+#[async_trait]
+impl ChatBus for LocalBus {
+    async fn publish(&self, event: ChatEvent) -> Result<(), BusError> {
+        // Sending only fails when nobody is connected, which is fine
+        let _ = self.sender.send(event);
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ChatEvent> {
+        self.sender.subscribe()
+    }
+}
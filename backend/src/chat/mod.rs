@@ -0,0 +1,68 @@
+use std::fmt;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::models::ChatPost;
+
+pub mod flood;
+pub mod local;
+
+/// Change to a chat room that every client connected to it has to see.
+/// Serializable so a bus can carry it between instances, e.g. as a NOTIFY payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    Message { post: ChatPost },
+    Deleted { room_id: i32, id: i32 },
+    RoomArchived { room_id: i32, archived: bool },
+    RoomDeleted { room_id: i32 },
+}
+
+impl ChatEvent {
+    pub fn room_id(&self) -> i32 {
+        match self {
+            ChatEvent::Message { post } => post.room_id,
+            ChatEvent::Deleted { room_id, .. }
+            | ChatEvent::RoomArchived { room_id, .. }
+            | ChatEvent::RoomDeleted { room_id } => *room_id,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BusError(pub String);
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chat bus error: {}", self.0)
+    }
+}
+
+impl std::error::Error for BusError {}
+
+/// Fan-out of chat events to the subscribers of this instance.
+/// `publish` must eventually reach the subscribers of every instance, `LocalBus` covers a single
+/// process. A Postgres backed bus would NOTIFY in `publish` and have a LISTEN task feed its own
+/// local channel, the handlers only ever see this trait.
+#[async_trait]
+pub trait ChatBus: fmt::Debug + Send + Sync {
+    async fn publish(&self, event: ChatEvent) -> Result<(), BusError>;
+    fn subscribe(&self) -> broadcast::Receiver<ChatEvent>;
+}
+
+#[derive(Debug)]
+pub struct ChatHub {
+    pub bus: Box<dyn ChatBus>,
+    pub flood: flood::FloodGuard,
+}
+
+impl ChatHub {
+    pub fn new(bus: Box<dyn ChatBus>) -> Self {
+        ChatHub {
+            bus,
+            flood: flood::FloodGuard::default(),
+        }
+    }
+}
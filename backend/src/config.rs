@@ -1,13 +1,74 @@
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    #[serde(default = "OidcProvider::default_scopes")]
+    pub scopes: Vec<String>,
+    /// Link to an existing account with the same address when the provider says the email is verified
+    #[serde(default)]
+    pub trust_email: bool,
+}
+
+impl OidcProvider {
+    fn default_scopes() -> Vec<String> {
+        vec!["openid".to_string(), "email".to_string(), "profile".to_string()]
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OidcProviders {
+    #[serde(default)]
+    providers: Vec<OidcProvider>,
+}
+
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    Local {
+        path: String,
+    },
+    S3 {
+        bucket: String,
+        region: String,
+        /// Set for S3 compatible servers, left out for AWS itself
+        endpoint: Option<String>,
+        access_key: String,
+        secret_key: String,
+        path_style: bool,
+    },
+}
+
+const DEFAULT_REACTIONS: &str = "👍,❤️,😂,😮,😢,🎉";
+const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+const DEFAULT_REPORT_HIDE_THRESHOLD: u32 = 5;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
     pub jwt_maxage: i64,
+    pub refresh_maxage: i64,
     pub port_http: u16,
     pub port_https: u16,
     pub enable_https: bool,
     pub email_verification: bool,
+    pub require_2fa_privileged: bool,
+    pub totp_issuer: String,
     pub host_url: String,
+    pub api_url: String,
+    pub oidc_providers: Vec<OidcProvider>,
+    pub storage: StorageConfig,
+    /// Emoji users can react to posts with
+    pub reactions: Vec<String>,
+    /// Render BBCode tags in posts besides Markdown
+    pub bbcode: bool,
+    /// Days deleted threads and posts stay restorable
+    pub trash_retention_days: u32,
+    /// Reports from different users after which the target is hidden, 0 never hides
+    pub report_hide_threshold: u32,
 }
 
 impl Config {
@@ -16,20 +77,88 @@ impl Config {
         let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
         let jwt_secret = std::env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set");
         let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
+        let refresh_maxage = std::env::var("REFRESH_TOKEN_MAXAGE").expect("REFRESH_TOKEN_MAXAGE must be set");
         let enable_https =std::env::var("ENABLE_HTTPS").expect("ENABLE_HTTPS must be set");
         let email_verification =std::env::var("VERIFY_EMAIL").expect("VERIFY_EMAIL must be set");
         let host_url =std::env::var("HOST_URL").expect("HOST_URL must be set");
+        let require_2fa_privileged = std::env::var("REQUIRE_2FA_PRIVILEGED").expect("REQUIRE_2FA_PRIVILEGED must be set");
+        let totp_issuer = std::env::var("TOTP_ISSUER").expect("TOTP_ISSUER must be set");
+        let api_url = std::env::var("API_URL").expect("API_URL must be set");
+        let oidc_providers = std::env::var("OIDC_PROVIDERS_FILE")
+            .map(|path| Self::load_oidc_providers(&path))
+            .unwrap_or_default();
+        let storage = Self::load_storage();
+        let reactions = Self::load_reactions();
+        let bbcode = std::env::var("ENABLE_BBCODE").map(|v| v.parse::<bool>().unwrap_or(false)).unwrap_or(false);
+        let report_hide_threshold = std::env::var("REPORT_HIDE_THRESHOLD").map(|v| v.parse::<u32>().expect("REPORT_HIDE_THRESHOLD must be a number of reports")).unwrap_or(DEFAULT_REPORT_HIDE_THRESHOLD);
+        let trash_retention_days = std::env::var("TRASH_RETENTION_DAYS").map(|v| v.parse::<u32>().expect("TRASH_RETENTION_DAYS must be a number of days")).unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
 
         Config {
             database_url,
             jwt_secret,
             jwt_maxage: jwt_maxage.parse::<i64>().unwrap(),
+            refresh_maxage: refresh_maxage.parse::<i64>().unwrap(),
             port_https: 8080,
             port_http: 8000,
             enable_https: enable_https.parse::<bool>().unwrap_or(false),
             email_verification: email_verification.parse::<bool>().unwrap(),
+            require_2fa_privileged: require_2fa_privileged.parse::<bool>().unwrap_or(false),
+            totp_issuer,
             host_url,
+            api_url,
+            oidc_providers,
+            storage,
+            reactions,
+            bbcode,
+            trash_retention_days,
+            report_hide_threshold,
         }
     }
+
+    fn load_reactions() -> Vec<String> {
+        let list = std::env::var("REACTIONS").unwrap_or_else(|_| DEFAULT_REACTIONS.to_string());
+        let mut reactions: Vec<String> = Vec::new();
+
+        for emoji in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            // forum.post_reactions.emoji is a varchar(32)
+            assert!(emoji.chars().count() <= 32, "REACTIONS entries must be at most 32 characters, got {}", emoji);
+            if !reactions.iter().any(|r| r == emoji) {
+                reactions.push(emoji.to_string());
+            }
+        }
+
+        assert!(!reactions.is_empty(), "REACTIONS must contain at least one emoji");
+        reactions
+    }
+
+    fn load_storage() -> StorageConfig {
+        let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "local".to_string());
+
+        match backend.as_str() {
+            "local" => StorageConfig::Local {
+                path: std::env::var("STORAGE_PATH").unwrap_or_else(|_| "uploads".to_string()),
+            },
+            "s3" => StorageConfig::S3 {
+                bucket: std::env::var("S3_BUCKET").expect("S3_BUCKET must be set"),
+                region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                endpoint: std::env::var("S3_ENDPOINT").ok(),
+                access_key: std::env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set"),
+                secret_key: std::env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set"),
+                path_style: std::env::var("S3_PATH_STYLE").map(|v| v.parse::<bool>().unwrap_or(false)).unwrap_or(false),
+            },
+            other => panic!("STORAGE_BACKEND must be local or s3, got {}", other),
+        }
+    }
+
+    fn load_oidc_providers(path: &str) -> Vec<OidcProvider> {
+        let content = std::fs::read_to_string(path).expect("OIDC_PROVIDERS_FILE must be readable");
+        toml::from_str::<OidcProviders>(&content)
+            .expect("OIDC_PROVIDERS_FILE must be valid")
+            .providers
+    }
+
+    pub fn oidc_provider(&self, name: &str) -> Option<&OidcProvider> {
+        self.oidc_providers.iter().find(|p| p.name == name)
+    }
     
 }
@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::models::{AccountDeletion, PrivateMessage};
+
+#[async_trait]
+pub trait AccountExt {
+    async fn request_deletion(&self, user_id: Uuid, delete_after: DateTime<Utc>) -> Result<AccountDeletion, sqlx::Error>;
+    async fn cancel_deletion(&self, user_id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn get_deletion(&self, user_id: Uuid) -> Result<Option<AccountDeletion>, sqlx::Error>;
+    async fn get_due_deletions(&self) -> Result<Vec<Uuid>, sqlx::Error>;
+    async fn anonymize_user(&self, user_id: Uuid, password: &str) -> Result<Option<String>, sqlx::Error>;
+    async fn get_all_pms(&self, user_id: Uuid) -> Result<Vec<PrivateMessage>, sqlx::Error>;
+}
+
+#[async_trait]
+impl AccountExt for crate::db::DBClient {
+    async fn request_deletion(&self, user_id: Uuid, delete_after: DateTime<Utc>) -> Result<AccountDeletion, sqlx::Error> {
+        // Asking again while a deletion is pending keeps the original date
+        sqlx::query_as!(AccountDeletion,
+            r#" INSERT INTO forum.account_deletions(user_id, requested_at, delete_after)
+                VALUES ($1, LOCALTIMESTAMP, $2)
+                ON CONFLICT (user_id) DO UPDATE
+                SET delete_after = forum.account_deletions.delete_after
+                RETURNING user_id, requested_at, delete_after, completed_at"#,
+            user_id, delete_after)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn cancel_deletion(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#" DELETE FROM forum.account_deletions
+                WHERE user_id = $1 AND completed_at IS NULL"#, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_deletion(&self, user_id: Uuid) -> Result<Option<AccountDeletion>, sqlx::Error> {
+        sqlx::query_as!(AccountDeletion,
+            r#" SELECT user_id, requested_at, delete_after, completed_at
+                FROM forum.account_deletions WHERE user_id = $1"#, user_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_due_deletions(&self) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#" SELECT user_id FROM forum.account_deletions
+                WHERE completed_at IS NULL AND delete_after <= LOCALTIMESTAMP"#)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn anonymize_user(&self, user_id: Uuid, password: &str) -> Result<Option<String>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let avatar = sqlx::query_scalar!(
+            r#"SELECT avatar FROM forum.users WHERE id = $1 FOR UPDATE"#, user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"UPDATE forum.posts SET author = NULL WHERE author = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.private_messages WHERE author = $1 OR receiver = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.chat_posts WHERE author = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.chat_room_members WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.user_group_members WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.section_moderators WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Take their reactions back out of the like counters they fed
+        sqlx::query!(
+            r#" WITH r AS (
+                    DELETE FROM forum.post_reactions WHERE user_id = $1
+                    RETURNING post_id),
+                p AS (
+                    UPDATE forum.posts SET likes = GREATEST(likes - c.n, 0)
+                    FROM (SELECT post_id, COUNT(*) n FROM r GROUP BY post_id) c
+                    WHERE forum.posts.id = c.post_id
+                    RETURNING forum.posts.author, c.n)
+                UPDATE forum.users u SET received_likes = GREATEST(received_likes - a.n, 0)
+                FROM (SELECT author, SUM(n) n FROM p GROUP BY author) a
+                WHERE u.id = a.author"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // The row itself stays because threads and warnings still point at it
+        sqlx::query!(r#"DELETE FROM forum.sessions WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.user_totp WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.recovery_codes WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.user_identities WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.email_changes WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.profile_history WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" UPDATE forum.users
+                SET name = 'deleted-' || replace(id::text, '-', ''),
+                    email = 'deleted-' || replace(id::text, '-', '') || '@deleted.invalid',
+                    password = $2,
+                    role = 'user',
+                    verified = false,
+                    verification_token = NULL,
+                    token_expires_at = NULL,
+                    description = NULL,
+                    avatar = NULL,
+                    facebook = NULL,
+                    x_id = NULL,
+                    banned_until = NULL,
+                    last_online = NULL,
+                    updated_at = LOCALTIMESTAMP
+                WHERE id = $1"#,
+            user_id, password)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"UPDATE forum.account_deletions SET completed_at = LOCALTIMESTAMP WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(avatar)
+    }
+
+    async fn get_all_pms(&self, user_id: Uuid) -> Result<Vec<PrivateMessage>, sqlx::Error> {
+        sqlx::query_as!(PrivateMessage,
+            r#" SELECT id, author, receiver, content FROM forum.private_messages
+                WHERE author = $1 OR receiver = $1
+                ORDER BY id"#, user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+}
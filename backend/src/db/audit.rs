@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{audit::AuditEntry,
+    models::{AuditAction, AuditFilter, AuditLogEntry, AuditTarget}};
+
+/// `forum.audit_log` only takes inserts, a trigger rejects any update or delete.
+#[async_trait]
+pub trait AuditExt {
+    async fn add_audit_entry(&self, actor: Uuid, ip: Option<&str>, entry: &AuditEntry) -> Result<(), sqlx::Error>;
+    async fn get_audit_log(&self, filter: &AuditFilter, page: u32, limit: usize) -> Result<Vec<AuditLogEntry>, sqlx::Error>;
+}
+
+#[async_trait]
+impl AuditExt for crate::db::DBClient {
+    async fn add_audit_entry(&self, actor: Uuid, ip: Option<&str>, entry: &AuditEntry) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" INSERT INTO forum.audit_log(actor, action, target_type, target_id, before, after, ip)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+            actor, entry.action.to_str(), entry.target_type.to_str(), entry.target_id, entry.before, entry.after, ip)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Newest first.
+    async fn get_audit_log(&self, filter: &AuditFilter, page: u32, limit: usize) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+        let offset = (page.max(1) as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(AuditLogEntry,
+            r#" SELECT l.id, l.actor, u.name as actor_name, l.action::text as "action!: AuditAction",
+                    l.target_type::text as "target_type!: AuditTarget", l.target_id, l.before, l.after, l.ip, l.created_at
+                FROM forum.audit_log l
+                INNER JOIN forum.users u ON u.id = l.actor
+                WHERE ($1::uuid IS NULL OR l.actor = $1)
+                    AND ($2::text IS NULL OR l.action = $2)
+                    AND ($3::text IS NULL OR l.target_type = $3)
+                    AND ($4::text IS NULL OR l.target_id = $4)
+                    AND ($5::timestamptz IS NULL OR l.created_at >= $5)
+                    AND ($6::timestamptz IS NULL OR l.created_at < $6)
+                ORDER BY l.created_at DESC, l.id DESC
+                LIMIT $7
+                OFFSET $8"#,
+            filter.actor, filter.action.map(AuditAction::to_str), filter.target_type.map(AuditTarget::to_str),
+            filter.target_id, filter.from, filter.to, limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+}
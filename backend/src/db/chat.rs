@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{ChatPost, ChatRoom, ChatRoomAccess, ChatRoomMember, UserRole};
+
+#[async_trait]
+pub trait ChatExt {
+    async fn create_room(&self, name: &str, description: Option<&str>, access: ChatRoomAccess, section_id: Option<i64>, allowed_for: &[UserRole], created_by: Uuid) -> Result<i32, sqlx::Error>;
+    async fn get_rooms(&self, user_id: Uuid, room_id: Option<i32>) -> Result<Vec<ChatRoom>, sqlx::Error>;
+    async fn get_room(&self, user_id: Uuid, room_id: i32) -> Result<Option<ChatRoom>, sqlx::Error>;
+    async fn set_room_archived(&self, room_id: i32, archived: bool) -> Result<bool, sqlx::Error>;
+    async fn delete_room(&self, room_id: i32) -> Result<bool, sqlx::Error>;
+
+    async fn get_chat(&self, room_id: i32, before: Option<i32>, limit: usize) -> Result<Vec<ChatPost>, sqlx::Error>;
+    async fn get_chat_post(&self, post_id: i32) -> Result<Option<ChatPost>, sqlx::Error>;
+    async fn post_chat(&self, room_id: i32, u_id: Uuid, content: &str) -> Result<ChatPost, sqlx::Error>;
+    async fn delete_chat(&self, post_id: i32) -> Result<Option<i32>, sqlx::Error>;
+
+    async fn join_room(&self, room_id: i32, user_id: Uuid) -> Result<(), sqlx::Error>;
+    async fn leave_room(&self, room_id: i32, user_id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn get_room_members(&self, room_id: i32, page: u32, limit: usize) -> Result<Vec<ChatRoomMember>, sqlx::Error>;
+}
+
+#[async_trait]
+impl ChatExt for crate::db::DBClient {
+    async fn create_room(&self, name: &str, description: Option<&str>, access: ChatRoomAccess, section_id: Option<i64>, allowed_for: &[UserRole], created_by: Uuid) -> Result<i32, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let room_id = sqlx::query_scalar!(
+            r#" INSERT INTO forum.chat_rooms(name, description, access, section_id, created_by, created_at)
+                VALUES ($1, $2, $3, $4, $5, LOCALTIMESTAMP)
+                RETURNING id"#,
+            name, description, access as ChatRoomAccess, section_id, created_by)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.chat_rooms_allowed(room_id, role)
+                SELECT $1, role FROM UNNEST($2::forum.user_role[]) AS role
+                ON CONFLICT DO NOTHING"#,
+            room_id, allowed_for as &[UserRole])
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(room_id)
+    }
+
+    /// Rooms the user can see, admins see all of them.
+    /// Role gating works like `forum.sections_allowed`, a section room is open to whoever may view its section.
+    async fn get_rooms(&self, user_id: Uuid, room_id: Option<i32>) -> Result<Vec<ChatRoom>, sqlx::Error> {
+        sqlx::query_as!(ChatRoom,
+            r#" SELECT r.id, r.name, r.description, r.access as "access: ChatRoomAccess", r.section_id,
+                    COALESCE(array_agg(ca.role) FILTER (WHERE ca.role IS NOT NULL), '{}') as "allowed_for!: Vec<UserRole>",
+                    r.created_by, r.created_at, r.archived_at
+                FROM forum.chat_rooms r
+                CROSS JOIN (SELECT role FROM forum.users WHERE id = $1) u
+                LEFT JOIN forum.chat_rooms_allowed ca ON ca.room_id = r.id
+                WHERE ($2::int4 IS NULL OR r.id = $2)
+                GROUP BY r.id, u.role
+                HAVING u.role = 'admin'
+                    OR r.access = 'public'
+                    OR (r.access = 'section' AND EXISTS (
+                        SELECT 1 FROM forum.section_access sa
+                        WHERE sa.section_id = r.section_id AND sa.user_id = $1 AND sa.can_view))
+                    OR (r.access = 'roles' AND u.role = ANY(array_agg(ca.role)))
+                ORDER BY r.archived_at IS NOT NULL, r.name"#,
+            user_id, room_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_room(&self, user_id: Uuid, room_id: i32) -> Result<Option<ChatRoom>, sqlx::Error> {
+        Ok(self.get_rooms(user_id, Some(room_id)).await?.pop())
+    }
+
+    async fn set_room_archived(&self, room_id: i32, archived: bool) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#" UPDATE forum.chat_rooms
+                SET archived_at = CASE WHEN $2 THEN COALESCE(archived_at, LOCALTIMESTAMP) END
+                WHERE id = $1"#,
+            room_id, archived)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_room(&self, room_id: i32) -> Result<bool, sqlx::Error> {
+        // Posts, members and allowed roles go with it through their foreign keys
+        let result = sqlx::query!(r#"DELETE FROM forum.chat_rooms WHERE id = $1"#, room_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_chat(&self, room_id: i32, before: Option<i32>, limit: usize) -> Result<Vec<ChatPost>, sqlx::Error> {
+        let limit = limit as i64;
+        sqlx::query_as!(ChatPost,
+            r#" SELECT p.id, added, author, u.name as author_name, content, room_id FROM forum.chat_posts p
+                INNER JOIN forum.users u ON author = u.id
+                WHERE room_id = $1 AND p.hidden_at IS NULL AND ($2::int4 IS NULL OR p.id < $2)
+                ORDER BY p.id DESC
+                LIMIT $3"#, room_id, before, limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_chat_post(&self, post_id: i32) -> Result<Option<ChatPost>, sqlx::Error> {
+        sqlx::query_as!(ChatPost,
+            r#" SELECT p.id, added, author, u.name as author_name, content, room_id FROM forum.chat_posts p
+                INNER JOIN forum.users u ON author = u.id
+                WHERE p.id = $1 AND p.hidden_at IS NULL"#, post_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn post_chat(&self, room_id: i32, u_id: Uuid, content: &str) -> Result<ChatPost, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let post = sqlx::query_as!(ChatPost,
+            r#" WITH p AS (
+                    INSERT INTO forum.chat_posts(added, author, content, room_id)
+                    VALUES (LOCALTIMESTAMP, $1, $2, $3)
+                    RETURNING id, added, author, content, room_id)
+                SELECT p.id, added, author, u.name as author_name, content, room_id FROM p
+                INNER JOIN forum.users u ON author = u.id"#, u_id, content, room_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // Talking in a room makes you a member of it
+        sqlx::query!(
+            r#" INSERT INTO forum.chat_room_members(room_id, user_id, joined_at)
+                VALUES ($1, $2, LOCALTIMESTAMP)
+                ON CONFLICT DO NOTHING"#, room_id, u_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(post)
+    }
+
+    async fn delete_chat(&self, post_id: i32) -> Result<Option<i32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"DELETE FROM forum.chat_posts WHERE id = $1 RETURNING room_id"#, post_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn join_room(&self, room_id: i32, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" INSERT INTO forum.chat_room_members(room_id, user_id, joined_at)
+                VALUES ($1, $2, LOCALTIMESTAMP)
+                ON CONFLICT DO NOTHING"#, room_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn leave_room(&self, room_id: i32, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM forum.chat_room_members WHERE room_id = $1 AND user_id = $2"#, room_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_room_members(&self, room_id: i32, page: u32, limit: usize) -> Result<Vec<ChatRoomMember>, sqlx::Error> {
+        let offset = (page as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(ChatRoomMember,
+            r#" SELECT m.user_id, u.name, u.avatar, m.joined_at
+                FROM forum.chat_room_members m
+                INNER JOIN forum.users u ON m.user_id = u.id
+                WHERE m.room_id = $1
+                ORDER BY u.name
+                LIMIT $2
+                OFFSET $3"#,
+            room_id, limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+}
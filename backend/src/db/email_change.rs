@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use crate::models::{EmailChange, NewEmailChange};
+
+#[async_trait]
+pub trait EmailChangeExt {
+    async fn create_email_change(&self, change: &NewEmailChange<'_>) -> Result<EmailChange, sqlx::Error>;
+    async fn get_email_change(&self, confirm_token: Option<&str>, revert_token: Option<&str>) -> Result<Option<EmailChange>, sqlx::Error>;
+    async fn confirm_email_change(&self, change: &EmailChange) -> Result<bool, sqlx::Error>;
+    async fn revert_email_change(&self, change: &EmailChange) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl EmailChangeExt for crate::db::DBClient {
+    async fn create_email_change(&self, change: &NewEmailChange<'_>) -> Result<EmailChange, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Only the latest request can be confirmed, older links stop working.
+        sqlx::query!(
+            r#" DELETE FROM forum.email_changes
+                WHERE user_id = $1 AND confirmed_at IS NULL AND reverted_at IS NULL"#, change.user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let created = sqlx::query_as!(EmailChange,
+            r#" INSERT INTO forum.email_changes(user_id, old_email, new_email, confirm_token, revert_token, created_at, expires_at, revert_expires_at)
+                VALUES ($1, $2, $3, $4, $5, LOCALTIMESTAMP, $6, $7)
+                RETURNING id, user_id, old_email, new_email, created_at, expires_at, confirmed_at, revert_expires_at, reverted_at"#,
+            change.user_id, change.old_email, change.new_email, change.confirm_token, change.revert_token,
+            change.expires_at, change.revert_expires_at)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    async fn get_email_change(&self, confirm_token: Option<&str>, revert_token: Option<&str>) -> Result<Option<EmailChange>, sqlx::Error> {
+        let mut change: Option<EmailChange> = None;
+
+        if let Some(confirm_token) = confirm_token {
+            change = sqlx::query_as!(EmailChange,
+                r#" SELECT id, user_id, old_email, new_email, created_at, expires_at, confirmed_at, revert_expires_at, reverted_at
+                    FROM forum.email_changes WHERE confirm_token = $1"#, confirm_token)
+                .fetch_optional(&self.pool)
+                .await?;
+        } else if let Some(revert_token) = revert_token {
+            change = sqlx::query_as!(EmailChange,
+                r#" SELECT id, user_id, old_email, new_email, created_at, expires_at, confirmed_at, revert_expires_at, reverted_at
+                    FROM forum.email_changes WHERE revert_token = $1"#, revert_token)
+                .fetch_optional(&self.pool)
+                .await?;
+        }
+
+        Ok(change)
+    }
+
+    async fn confirm_email_change(&self, change: &EmailChange) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let claimed = sqlx::query!(
+            r#" UPDATE forum.email_changes SET confirmed_at = LOCALTIMESTAMP
+                WHERE id = $1 AND confirmed_at IS NULL AND reverted_at IS NULL"#, change.id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Only applies while the account still uses the address the request was made from
+        let updated = sqlx::query!(
+            r#" UPDATE forum.users SET email = $3, verified = true, updated_at = LOCALTIMESTAMP
+                WHERE id = $1 AND email = $2"#,
+            change.user_id, change.old_email, change.new_email)
+            .execute(&mut *tx)
+            .await?;
+
+        if claimed.rows_affected() == 0 || updated.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#" INSERT INTO forum.profile_history(user_id, changed_by, field, old_value, new_value)
+                VALUES ($1, $1, 'email', $2, $3)"#,
+            change.user_id, change.old_email, change.new_email)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn revert_email_change(&self, change: &EmailChange) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let claimed = sqlx::query!(
+            r#" UPDATE forum.email_changes SET reverted_at = LOCALTIMESTAMP
+                WHERE id = $1 AND reverted_at IS NULL"#, change.id)
+            .execute(&mut *tx)
+            .await?;
+
+        if claimed.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        // Before confirmation there is nothing to undo, the request is just cancelled
+        if change.confirmed_at.is_some() {
+            let updated = sqlx::query!(
+                r#" UPDATE forum.users SET email = $2, updated_at = LOCALTIMESTAMP
+                    WHERE id = $1 AND email = $3"#,
+                change.user_id, change.old_email, change.new_email)
+                .execute(&mut *tx)
+                .await?;
+
+            if updated.rows_affected() == 0 {
+                tx.rollback().await?;
+                return Ok(false);
+            }
+
+            sqlx::query!(
+                r#" INSERT INTO forum.profile_history(user_id, changed_by, field, old_value, new_value)
+                    VALUES ($1, $1, 'email', $2, $3)"#,
+                change.user_id, change.new_email, change.old_email)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+}
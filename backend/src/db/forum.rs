@@ -1,73 +1,117 @@
 use async_trait::async_trait;
 use uuid::Uuid;
 
-use crate::models::{Section, Thread, ChatPost, Post, UserRole};
+use crate::models::{ForumSearch, Post, ReplyRow, SearchCursor, SearchHit, Section, SectionContent, SectionRow, SplitThread, Thread, UserRole};
 
 #[async_trait]
 pub trait ForumExt {
-    async fn create_thread(&self, user: Uuid, section: i64, title: &str, content: &str, hash_tags: &Vec<String>) -> Result<(), sqlx::Error>;
-    async fn delete_thread(&self, thread_id: i64) -> Result<(), sqlx::Error>;
-    async fn update_thread(&self, thread_id: i64, title: &str, content: &str) -> Result<(), sqlx::Error>;
+    async fn create_thread(&self, user: Uuid, section: i64, title: &str, content: &str, content_html: &str, hash_tags: &[String]) -> Result<(), sqlx::Error>;
+    async fn delete_thread(&self, thread_id: i64, deleted_by: Uuid) -> Result<(), sqlx::Error>;
+    async fn update_thread(&self, thread_id: i64, title: &str, content: &str, content_html: &str, editor: Uuid, reason: Option<&str>) -> Result<bool, sqlx::Error>;
     async fn lock_thread(&self, thread_id: i64, locked: bool) -> Result<(), sqlx::Error>;
-
-    async fn create_section(&self, name: &str, description: &str, allowed_for: &[UserRole]) -> Result<(), sqlx::Error>;
-    async fn get_sections(&self, user: Uuid) -> Result<Vec<Section>, sqlx::Error>;
-    async fn delete_section(&self, s_id: i64) -> Result<(), sqlx::Error>;
-
-    async fn get_chat(&self, limit: usize) -> Result<Vec<ChatPost>, sqlx::Error>;
-    async fn post_chat(&self, u_id: Uuid, content: &str) -> Result<(), sqlx::Error>;
-    async fn delete_chat(&self, post_id: i32) -> Result<(), sqlx::Error>;
+    async fn set_thread_sticky(&self, thread_id: i64, sticky: bool) -> Result<(), sqlx::Error>;
+    async fn set_thread_announcement(&self, thread_id: i64, announcement: bool) -> Result<(), sqlx::Error>;
+    async fn get_announcements(&self, user: Uuid) -> Result<Vec<Thread>, sqlx::Error>;
+    async fn move_thread(&self, thread_id: i64, s_id: i64, leave_stub: bool) -> Result<(), sqlx::Error>;
+    async fn merge_threads(&self, from: i64, into: i64) -> Result<u64, sqlx::Error>;
+    async fn split_thread(&self, thread_id: i64, post_ids: &[i64], title: &str, s_id: i64, moderator: Uuid) -> Result<Option<SplitThread>, sqlx::Error>;
+
+    async fn create_section(&self, name: &str, description: Option<&str>, parent_id: Option<i64>, category: bool, allowed_for: &[UserRole]) -> Result<i64, sqlx::Error>;
+    async fn get_sections(&self, user: Uuid) -> Result<Vec<SectionRow>, sqlx::Error>;
+    async fn get_section_info(&self, s_id: i64) -> Result<Option<Section>, sqlx::Error>;
+    async fn get_section_content(&self, s_id: i64) -> Result<SectionContent, sqlx::Error>;
+    async fn is_in_section_tree(&self, s_id: i64, root: i64) -> Result<bool, sqlx::Error>;
+    async fn move_section(&self, s_id: i64, parent_id: Option<i64>) -> Result<(), sqlx::Error>;
+    async fn reorder_sections(&self, parent_id: Option<i64>, order: &[i64]) -> Result<bool, sqlx::Error>;
+    async fn delete_section(&self, s_id: i64, move_to: Option<i64>) -> Result<(), sqlx::Error>;
+    async fn delete_section_tree(&self, s_id: i64) -> Result<(), sqlx::Error>;
 
     async fn get_section(&self, s_id: i64, page: i32, limit: usize) -> Result<Vec<Thread>, sqlx::Error>;
     async fn get_thread(&self, t_id: i64, page: i32, limit: usize) -> Result<Vec<Post>, sqlx::Error>;
+    async fn get_reply_tree(&self, t_id: i64, parent: Option<i64>, after: Option<i64>, limit: usize, depth: u32, replies: usize) -> Result<Vec<ReplyRow>, sqlx::Error>;
     async fn get_thread_info(&self, t_id: i64) -> Result<Thread, sqlx::Error>;
     async fn get_thread_author(&self, t_id: i64) -> Result<Uuid, sqlx::Error>;
     async fn get_thread_reply_count(&self, t_id: i64) -> Result<i64, sqlx::Error>;
 
-    async fn update_post(&self, p_id: i64, content: &str) -> Result<(), sqlx::Error>;
-    async fn delete_post(&self, post_id: i64) -> Result<(), sqlx::Error>;
+    async fn add_post(&self, user: Uuid, t_id: i64, content: &str, content_html: &str, parent: Option<i64>) -> Result<Option<Post>, sqlx::Error>;
+    async fn update_post(&self, p_id: i64, content: &str, content_html: &str, editor: Uuid, reason: Option<&str>) -> Result<bool, sqlx::Error>;
+    async fn get_post(&self, p_id: i64) -> Result<Option<Post>, sqlx::Error>;
+    async fn delete_post(&self, post_id: i64, deleted_by: Uuid) -> Result<(), sqlx::Error>;
     async fn get_post_author(&self, t_id: i64) -> Result<Option<Uuid>, sqlx::Error>;
-    async fn posts_since(&self, post_id: i64) -> Result<i64, sqlx::Error>;
+
+    async fn search(&self, user: Uuid, search: &ForumSearch, cursor: Option<&SearchCursor>, limit: usize) -> Result<Vec<SearchHit>, sqlx::Error>;
 }
 
 #[async_trait]
 impl ForumExt for crate::db::DBClient {
-    async fn create_thread(&self, user: Uuid, section: i64, title: &str, content: &str, hash_tags: &Vec<String>) -> Result<(), sqlx::Error> {
+    async fn create_thread(&self, user: Uuid, section: i64, title: &str, content: &str, content_html: &str, hash_tags: &[String]) -> Result<(), sqlx::Error> {
         struct ParsingHelper {
             id: i64,
         }
 
-        let r = sqlx::query_as!(ParsingHelper, r#"INSERT INTO forum.threads(title,created_at,content,author,section_id,locked)
-            VALUES ($1,LOCALTIMESTAMP,$2,$3,$4,false)
+        let mut tx = self.pool.begin().await?;
+
+        let r = sqlx::query_as!(ParsingHelper, r#"INSERT INTO forum.threads(title,created_at,content,author,section_id,locked,content_html)
+            VALUES ($1,LOCALTIMESTAMP,$2,$3,$4,false,$5)
             RETURNING id"#,
-            title, content, user, section)
-            .fetch_one(&self.pool)
+            title, content, user, section, content_html)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // Tags arrive normalized, the same tag twice is only stored once
+        sqlx::query!(r#"INSERT INTO forum.hashtags(tag, topic)
+            SELECT DISTINCT tag, $2::int8 FROM unnest($1::varchar[]) AS tag
+            ON CONFLICT DO NOTHING"#, hash_tags, r.id)
+            .execute(&mut *tx)
             .await?;
-            let _ = hash_tags.iter().map(async |t| {
-                sqlx::query!(r#"INSERT INTO forum.hashtags(tag, topic) VALUES($1, $2)"#, t, r.id)
-                    .execute(&self.pool)
-                    .await
-                    .map_err(|e| e)
-            });
+
+        tx.commit().await?;
+
         Ok(())
     }
 
-    async fn delete_thread(&self, thread_id: i64) -> Result<(), sqlx::Error> {
-        sqlx::query!(r#"DELETE FROM forum.threads WHERE id = $1"#, thread_id)
+    /// Only hides the thread, `TrashExt` restores or purges it.
+    async fn delete_thread(&self, thread_id: i64, deleted_by: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" UPDATE forum.threads
+                SET deleted_at = NOW(), deleted_by = $2
+                WHERE id = $1 AND deleted_at IS NULL"#, thread_id, deleted_by)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 
-    async fn update_thread(&self, thread_id: i64, title: &str, content: &str) -> Result<(), sqlx::Error> {
+    /// Keeps the replaced title and content as a revision. An edit changing neither is not recorded and returns false.
+    async fn update_thread(&self, thread_id: i64, title: &str, content: &str, content_html: &str, editor: Uuid, reason: Option<&str>) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let saved = sqlx::query!(
+            r#" INSERT INTO forum.revisions(thread_id, title, content, editor, edited_at, reason)
+                SELECT id, title, content, $4, NOW(), $5
+                FROM forum.threads
+                WHERE id = $1 AND (title <> $2 OR content <> $3)"#,
+            thread_id, title, content, editor, reason)
+            .execute(&mut *tx)
+            .await?;
+
+        if saved.rows_affected() == 0 {
+            return Ok(false);
+        }
+
         sqlx::query!(r#"UPDATE forum.threads
             SET
                 title = $2,
-                content = $3
-            WHERE id = $1"#, thread_id, title, content)
-            .execute(&self.pool)
+                content = $3,
+                content_html = $4,
+                modified_at = NOW(),
+                edit_count = edit_count + 1
+            WHERE id = $1"#, thread_id, title, content, content_html)
+            .execute(&mut *tx)
             .await?;
-        Ok(())
+
+        tx.commit().await?;
+
+        Ok(true)
     }
 
     async fn lock_thread(&self, thread_id: i64, locked: bool) -> Result<(), sqlx::Error> {
@@ -77,82 +121,409 @@ impl ForumExt for crate::db::DBClient {
             WHERE id = $1
             "#,
             thread_id, locked)
-            .fetch_one(&self.pool)
+            .execute(&self.pool)
             .await?;
         Ok(())
     }
 
-    async fn create_section(&self, name: &str, description: &str, allowed_for: &[UserRole]) -> Result<(), sqlx::Error> {
-        struct Helper {
-            id: i64,
+    async fn set_thread_sticky(&self, thread_id: i64, sticky: bool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE forum.threads SET sticky = $2 WHERE id = $1"#, thread_id, sticky)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_thread_announcement(&self, thread_id: i64, announcement: bool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE forum.threads SET announcement = $2 WHERE id = $1"#, thread_id, announcement)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Announcements from every section the user may view, the same rule as `get_sections`.
+    async fn get_announcements(&self, user: Uuid) -> Result<Vec<Thread>, sqlx::Error> {
+        sqlx::query_as!(Thread,
+            r#" SELECT id, title, created_at, content, author, section_id, locked, sticky, content_html, announcement, redirect_to, modified_at, edit_count
+                FROM forum.threads
+                WHERE announcement
+                    AND deleted_at IS NULL
+                    AND section_id IN (
+                        SELECT sa.section_id FROM forum.section_access sa
+                        WHERE sa.user_id = $1 AND sa.can_view)
+                ORDER BY created_at DESC, id DESC"#, user)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// With `leave_stub` a locked copy pointing to the thread stays in the old section.
+    async fn move_thread(&self, thread_id: i64, s_id: i64, leave_stub: bool) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        if leave_stub {
+            sqlx::query!(
+                r#" INSERT INTO forum.threads(title, created_at, content, author, section_id, locked, redirect_to)
+                    SELECT title, created_at, '', author, section_id, true, id
+                    FROM forum.threads
+                    WHERE id = $1 AND section_id <> $2"#, thread_id, s_id)
+                .execute(&mut *tx)
+                .await?;
         }
 
-        let r = sqlx::query_as!(Helper,
-            r#" INSERT INTO forum.sections
-                    (name, description)
-                VALUES($1, $2)
-                RETURNING id"#, name, description)
-            .fetch_one(&self.pool)
+        sqlx::query!(
+            r#"UPDATE forum.threads SET section_id = $2 WHERE id = $1"#, thread_id, s_id)
+            .execute(&mut *tx)
             .await?;
 
-        let _ = allowed_for.iter().map(async |rl| {
-            sqlx::query!(r#"INSERT INTO forum.sections_allowed
-                                (section_id, role)
-                            VALUES($1, $2)"#, r.id, *rl as UserRole)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| e)
-        });
+        tx.commit().await?;
 
         Ok(())
     }
 
-    async fn get_sections(&self, user: Uuid) -> Result<Vec<Section>, sqlx::Error> {
-         sqlx::query_as!(Section,
-            r#"SELECT s.id, s.name, s.description FROM forum.sections_allowed sa
-               JOIN(SELECT role FROM forum.users WHERE id = $1) ur ON sa.role = ur.role
-               JOIN forum.sections s ON section_id = sa.section_id"#, user)
+    /// Moves the posts and tags of `from` into `into` and leaves `from` as a stub pointing to it.
+    /// The opening post of `from` becomes a reply with its original time, so both threads interleave
+    /// in order. Returns the number of posts moved, the opening one included.
+    async fn merge_threads(&self, from: i64, into: i64) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let moved = sqlx::query!(
+            r#"UPDATE forum.posts SET topic = $2 WHERE topic = $1"#, from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.posts(content, author, topic, comments, created_at, content_html)
+                SELECT content, author, $2, NULL, created_at, content_html
+                FROM forum.threads WHERE id = $1"#, from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.hashtags(tag, topic)
+                SELECT tag, $2 FROM forum.hashtags WHERE topic = $1
+                ON CONFLICT DO NOTHING"#, from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"DELETE FROM forum.hashtags WHERE topic = $1"#, from)
+            .execute(&mut *tx)
+            .await?;
+
+        // Stubs already pointing to `from` skip the extra hop
+        sqlx::query!(
+            r#"UPDATE forum.threads SET redirect_to = $2 WHERE redirect_to = $1"#, from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" UPDATE forum.threads
+                SET content = '', content_html = NULL, locked = true, sticky = false, announcement = false, redirect_to = $2
+                WHERE id = $1"#, from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(moved.rows_affected() + 1)
+    }
+
+    /// Opens a thread in `s_id` with the chosen posts, the oldest of them becoming its opening post.
+    /// Replies whose parent ends up in the other thread move to the top level. `moderator` stands
+    /// in as the author when the opening post's author deleted their account.
+    /// Returns `None` when one of the posts is not in the thread.
+    async fn split_thread(&self, thread_id: i64, post_ids: &[i64], title: &str, s_id: i64, moderator: Uuid) -> Result<Option<SplitThread>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let posts = sqlx::query!(
+            r#" SELECT id, author, content, content_html, created_at, modified_at, edit_count, likes
+                FROM forum.posts
+                WHERE id = ANY($1) AND topic = $2 AND deleted_at IS NULL
+                ORDER BY created_at, id
+                FOR UPDATE"#, post_ids, thread_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        let mut wanted = post_ids.to_vec();
+        wanted.sort_unstable();
+        wanted.dedup();
+        if posts.is_empty() || posts.len() != wanted.len() {
+            return Ok(None);
+        }
+
+        let first = &posts[0];
+        let new_id = sqlx::query_scalar!(
+            r#" INSERT INTO forum.threads(title, created_at, content, author, section_id, locked, content_html, modified_at, edit_count)
+                VALUES ($1, $2, $3, $4, $5, false, $6, $7, $8)
+                RETURNING id"#,
+            title, first.created_at, first.content, first.author.unwrap_or(moderator), s_id, first.content_html, first.modified_at, first.edit_count)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // The edit history of the opening post becomes the thread's, under the thread's title
+        sqlx::query!(
+            r#"UPDATE forum.revisions SET post_id = NULL, thread_id = $2, title = $3 WHERE post_id = $1"#, first.id, new_id, title)
+            .execute(&mut *tx)
+            .await?;
+
+        let dropped_reactions = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM forum.post_reactions WHERE post_id = $1"#, first.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"UPDATE forum.posts SET topic = $2 WHERE id = ANY($1) AND id <> $3"#, post_ids, new_id, first.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"UPDATE forum.posts SET comments = NULL WHERE comments = $1"#, first.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" UPDATE forum.posts c SET comments = NULL
+                FROM forum.posts p
+                WHERE c.comments = p.id
+                    AND c.topic <> p.topic
+                    AND c.topic IN ($1, $2)"#, thread_id, new_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // The opening post is now part of the thread itself, its reactions go the same way as in `delete_post`
+        sqlx::query!(
+            r#" WITH d AS (
+                    DELETE FROM forum.posts
+                    WHERE id = $1
+                    RETURNING author, likes)
+                UPDATE forum.users u SET received_likes = GREATEST(received_likes - d.likes, 0)
+                FROM d WHERE u.id = d.author"#, first.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(SplitThread {
+            thread_id: new_id,
+            dropped_likes: first.likes,
+            dropped_reactions,
+        }))
+    }
+
+    /// New sections go after their siblings. Every role listed may view and post, staff roles also moderate.
+    async fn create_section(&self, name: &str, description: Option<&str>, parent_id: Option<i64>, category: bool, allowed_for: &[UserRole]) -> Result<i64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let id = sqlx::query_scalar!(
+            r#" INSERT INTO forum.sections
+                    (name, description, parent_id, position, category)
+                VALUES($1, $2, $3,
+                    (SELECT COALESCE(MAX(position) + 1, 0) FROM forum.sections WHERE parent_id IS NOT DISTINCT FROM $3),
+                    $4)
+                RETURNING id"#, name, description, parent_id, category)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.sections_allowed(section_id, role, can_moderate)
+                SELECT DISTINCT $1::int8, role, role IN ('admin', 'mod') FROM UNNEST($2::forum.user_role[]) AS role"#,
+            id, allowed_for as &[UserRole])
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    async fn get_sections(&self, user: Uuid) -> Result<Vec<SectionRow>, sqlx::Error> {
+        sqlx::query_as!(SectionRow,
+            r#" SELECT s.id, s.name, s.description, s.parent_id, s.position, s.category,
+                    (SELECT COUNT(*) FROM forum.threads t
+                     WHERE t.section_id = s.id AND t.redirect_to IS NULL AND t.deleted_at IS NULL) as "thread_count!",
+                    (SELECT COUNT(*) FROM forum.posts p
+                     INNER JOIN forum.threads t ON t.id = p.topic
+                     WHERE t.section_id = s.id AND t.deleted_at IS NULL AND p.deleted_at IS NULL) as "post_count!",
+                    l.thread_id as "latest_thread_id?", l.post_id as "latest_post_id?", l.title as "latest_title?",
+                    l.author as "latest_author?", u.name as "latest_author_name?", l.created_at as "latest_at?"
+                FROM forum.sections s
+                LEFT JOIN LATERAL (
+                    (SELECT t.id AS thread_id, NULL::int8 AS post_id, t.title, t.author, t.created_at
+                     FROM forum.threads t
+                     WHERE t.section_id = s.id AND t.redirect_to IS NULL AND t.deleted_at IS NULL
+                     ORDER BY t.created_at DESC
+                     LIMIT 1)
+                    UNION ALL
+                    (SELECT t.id, p.id, t.title, p.author, p.created_at
+                     FROM forum.posts p
+                     INNER JOIN forum.threads t ON t.id = p.topic
+                     WHERE t.section_id = s.id AND t.deleted_at IS NULL AND p.deleted_at IS NULL
+                     ORDER BY p.created_at DESC
+                     LIMIT 1)
+                    ORDER BY created_at DESC
+                    LIMIT 1) l ON true
+                LEFT JOIN forum.users u ON u.id = l.author
+                WHERE s.id IN (
+                    SELECT sa.section_id FROM forum.section_access sa
+                    WHERE sa.user_id = $1 AND sa.can_view)
+                ORDER BY s.position, s.id"#, user)
             .fetch_all(&self.pool)
             .await
     }
 
-    async fn delete_section(&self, s_id: i64) -> Result<(), sqlx::Error> {
+    async fn get_section_info(&self, s_id: i64) -> Result<Option<Section>, sqlx::Error> {
+        sqlx::query_as!(Section,
+            r#"SELECT id, name, description, parent_id, position, category FROM forum.sections WHERE id = $1"#, s_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_section_content(&self, s_id: i64) -> Result<SectionContent, sqlx::Error> {
+        sqlx::query_as!(SectionContent,
+            r#" SELECT
+                    (SELECT COUNT(*) FROM forum.sections WHERE parent_id = $1) as "children!",
+                    (SELECT COUNT(*) FROM forum.threads WHERE section_id = $1) as "threads!""#, s_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Whether `s_id` is `root` or one of its subsections, a section can't be moved below itself.
+    async fn is_in_section_tree(&self, s_id: i64, root: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#" WITH RECURSIVE tree AS (
+                    SELECT id FROM forum.sections WHERE id = $2
+                    UNION
+                    SELECT s.id FROM forum.sections s
+                    INNER JOIN tree t ON s.parent_id = t.id
+                )
+                SELECT EXISTS (SELECT 1 FROM tree WHERE id = $1) as "exists!""#, s_id, root)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Puts the section after the sections already below its new parent.
+    async fn move_section(&self, s_id: i64, parent_id: Option<i64>) -> Result<(), sqlx::Error> {
         sqlx::query!(
-            r#"DELETE FROM forum.sections
-               WHERE id = $1"#, s_id)
+            r#" UPDATE forum.sections
+                SET parent_id = $2,
+                    position = (SELECT COALESCE(MAX(position) + 1, 0) FROM forum.sections WHERE parent_id IS NOT DISTINCT FROM $2 AND id <> $1)
+                WHERE id = $1"#, s_id, parent_id)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
 
-    async fn get_chat(&self, limit: usize) -> Result<Vec<ChatPost>, sqlx::Error> {
-        let limit = limit as i64;
-        sqlx::query_as!(ChatPost,
-            r#" SELECT p.id,added,author,u.name as author_name,content FROM forum.chat_posts p
-                INNER JOIN forum.users u ON author = u.id
-                ORDER BY added DESC
-                LIMIT $1"#, limit)
-            .fetch_all(&self.pool)
-            .await
+    /// `order` has to list every section below the parent exactly once, nothing changes otherwise.
+    async fn reorder_sections(&self, parent_id: Option<i64>, order: &[i64]) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#" UPDATE forum.sections s
+                SET position = o.pos::int4 - 1
+                FROM UNNEST($2::int8[]) WITH ORDINALITY AS o(id, pos)
+                WHERE s.id = o.id AND s.parent_id IS NOT DISTINCT FROM $1"#,
+            parent_id, order)
+            .execute(&mut *tx)
+            .await?;
+
+        let siblings = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM forum.sections WHERE parent_id IS NOT DISTINCT FROM $1"#, parent_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // Duplicates are caught too, a section is only updated once however often it is listed
+        if result.rows_affected() != order.len() as u64 || siblings != order.len() as i64 {
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
     }
 
-    async fn post_chat(&self, u_id: Uuid, content: &str) -> Result<(), sqlx::Error> {
+    /// Hands subsections and threads over to `move_to` before deleting the section.
+    async fn delete_section(&self, s_id: i64, move_to: Option<i64>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        if let Some(move_to) = move_to {
+            sqlx::query!(
+                r#" UPDATE forum.sections
+                    SET parent_id = $2,
+                        position = position + (SELECT COALESCE(MAX(position) + 1, 0) FROM forum.sections WHERE parent_id = $2)
+                    WHERE parent_id = $1"#, s_id, move_to)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query!(
+                r#"UPDATE forum.threads SET section_id = $2 WHERE section_id = $1"#, s_id, move_to)
+                .execute(&mut *tx)
+                .await?;
+        }
+
         sqlx::query!(
-            r#" INSERT INTO forum.chat_posts(added, author, content)
-                VALUES (LOCALTIMESTAMP, $1, $2)"#, u_id, content)
-            .execute(&self.pool)
+            r#"DELETE FROM forum.sections_allowed WHERE section_id = $1"#, s_id)
+            .execute(&mut *tx)
             .await?;
 
+        sqlx::query!(
+            r#"DELETE FROM forum.sections
+               WHERE id = $1"#, s_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
 
-    async fn delete_chat(&self, post_id: i32) -> Result<(), sqlx::Error> {
+    /// Deletes the section with every subsection, thread and post below it.
+    async fn delete_section_tree(&self, s_id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let sections = sqlx::query_scalar!(
+            r#" WITH RECURSIVE tree AS (
+                    SELECT id FROM forum.sections WHERE id = $1
+                    UNION
+                    SELECT s.id FROM forum.sections s
+                    INNER JOIN tree t ON s.parent_id = t.id
+                )
+                SELECT id as "id!" FROM tree"#, s_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        // Same bookkeeping as delete_post, the reactions go with the posts
         sqlx::query!(
-            r#"DELETE FROM forum.chat_posts WHERE id = $1"#, post_id)
-            .execute(&self.pool)
+            r#" WITH d AS (
+                    DELETE FROM forum.posts p
+                    USING forum.threads t
+                    WHERE p.topic = t.id AND t.section_id = ANY($1)
+                    RETURNING p.author, p.likes),
+                l AS (
+                    SELECT author, SUM(likes) AS likes FROM d GROUP BY author)
+                UPDATE forum.users u SET received_likes = GREATEST(received_likes - l.likes, 0)
+                FROM l WHERE u.id = l.author"#, &sections)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"DELETE FROM forum.threads WHERE section_id = ANY($1)"#, &sections)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"DELETE FROM forum.sections_allowed WHERE section_id = ANY($1)"#, &sections)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"DELETE FROM forum.sections WHERE id = ANY($1)"#, &sections)
+            .execute(&mut *tx)
             .await?;
 
+        tx.commit().await?;
+
         Ok(())
     }
 
@@ -162,7 +533,9 @@ impl ForumExt for crate::db::DBClient {
         let offset = offset as i64;
 
         sqlx::query_as!(Thread,
-            r#" SELECT * FROM forum.threads WHERE section_id = $1
+            r#" SELECT id, title, created_at, content, author, section_id, locked, sticky, content_html, announcement, redirect_to, modified_at, edit_count FROM forum.threads
+                WHERE section_id = $1 AND NOT announcement AND deleted_at IS NULL
+                ORDER BY sticky DESC, created_at DESC, id DESC
                 LIMIT $2 OFFSET $3"#, s_id, limit, offset)
             .fetch_all(&self.pool)
             .await
@@ -173,15 +546,74 @@ impl ForumExt for crate::db::DBClient {
         let limit = limit as i64;
         let offset = offset as i64;
         sqlx::query_as!(Post,
-            r#" SELECT * FROM forum.posts WHERE topic = $1
+            r#" SELECT id, content, author, topic, comments, created_at, modified_at, likes, content_html, edit_count FROM forum.posts WHERE topic = $1 AND deleted_at IS NULL
+                ORDER BY created_at, id
                 LIMIT $2 OFFSET $3"#, t_id, limit, offset)
             .fetch_all(&self.pool)
             .await
     }
 
+    /// Up to `limit` replies to `parent` (top level posts when `None`) after the `after` cursor,
+    /// each with its replies down to `depth` levels. Posts are ordered by time rather than id,
+    /// merged threads bring older posts with newer ids.
+    /// `limit` posts under `parent`, and below each of them its first `replies` replies down to `depth` levels.
+    /// `reply_count` has every reply, for the cursor to the ones left out.
+    async fn get_reply_tree(&self, t_id: i64, parent: Option<i64>, after: Option<i64>, limit: usize, depth: u32, replies: usize) -> Result<Vec<ReplyRow>, sqlx::Error> {
+        let limit = limit as i64;
+        let depth = depth as i32;
+        let replies = replies as i64;
+
+        // A deleted post stays as an empty placeholder while a live post is anywhere below it, so the tree keeps its shape
+        sqlx::query_as!(ReplyRow,
+            r#" WITH RECURSIVE visible AS (
+                    SELECT p.id, p.comments FROM forum.posts p
+                    WHERE p.topic = $1 AND p.deleted_at IS NULL
+                    UNION
+                    SELECT a.id, a.comments FROM forum.posts a
+                    INNER JOIN visible v ON a.id = v.comments
+                ),
+                tree AS (
+                    (SELECT p.id, p.content, p.author, p.topic, p.comments, p.created_at, p.modified_at, p.likes, p.content_html, p.edit_count,
+                        p.deleted_at, 0 AS depth FROM forum.posts p
+                     WHERE p.topic = $1
+                        AND p.comments IS NOT DISTINCT FROM $2
+                        AND ($3::int8 IS NULL OR (p.created_at, p.id) > (SELECT a.created_at, a.id FROM forum.posts a WHERE a.id = $3))
+                        AND p.id IN (SELECT v.id FROM visible v)
+                     ORDER BY p.created_at, p.id
+                     LIMIT $4)
+                    UNION ALL
+                    SELECT p.id, p.content, p.author, p.topic, p.comments, p.created_at, p.modified_at, p.likes, p.content_html, p.edit_count,
+                        p.deleted_at, t.depth + 1 FROM tree t
+                    CROSS JOIN LATERAL (
+                        SELECT * FROM forum.posts c
+                        WHERE c.comments = t.id AND c.id IN (SELECT v.id FROM visible v)
+                        ORDER BY c.created_at, c.id
+                        LIMIT $6) p
+                    WHERE t.depth < $5
+                )
+                SELECT t.id as "id!",
+                    CASE WHEN t.deleted_at IS NULL THEN t.content ELSE '' END as "content!",
+                    CASE WHEN t.deleted_at IS NULL THEN t.author END as author,
+                    t.topic as "topic!", t.comments,
+                    t.created_at as "created_at!", t.modified_at,
+                    CASE WHEN t.deleted_at IS NULL THEN t.likes ELSE 0 END as "likes!",
+                    CASE WHEN t.deleted_at IS NULL THEN t.content_html ELSE '' END as content_html,
+                    t.edit_count as "edit_count!",
+                    t.deleted_at IS NOT NULL as "deleted!",
+                    t.depth as "depth!",
+                    (SELECT COUNT(*) FROM forum.posts r
+                     WHERE r.comments = t.id AND r.id IN (SELECT v.id FROM visible v)) as "reply_count!"
+                FROM tree t
+                ORDER BY t.depth, t.created_at, t.id"#,
+            t_id, parent, after, limit, depth, replies)
+            .fetch_all(&self.pool)
+            .await
+    }
+
     async fn get_thread_info(&self, t_id: i64) -> Result<Thread, sqlx::Error> {
         sqlx::query_as!(Thread,
-            r#" SELECT * FROM forum.threads WHERE id = $1"#, t_id)
+            r#" SELECT id, title, created_at, content, author, section_id, locked, sticky, content_html, announcement, redirect_to, modified_at, edit_count FROM forum.threads
+                WHERE id = $1 AND deleted_at IS NULL"#, t_id)
             .fetch_one(&self.pool)
             .await
     }
@@ -226,43 +658,244 @@ impl ForumExt for crate::db::DBClient {
         Ok(res.cnt.unwrap_or(-1))
     }
 
-    async fn update_post(&self, p_id: i64, content: &str) -> Result<(), sqlx::Error> {
+    /// Returns `None` when the parent post is not part of the thread.
+    async fn add_post(&self, user: Uuid, t_id: i64, content: &str, content_html: &str, parent: Option<i64>) -> Result<Option<Post>, sqlx::Error> {
+        sqlx::query_as!(Post,
+            r#" INSERT INTO forum.posts(content, author, topic, comments, created_at, content_html)
+                SELECT $1, $2, $3, $4, LOCALTIMESTAMP, $5
+                WHERE $4::int8 IS NULL
+                    OR EXISTS (SELECT 1 FROM forum.posts WHERE id = $4 AND topic = $3 AND deleted_at IS NULL)
+                RETURNING id, content, author, topic, comments, created_at, modified_at, likes, content_html, edit_count"#,
+            content, user, t_id, parent, content_html)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Keeps the replaced content as a revision. An edit that changes nothing is not recorded and returns false.
+    async fn update_post(&self, p_id: i64, content: &str, content_html: &str, editor: Uuid, reason: Option<&str>) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let saved = sqlx::query!(
+            r#" INSERT INTO forum.revisions(post_id, content, editor, edited_at, reason)
+                SELECT id, content, $3, NOW(), $4
+                FROM forum.posts
+                WHERE id = $1 AND content <> $2"#,
+            p_id, content, editor, reason)
+            .execute(&mut *tx)
+            .await?;
+
+        if saved.rows_affected() == 0 {
+            return Ok(false);
+        }
+
         sqlx::query!(
             r#" UPDATE forum.posts
-                SET content = $1
-                WHERE id = $2"#, content, p_id)
-            .execute(&self.pool)
+                SET content = $1,
+                    content_html = $3,
+                    modified_at = NOW(),
+                    edit_count = edit_count + 1
+                WHERE id = $2"#, content, p_id, content_html)
+            .execute(&mut *tx)
             .await?;
 
-        Ok(())
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_post(&self, p_id: i64) -> Result<Option<Post>, sqlx::Error> {
+        sqlx::query_as!(Post,
+            r#" SELECT id, content, author, topic, comments, created_at, modified_at, likes, content_html, edit_count
+                FROM forum.posts WHERE id = $1 AND deleted_at IS NULL"#, p_id)
+            .fetch_optional(&self.pool)
+            .await
     }
 
-    async fn delete_post(&self, post_id: i64) -> Result<(), sqlx::Error> {
+    /// Only hides the post, replies keep hanging below its placeholder. Its likes stay with the
+    /// author until it is purged, a restored post brings them back anyway.
+    async fn delete_post(&self, post_id: i64, deleted_by: Uuid) -> Result<(), sqlx::Error> {
         sqlx::query!(
-            r#" DELETE FROM forum.posts
-                WHERE id = $1"#, post_id)
+            r#" UPDATE forum.posts
+                SET deleted_at = NOW(), deleted_by = $2
+                WHERE id = $1 AND deleted_at IS NULL"#, post_id, deleted_by)
             .execute(&self.pool)
             .await?;
 
         Ok(())
+    }
+
+
+    /// Threads and posts matching the search in sections the user may view, the same rule as `get_sections`.
+    async fn search(&self, user: Uuid, search: &ForumSearch, cursor: Option<&SearchCursor>, limit: usize) -> Result<Vec<SearchHit>, sqlx::Error> {
+        sqlx::query_as!(SearchHit,
+            r#" WITH q AS (SELECT to_tsquery('simple', $2) AS query),
+                hits AS (
+                    SELECT 'thread' AS kind, t.id, t.id AS thread_id, t.title, t.section_id, t.author, t.created_at,
+                        ts_rank(t.search_vector, q.query) AS rank, t.title || E'\n' || t.content AS body
+                    FROM forum.threads t, q
+                    WHERE $3 <> 'posts' AND t.redirect_to IS NULL AND t.deleted_at IS NULL AND t.search_vector @@ q.query
+                    UNION ALL
+                    SELECT 'post', p.id, p.topic, t.title, t.section_id, p.author, p.created_at,
+                        ts_rank(p.search_vector, q.query), p.content
+                    FROM forum.posts p
+                    INNER JOIN forum.threads t ON t.id = p.topic, q
+                    WHERE $3 <> 'threads' AND t.deleted_at IS NULL AND p.deleted_at IS NULL AND p.search_vector @@ q.query
+                ),
+                ranked AS (
+                    SELECT h.*,
+                        CASE WHEN $4 = 'newest' THEN extract(epoch FROM h.created_at)::float8 ELSE h.rank::float8 END AS sort_key
+                    FROM hits h
+                    WHERE h.section_id IN (
+                            SELECT sa.section_id FROM forum.section_access sa
+                            WHERE sa.user_id = $1 AND sa.can_view)
+                        AND ($5::int8 IS NULL OR h.section_id = $5)
+                        AND ($6::text IS NULL OR h.author = (SELECT id FROM forum.users WHERE lower(name) = lower($6)))
+                        AND ($7::text IS NULL OR EXISTS (
+                            SELECT 1 FROM forum.hashtags ht WHERE ht.topic = h.thread_id AND lower(ht.tag) = lower($7)))
+                        AND ($8::timestamptz IS NULL OR h.created_at >= $8)
+                        AND ($9::timestamptz IS NULL OR h.created_at <= $9)
+                ),
+                page AS (
+                    SELECT * FROM ranked
+                    WHERE $10::float8 IS NULL OR (sort_key, kind, id) < ($10, $11::text, $12::int8)
+                    ORDER BY sort_key DESC, kind DESC, id DESC
+                    LIMIT $13
+                )
+                -- Snippets only for the rows that are returned, ts_headline has to parse the whole text
+                SELECT page.kind as "kind!", page.id as "id!", page.thread_id as "thread_id!", page.title as "title!",
+                    page.section_id as "section_id!", page.author, u.name as "author_name?", page.created_at as "created_at!",
+                    page.rank as "rank!", page.sort_key as "sort_key!",
+                    ts_headline('simple', page.body, q.query,
+                        'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', MaxFragments=2, MaxWords=30, MinWords=10, FragmentDelimiter=" … "') as "snippet!"
+                FROM page
+                CROSS JOIN q
+                LEFT JOIN forum.users u ON u.id = page.author
+                ORDER BY page.sort_key DESC, page.kind DESC, page.id DESC"#,
+            user,
+            search.query,
+            search.scope.to_str(),
+            search.sort.to_str(),
+            search.section_id,
+            search.author,
+            search.tag,
+            search.from,
+            search.to,
+            cursor.map(|c| c.key),
+            cursor.map(|c| c.kind.as_str()),
+            cursor.map(|c| c.id),
+            limit as i64)
+            .fetch_all(&self.pool)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::Fixture;
 
+    async fn reply_tree(db: &Fixture, replies: usize) -> Vec<ReplyRow> {
+        let tree = db.client().get_reply_tree(db.thread, None, None, 10, 5, replies).await;
+        db.clean_up().await;
+        tree.expect("reply tree")
     }
 
-    async fn posts_since(&self, post_id: i64) -> Result<i64, sqlx::Error> {
-        struct Helper {
-            count: Option<i64>,
+    #[tokio::test]
+    #[ignore = "needs the forum schema at DATABASE_URL"]
+    async fn reply_tree_keeps_deleted_posts_above_a_live_reply() {
+        let db = Fixture::new().await;
+
+        // Deleted A -> deleted B -> live C stays, deleted D -> deleted E has nothing left to show
+        let a = db.add_post(None, true).await;
+        let b = db.add_post(Some(a), true).await;
+        let c = db.add_post(Some(b), false).await;
+        let d = db.add_post(None, true).await;
+        db.add_post(Some(d), true).await;
+
+        let shape: Vec<(i64, bool, i32, i64)> = reply_tree(&db, 5).await.iter()
+            .map(|r| (r.id, r.deleted, r.depth, r.reply_count))
+            .collect();
+        assert_eq!(shape, vec![(a, true, 0, 1), (b, true, 1, 1), (c, false, 2, 0)]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the forum schema at DATABASE_URL"]
+    async fn reply_tree_loads_only_the_first_replies_of_each_post() {
+        let db = Fixture::new().await;
+
+        let root = db.add_post(None, false).await;
+        let mut replies = Vec::new();
+        for _ in 0..7 {
+            replies.push(db.add_post(Some(root), false).await);
+        }
+        for _ in 0..4 {
+            db.add_post(Some(replies[0]), false).await;
         }
 
-        let res = sqlx::query_as!(Helper,
-            r#" SELECT COUNT(*) 
-                FROM forum.posts 
-                WHERE created_at > (
-                    SELECT created_at 
-                    FROM forum.posts 
-                    WHERE id = $1)"#, post_id)
-            .fetch_one(&self.pool)
-            .await?;
+        let tree = reply_tree(&db, 3).await;
+        let children = |parent: i64| tree.iter().filter(|r| r.comments == Some(parent)).map(|r| r.id).collect::<Vec<_>>();
+
+        assert_eq!(tree[0].reply_count, 7);
+        assert_eq!(children(root), replies[..3]);
+        assert_eq!(children(replies[0]).len(), 3);
+        assert_eq!(tree.iter().find(|r| r.id == replies[0]).map(|r| r.reply_count), Some(4));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the forum schema at DATABASE_URL"]
+    async fn split_moves_the_opening_post_history_to_the_thread() {
+        let db = Fixture::new().await;
+
+        let first = db.add_post(None, false).await;
+        let reply = db.add_post(Some(first), false).await;
+        sqlx::query("INSERT INTO forum.revisions(post_id, content, editor) VALUES ($1, 'before', $2)")
+            .bind(first).bind(db.user).execute(&db.pool).await.expect("insert revision");
+        sqlx::query("UPDATE forum.posts SET edit_count = 1, modified_at = NOW(), likes = 1 WHERE id = $1")
+            .bind(first).execute(&db.pool).await.expect("edit post");
+        sqlx::query("INSERT INTO forum.post_reactions(post_id, user_id, emoji) VALUES ($1, $2, 'x')")
+            .bind(first).bind(db.user).execute(&db.pool).await.expect("insert reaction");
+
+        let client = db.client();
+        let split = client.split_thread(db.thread, &[first, reply], "split off", db.section, db.user).await;
+        let split = split.ok().flatten();
+
+        let moved: Option<(i64, Option<String>)> = match &split {
+            Some(split) => sqlx::query_as("SELECT COUNT(*), MAX(title) FROM forum.revisions WHERE thread_id = $1")
+                .bind(split.thread_id).fetch_optional(&db.pool).await.expect("revisions"),
+            None => None,
+        };
+        let thread = match &split {
+            Some(split) => client.get_thread_info(split.thread_id).await.ok(),
+            None => None,
+        };
+        db.clean_up().await;
+
+        let split = split.expect("split thread");
+        assert_eq!((split.dropped_likes, split.dropped_reactions), (1, 1));
+        assert_eq!(moved, Some((1, Some("split off".to_string()))));
+
+        let thread = thread.expect("new thread");
+        assert_eq!(thread.edit_count, 1);
+        assert!(thread.modified_at.is_some());
+    }
 
-        Ok(res.count.unwrap_or(-1))
+    #[tokio::test]
+    #[ignore = "needs the forum schema at DATABASE_URL"]
+    async fn edits_that_change_nothing_report_it() {
+        let db = Fixture::new().await;
+        let post = db.add_post(None, false).await;
+        let client = db.client();
+
+        let unchanged = client.update_post(post, "post", "", db.user, None).await;
+        let changed = client.update_post(post, "edited", "", db.user, None).await;
+        let title = client.get_thread_info(db.thread).await.map(|t| t.title).unwrap_or_default();
+        let thread_unchanged = client.update_thread(db.thread, &title, "", "", db.user, None).await;
+        let edit_count = client.get_post(post).await.ok().flatten().map(|p| p.edit_count);
+        db.clean_up().await;
+
+        assert!(!unchanged.expect("update post"));
+        assert!(changed.expect("update post"));
+        assert_eq!(edit_count, Some(1));
+        assert!(!thread_unchanged.expect("update thread"));
     }
 }
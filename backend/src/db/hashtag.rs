@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{TagCount, Thread, TrendingTag};
+
+/// Tags are stored normalized, see `render::normalize_hashtag`, so every lookup here is an exact match.
+/// Only threads in sections the user may view are counted, the same rule as `ForumExt::get_sections`.
+#[async_trait]
+pub trait HashtagExt {
+    async fn get_tag_threads(&self, user: Uuid, tag: &str, page: i32, limit: usize) -> Result<Vec<Thread>, sqlx::Error>;
+    async fn get_popular_tags(&self, user: Uuid, days: Option<u32>, limit: usize) -> Result<Vec<TagCount>, sqlx::Error>;
+    async fn get_trending_tags(&self, user: Uuid, days: u32, limit: usize) -> Result<Vec<TrendingTag>, sqlx::Error>;
+    async fn autocomplete_tags(&self, user: Uuid, prefix: &str, limit: usize) -> Result<Vec<TagCount>, sqlx::Error>;
+    async fn tag_exists(&self, tag: &str) -> Result<bool, sqlx::Error>;
+    async fn merge_tags(&self, from: &str, into: &str) -> Result<u64, sqlx::Error>;
+}
+
+#[async_trait]
+impl HashtagExt for crate::db::DBClient {
+    async fn get_tag_threads(&self, user: Uuid, tag: &str, page: i32, limit: usize) -> Result<Vec<Thread>, sqlx::Error> {
+        let offset = (page as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(Thread,
+            r#" SELECT t.id, t.title, t.created_at, t.content, t.author, t.section_id, t.locked, t.sticky, t.content_html, t.announcement, t.redirect_to, t.modified_at, t.edit_count
+                FROM forum.hashtags h
+                INNER JOIN forum.threads t ON t.id = h.topic
+                WHERE h.tag = $2 AND t.deleted_at IS NULL
+                    AND t.section_id IN (
+                        SELECT sa.section_id FROM forum.section_access sa
+                        WHERE sa.user_id = $1 AND sa.can_view)
+                ORDER BY t.created_at DESC, t.id DESC
+                LIMIT $3
+                OFFSET $4"#,
+            user, tag, limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_popular_tags(&self, user: Uuid, days: Option<u32>, limit: usize) -> Result<Vec<TagCount>, sqlx::Error> {
+        sqlx::query_as!(TagCount,
+            r#" SELECT h.tag, COUNT(*) as "threads!"
+                FROM forum.hashtags h
+                INNER JOIN forum.threads t ON t.id = h.topic
+                WHERE ($2::int4 IS NULL OR t.created_at >= NOW() - make_interval(days => $2))
+                    AND t.deleted_at IS NULL
+                    AND t.section_id IN (
+                        SELECT sa.section_id FROM forum.section_access sa
+                        WHERE sa.user_id = $1 AND sa.can_view)
+                GROUP BY h.tag
+                ORDER BY COUNT(*) DESC, h.tag
+                LIMIT $3"#,
+            user, days.map(|d| d as i32), limit as i64)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Tags gaining the most threads compared to the window before, tags without a recent thread are left out.
+    async fn get_trending_tags(&self, user: Uuid, days: u32, limit: usize) -> Result<Vec<TrendingTag>, sqlx::Error> {
+        sqlx::query_as!(TrendingTag,
+            r#" SELECT c.tag as "tag!", c.threads as "threads!", c.previous as "previous!"
+                FROM (
+                    SELECT h.tag,
+                        COUNT(*) FILTER (WHERE t.created_at >= NOW() - make_interval(days => $2)) AS threads,
+                        COUNT(*) FILTER (WHERE t.created_at < NOW() - make_interval(days => $2)) AS previous
+                    FROM forum.hashtags h
+                    INNER JOIN forum.threads t ON t.id = h.topic
+                    WHERE t.created_at >= NOW() - make_interval(days => $2 * 2)
+                        AND t.deleted_at IS NULL
+                        AND t.section_id IN (
+                            SELECT sa.section_id FROM forum.section_access sa
+                            WHERE sa.user_id = $1 AND sa.can_view)
+                    GROUP BY h.tag) c
+                WHERE c.threads > 0
+                ORDER BY c.threads - c.previous DESC, c.threads DESC, c.tag
+                LIMIT $3"#,
+            user, days as i32, limit as i64)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn autocomplete_tags(&self, user: Uuid, prefix: &str, limit: usize) -> Result<Vec<TagCount>, sqlx::Error> {
+        // `_` is a valid tag character but a LIKE wildcard
+        let pattern = format!("{}%", prefix.replace('_', "\\_"));
+
+        sqlx::query_as!(TagCount,
+            r#" SELECT h.tag, COUNT(*) as "threads!"
+                FROM forum.hashtags h
+                INNER JOIN forum.threads t ON t.id = h.topic
+                WHERE h.tag LIKE $2 AND t.deleted_at IS NULL
+                    AND t.section_id IN (
+                        SELECT sa.section_id FROM forum.section_access sa
+                        WHERE sa.user_id = $1 AND sa.can_view)
+                GROUP BY h.tag
+                ORDER BY COUNT(*) DESC, h.tag
+                LIMIT $3"#,
+            user, pattern, limit as i64)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn tag_exists(&self, tag: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM forum.hashtags WHERE tag = $1) as "exists!""#, tag)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Moves every use of `from` to `into`, which is also how a tag is renamed. Returns the number of threads that carried `from`.
+    async fn merge_tags(&self, from: &str, into: &str) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Threads carrying both tags keep the one they already have
+        let duplicates = sqlx::query!(
+            r#" DELETE FROM forum.hashtags h
+                WHERE h.tag = $1
+                    AND EXISTS (SELECT 1 FROM forum.hashtags o WHERE o.topic = h.topic AND o.tag = $2 AND o.id <> h.id)"#,
+            from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        let moved = sqlx::query!(
+            r#"UPDATE forum.hashtags SET tag = $2 WHERE tag = $1"#, from, into)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(duplicates.rows_affected() + moved.rows_affected())
+    }
+}
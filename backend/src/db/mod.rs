@@ -1,5 +1,21 @@
 pub mod user;
 pub mod forum;
+pub mod session;
+pub mod two_factor;
+pub mod oidc;
+pub mod throttle;
+pub mod email_change;
+pub mod account;
+pub mod chat;
+pub mod reaction;
+pub mod hashtag;
+pub mod permission;
+pub mod revision;
+pub mod trash;
+pub mod report;
+pub mod audit;
+#[cfg(test)]
+pub mod testing;
 use sqlx::{Pool, Postgres};
 
 #[derive(Debug, Clone)]
@@ -12,4 +28,3 @@ impl DBClient {
         DBClient { pool }
     }
 }
-
@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::models::{OidcState, UserIdentity, UserRole};
+
+#[async_trait]
+pub trait OidcExt {
+    async fn save_oidc_state(&self, state: &str, provider: &str, nonce: &str, code_verifier: &str,
+        link_user: Option<Uuid>, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
+    async fn take_oidc_state(&self, state: &str) -> Result<Option<OidcState>, sqlx::Error>;
+    async fn get_identity(&self, provider: &str, subject: &str) -> Result<Option<UserIdentity>, sqlx::Error>;
+    async fn get_user_identities(&self, user_id: Uuid) -> Result<Vec<UserIdentity>, sqlx::Error>;
+    async fn link_identity(&self, user_id: Uuid, provider: &str, subject: &str, email: Option<&str>) -> Result<(), sqlx::Error>;
+    async fn unlink_identity(&self, user_id: Uuid, provider: &str) -> Result<bool, sqlx::Error>;
+    async fn create_oidc_user(&self, name: &str, email: &str, password: &str, verified: bool,
+        provider: &str, subject: &str) -> Result<Uuid, sqlx::Error>;
+}
+
+#[async_trait]
+impl OidcExt for crate::db::DBClient {
+    async fn save_oidc_state(&self, state: &str, provider: &str, nonce: &str, code_verifier: &str,
+        link_user: Option<Uuid>, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        // Abandoned logins would pile up otherwise
+        sqlx::query!(r#"DELETE FROM forum.oidc_states WHERE expires_at < LOCALTIMESTAMP"#)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.oidc_states(state, provider, nonce, code_verifier, link_user, created_at, expires_at)
+                VALUES ($1, $2, $3, $4, $5, LOCALTIMESTAMP, $6)"#,
+            state, provider, nonce, code_verifier, link_user, expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn take_oidc_state(&self, state: &str) -> Result<Option<OidcState>, sqlx::Error> {
+        sqlx::query_as!(OidcState,
+            r#" DELETE FROM forum.oidc_states WHERE state = $1
+                RETURNING state, provider, nonce, code_verifier, link_user, expires_at"#, state)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_identity(&self, provider: &str, subject: &str) -> Result<Option<UserIdentity>, sqlx::Error> {
+        sqlx::query_as!(UserIdentity,
+            r#" SELECT id, user_id, provider, subject, email, linked_at
+                FROM forum.user_identities
+                WHERE provider = $1 AND subject = $2"#, provider, subject)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_user_identities(&self, user_id: Uuid) -> Result<Vec<UserIdentity>, sqlx::Error> {
+        sqlx::query_as!(UserIdentity,
+            r#" SELECT id, user_id, provider, subject, email, linked_at
+                FROM forum.user_identities
+                WHERE user_id = $1
+                ORDER BY linked_at"#, user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn link_identity(&self, user_id: Uuid, provider: &str, subject: &str, email: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" INSERT INTO forum.user_identities(user_id, provider, subject, email, linked_at)
+                VALUES ($1, $2, $3, $4, LOCALTIMESTAMP)"#,
+            user_id, provider, subject, email)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unlink_identity(&self, user_id: Uuid, provider: &str) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" DELETE FROM forum.user_identities
+                WHERE user_id = $1 AND provider = $2"#, user_id, provider)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn create_oidc_user(&self, name: &str, email: &str, password: &str, verified: bool,
+        provider: &str, subject: &str) -> Result<Uuid, sqlx::Error> {
+        struct Helper {
+            id: Uuid,
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let user = sqlx::query_as!(Helper,
+            r#" INSERT INTO forum.users
+                (name, email, password, verified, role)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id"#,
+            name, email, password, verified, UserRole::User as UserRole)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.user_identities(user_id, provider, subject, email, linked_at)
+                VALUES ($1, $2, $3, $4, LOCALTIMESTAMP)"#,
+            user.id, provider, subject, email)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(user.id)
+    }
+}
@@ -0,0 +1,234 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{GroupSectionRights, RoleSectionRights, SectionModerator, SectionPermissions, SectionRights, UserGroup, UserGroupMember, UserRole};
+
+#[async_trait]
+pub trait PermissionExt {
+    async fn get_section_permissions(&self, user_id: Uuid, section_id: Option<i64>, thread_id: Option<i64>, post_id: Option<i64>) -> Result<Option<SectionPermissions>, sqlx::Error>;
+
+    async fn get_role_rights(&self, s_id: i64) -> Result<Vec<RoleSectionRights>, sqlx::Error>;
+    async fn get_group_rights(&self, s_id: i64) -> Result<Vec<GroupSectionRights>, sqlx::Error>;
+    async fn set_role_rights(&self, s_id: i64, role: UserRole, rights: &SectionRights) -> Result<(), sqlx::Error>;
+    async fn remove_role_rights(&self, s_id: i64, role: UserRole) -> Result<bool, sqlx::Error>;
+    async fn set_group_rights(&self, s_id: i64, group_id: i32, rights: &SectionRights) -> Result<(), sqlx::Error>;
+    async fn remove_group_rights(&self, s_id: i64, group_id: i32) -> Result<bool, sqlx::Error>;
+
+    async fn create_group(&self, name: &str, description: Option<&str>) -> Result<i32, sqlx::Error>;
+    async fn get_groups(&self) -> Result<Vec<UserGroup>, sqlx::Error>;
+    async fn get_group(&self, group_id: i32) -> Result<Option<UserGroup>, sqlx::Error>;
+    async fn delete_group(&self, group_id: i32) -> Result<bool, sqlx::Error>;
+    async fn add_group_member(&self, group_id: i32, user_id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn remove_group_member(&self, group_id: i32, user_id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn get_group_members(&self, group_id: i32, page: u32, limit: usize) -> Result<Vec<UserGroupMember>, sqlx::Error>;
+
+    async fn get_section_moderators(&self, s_id: i64) -> Result<Vec<SectionModerator>, sqlx::Error>;
+    async fn add_section_moderator(&self, s_id: i64, user_id: Uuid, added_by: Uuid) -> Result<bool, sqlx::Error>;
+    async fn remove_section_moderator(&self, s_id: i64, user_id: Uuid) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl PermissionExt for crate::db::DBClient {
+    /// Rights of the user in the section given directly or through one of its threads or posts.
+    /// `None` when there is no such section, thread or post or it is in the trash, a section without any entry for the user grants nothing.
+    async fn get_section_permissions(&self, user_id: Uuid, section_id: Option<i64>, thread_id: Option<i64>, post_id: Option<i64>) -> Result<Option<SectionPermissions>, sqlx::Error> {
+        sqlx::query_as!(SectionPermissions,
+            r#" SELECT s.id as section_id,
+                    COALESCE(a.can_view, false) as "can_view!",
+                    COALESCE(a.can_create_thread, false) as "can_create_thread!",
+                    COALESCE(a.can_reply, false) as "can_reply!",
+                    COALESCE(a.can_moderate, false) as "can_moderate!"
+                FROM forum.sections s
+                LEFT JOIN forum.section_access a ON a.section_id = s.id AND a.user_id = $1
+                WHERE s.id = COALESCE(
+                    $2,
+                    (SELECT section_id FROM forum.threads WHERE id = $3 AND deleted_at IS NULL),
+                    (SELECT t.section_id FROM forum.posts p
+                     INNER JOIN forum.threads t ON t.id = p.topic
+                     WHERE p.id = $4 AND p.deleted_at IS NULL AND t.deleted_at IS NULL))"#,
+            user_id, section_id, thread_id, post_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_role_rights(&self, s_id: i64) -> Result<Vec<RoleSectionRights>, sqlx::Error> {
+        sqlx::query_as!(RoleSectionRights,
+            r#" SELECT role as "role: UserRole", can_view, can_create_thread, can_reply, can_moderate
+                FROM forum.sections_allowed
+                WHERE section_id = $1
+                ORDER BY role"#, s_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_group_rights(&self, s_id: i64) -> Result<Vec<GroupSectionRights>, sqlx::Error> {
+        sqlx::query_as!(GroupSectionRights,
+            r#" SELECT sg.group_id, g.name as group_name, sg.can_view, sg.can_create_thread, sg.can_reply, sg.can_moderate
+                FROM forum.sections_allowed_groups sg
+                INNER JOIN forum.user_groups g ON g.id = sg.group_id
+                WHERE sg.section_id = $1
+                ORDER BY g.name"#, s_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_role_rights(&self, s_id: i64, role: UserRole, rights: &SectionRights) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" INSERT INTO forum.sections_allowed(section_id, role, can_view, can_create_thread, can_reply, can_moderate)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (section_id, role) DO UPDATE
+                SET can_view = EXCLUDED.can_view,
+                    can_create_thread = EXCLUDED.can_create_thread,
+                    can_reply = EXCLUDED.can_reply,
+                    can_moderate = EXCLUDED.can_moderate"#,
+            s_id, role as UserRole, rights.can_view, rights.can_create_thread, rights.can_reply, rights.can_moderate)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_role_rights(&self, s_id: i64, role: UserRole) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM forum.sections_allowed WHERE section_id = $1 AND role = $2"#,
+            s_id, role as UserRole)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_group_rights(&self, s_id: i64, group_id: i32, rights: &SectionRights) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" INSERT INTO forum.sections_allowed_groups(section_id, group_id, can_view, can_create_thread, can_reply, can_moderate)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (section_id, group_id) DO UPDATE
+                SET can_view = EXCLUDED.can_view,
+                    can_create_thread = EXCLUDED.can_create_thread,
+                    can_reply = EXCLUDED.can_reply,
+                    can_moderate = EXCLUDED.can_moderate"#,
+            s_id, group_id, rights.can_view, rights.can_create_thread, rights.can_reply, rights.can_moderate)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_group_rights(&self, s_id: i64, group_id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM forum.sections_allowed_groups WHERE section_id = $1 AND group_id = $2"#,
+            s_id, group_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_group(&self, name: &str, description: Option<&str>) -> Result<i32, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#" INSERT INTO forum.user_groups(name, description, created_at)
+                VALUES ($1, $2, NOW())
+                RETURNING id"#, name, description)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_groups(&self) -> Result<Vec<UserGroup>, sqlx::Error> {
+        sqlx::query_as!(UserGroup,
+            r#" SELECT g.id, g.name, g.description, g.created_at,
+                    (SELECT COUNT(*) FROM forum.user_group_members m WHERE m.group_id = g.id) as "member_count!"
+                FROM forum.user_groups g
+                ORDER BY g.name"#)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_group(&self, group_id: i32) -> Result<Option<UserGroup>, sqlx::Error> {
+        sqlx::query_as!(UserGroup,
+            r#" SELECT g.id, g.name, g.description, g.created_at,
+                    (SELECT COUNT(*) FROM forum.user_group_members m WHERE m.group_id = g.id) as "member_count!"
+                FROM forum.user_groups g
+                WHERE g.id = $1"#, group_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Memberships and section entries of the group go with it.
+    async fn delete_group(&self, group_id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM forum.user_groups WHERE id = $1"#, group_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns false when the user already was a member.
+    async fn add_group_member(&self, group_id: i32, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#" INSERT INTO forum.user_group_members(group_id, user_id, added_at)
+                VALUES ($1, $2, NOW())
+                ON CONFLICT DO NOTHING"#, group_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_group_member(&self, group_id: i32, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM forum.user_group_members WHERE group_id = $1 AND user_id = $2"#, group_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_group_members(&self, group_id: i32, page: u32, limit: usize) -> Result<Vec<UserGroupMember>, sqlx::Error> {
+        let offset = (page as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(UserGroupMember,
+            r#" SELECT m.user_id, u.name, u.avatar, m.added_at
+                FROM forum.user_group_members m
+                INNER JOIN forum.users u ON u.id = m.user_id
+                WHERE m.group_id = $1
+                ORDER BY u.name
+                LIMIT $2
+                OFFSET $3"#,
+            group_id, limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_section_moderators(&self, s_id: i64) -> Result<Vec<SectionModerator>, sqlx::Error> {
+        sqlx::query_as!(SectionModerator,
+            r#" SELECT sm.user_id, u.name, u.avatar, sm.added_by, sm.added_at
+                FROM forum.section_moderators sm
+                INNER JOIN forum.users u ON u.id = sm.user_id
+                WHERE sm.section_id = $1
+                ORDER BY u.name"#, s_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Returns false when the user already moderated the section.
+    async fn add_section_moderator(&self, s_id: i64, user_id: Uuid, added_by: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#" INSERT INTO forum.section_moderators(section_id, user_id, added_by, added_at)
+                VALUES ($1, $2, $3, NOW())
+                ON CONFLICT DO NOTHING"#, s_id, user_id, added_by)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_section_moderator(&self, s_id: i64, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM forum.section_moderators WHERE section_id = $1 AND user_id = $2"#, s_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
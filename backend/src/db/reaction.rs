@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{ReactionCount, Reactor};
+
+#[async_trait]
+pub trait ReactionExt {
+    async fn add_reaction(&self, post_id: i64, user_id: Uuid, emoji: &str) -> Result<bool, sqlx::Error>;
+    async fn remove_reaction(&self, post_id: i64, user_id: Uuid, emoji: &str) -> Result<bool, sqlx::Error>;
+    async fn get_reaction_counts(&self, user_id: Uuid, post_id: Option<i64>, thread_id: Option<i64>) -> Result<Vec<ReactionCount>, sqlx::Error>;
+    async fn get_reactors(&self, post_id: i64, emoji: Option<&str>, page: u32, limit: usize) -> Result<Vec<Reactor>, sqlx::Error>;
+    async fn get_received_likes(&self, user_id: Uuid) -> Result<i64, sqlx::Error>;
+}
+
+#[async_trait]
+impl ReactionExt for crate::db::DBClient {
+    /// Returns false when the user had already reacted with this emoji.
+    async fn add_reaction(&self, post_id: i64, user_id: Uuid, emoji: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#" INSERT INTO forum.post_reactions(post_id, user_id, emoji, created_at)
+                VALUES ($1, $2, $3, LOCALTIMESTAMP)
+                ON CONFLICT DO NOTHING"#,
+            post_id, user_id, emoji)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        // posts.likes and users.received_likes are caches of the reaction rows
+        sqlx::query!(
+            r#" WITH p AS (
+                    UPDATE forum.posts SET likes = likes + 1
+                    WHERE id = $1
+                    RETURNING author)
+                UPDATE forum.users u SET received_likes = received_likes + 1
+                FROM p WHERE u.id = p.author"#, post_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn remove_reaction(&self, post_id: i64, user_id: Uuid, emoji: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#" DELETE FROM forum.post_reactions
+                WHERE post_id = $1 AND user_id = $2 AND emoji = $3"#,
+            post_id, user_id, emoji)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#" WITH p AS (
+                    UPDATE forum.posts SET likes = GREATEST(likes - 1, 0)
+                    WHERE id = $1
+                    RETURNING author)
+                UPDATE forum.users u SET received_likes = GREATEST(received_likes - 1, 0)
+                FROM p WHERE u.id = p.author"#, post_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Counts per post and emoji, for one post or for every post of a thread.
+    async fn get_reaction_counts(&self, user_id: Uuid, post_id: Option<i64>, thread_id: Option<i64>) -> Result<Vec<ReactionCount>, sqlx::Error> {
+        sqlx::query_as!(ReactionCount,
+            r#" SELECT r.post_id, r.emoji, COUNT(*) as "count!", bool_or(r.user_id = $1) as "reacted!"
+                FROM forum.post_reactions r
+                INNER JOIN forum.posts p ON p.id = r.post_id
+                WHERE ($2::int8 IS NULL OR r.post_id = $2)
+                    AND ($3::int8 IS NULL OR p.topic = $3)
+                GROUP BY r.post_id, r.emoji
+                ORDER BY r.post_id, COUNT(*) DESC, MIN(r.created_at)"#,
+            user_id, post_id, thread_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_reactors(&self, post_id: i64, emoji: Option<&str>, page: u32, limit: usize) -> Result<Vec<Reactor>, sqlx::Error> {
+        let offset = (page as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(Reactor,
+            r#" SELECT r.user_id, u.name, u.avatar, r.emoji, r.created_at
+                FROM forum.post_reactions r
+                INNER JOIN forum.users u ON u.id = r.user_id
+                WHERE r.post_id = $1 AND ($2::varchar IS NULL OR r.emoji = $2)
+                ORDER BY r.created_at, u.name
+                LIMIT $3
+                OFFSET $4"#,
+            post_id, emoji, limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_received_likes(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT received_likes FROM forum.users WHERE id = $1"#, user_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+}
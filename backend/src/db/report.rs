@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::db::user::insert_warning;
+use crate::models::{Report, ReportEntry, ReportFilter, ReportFiled, ReportReason, ReportStatus, ReportTarget, ReportedContent};
+
+/// Reports about the same target are collected in one open report, each user can add to it once.
+/// Hiding reuses what each target already has: threads and posts go to the trash, chat and private messages get `hidden_at`.
+/// The trash purge leaves hidden threads and posts alone while their report is open.
+#[async_trait]
+pub trait ReportExt {
+    async fn create_report(&self, target: &ReportedContent, reporter: Uuid, reason: ReportReason, comment: Option<&str>, hide_threshold: u32) -> Result<Option<ReportFiled>, sqlx::Error>;
+    async fn get_reports(&self, report_id: Option<i64>, filter: &ReportFilter, page: u32, limit: usize) -> Result<Vec<Report>, sqlx::Error>;
+    async fn get_report(&self, report_id: i64) -> Result<Option<Report>, sqlx::Error>;
+    async fn get_report_entries(&self, report_id: i64) -> Result<Vec<ReportEntry>, sqlx::Error>;
+    async fn claim_report(&self, report_id: i64, user_id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn release_report(&self, report_id: i64, user_id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn close_report(&self, report_id: i64, status: ReportStatus, closed_by: Uuid, resolution: Option<&str>, warn: Option<(Option<&str>, Option<i32>)>) -> Result<bool, sqlx::Error>;
+}
+
+/// Hides the target, returns false when it was already gone or hidden.
+async fn hide_target(tx: &mut sqlx::PgConnection, target_type: ReportTarget, target_id: i64) -> Result<bool, sqlx::Error> {
+    let res = match target_type {
+        ReportTarget::Post => sqlx::query!(
+            r#"UPDATE forum.posts SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL"#, target_id)
+            .execute(&mut *tx)
+            .await?,
+        ReportTarget::Thread => sqlx::query!(
+            r#"UPDATE forum.threads SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL"#, target_id)
+            .execute(&mut *tx)
+            .await?,
+        ReportTarget::Chat => sqlx::query!(
+            r#"UPDATE forum.chat_posts SET hidden_at = NOW() WHERE id = $1 AND hidden_at IS NULL"#, target_id as i32)
+            .execute(&mut *tx)
+            .await?,
+        ReportTarget::Pm => sqlx::query!(
+            r#"UPDATE forum.private_messages SET hidden_at = NOW() WHERE id = $1 AND hidden_at IS NULL"#, target_id)
+            .execute(&mut *tx)
+            .await?,
+    };
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// Shows the target again, unless it was hidden or deleted since by someone else.
+/// The target and the report got the same `NOW()` when it was hidden, any later hide or delete has another one.
+async fn unhide_target(tx: &mut sqlx::PgConnection, target_type: ReportTarget, target_id: i64, hidden_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    match target_type {
+        ReportTarget::Post => sqlx::query!(
+            r#"UPDATE forum.posts SET deleted_at = NULL WHERE id = $1 AND deleted_at = $2 AND deleted_by IS NULL"#, target_id, hidden_at)
+            .execute(&mut *tx)
+            .await?,
+        ReportTarget::Thread => sqlx::query!(
+            r#"UPDATE forum.threads SET deleted_at = NULL WHERE id = $1 AND deleted_at = $2 AND deleted_by IS NULL"#, target_id, hidden_at)
+            .execute(&mut *tx)
+            .await?,
+        ReportTarget::Chat => sqlx::query!(
+            r#"UPDATE forum.chat_posts SET hidden_at = NULL WHERE id = $1 AND hidden_at = $2"#, target_id as i32, hidden_at)
+            .execute(&mut *tx)
+            .await?,
+        ReportTarget::Pm => sqlx::query!(
+            r#"UPDATE forum.private_messages SET hidden_at = NULL WHERE id = $1 AND hidden_at = $2"#, target_id, hidden_at)
+            .execute(&mut *tx)
+            .await?,
+    };
+
+    Ok(())
+}
+
+#[async_trait]
+impl ReportExt for crate::db::DBClient {
+    /// `None` when the user already reported the target. A `hide_threshold` of 0 never hides anything.
+    async fn create_report(&self, target: &ReportedContent, reporter: Uuid, reason: ReportReason, comment: Option<&str>, hide_threshold: u32) -> Result<Option<ReportFiled>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // The no-op update lets the existing open report be returned as well
+        let report = sqlx::query!(
+            r#" INSERT INTO forum.reports(target_type, target_id, target_author, content)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (target_type, target_id) WHERE status = 'open'
+                DO UPDATE SET target_type = EXCLUDED.target_type
+                RETURNING id, hidden_at"#,
+            target.target_type as ReportTarget, target.target_id, target.author, target.content)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let added = sqlx::query!(
+            r#" INSERT INTO forum.report_entries(report_id, reporter, reason, comment)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT DO NOTHING"#,
+            report.id, reporter, reason as ReportReason, comment)
+            .execute(&mut *tx)
+            .await?;
+
+        if added.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let reports = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM forum.report_entries WHERE report_id = $1"#, report.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let mut hidden = false;
+        if hide_threshold > 0 && reports >= hide_threshold as i64 && report.hidden_at.is_none() {
+            hidden = hide_target(&mut tx, target.target_type, target.target_id).await?;
+            if hidden {
+                sqlx::query!(r#"UPDATE forum.reports SET hidden_at = NOW() WHERE id = $1"#, report.id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(Some(ReportFiled { report_id: report.id, hidden }))
+    }
+
+    /// Oldest first, so the queue is worked through in the order things were reported.
+    async fn get_reports(&self, report_id: Option<i64>, filter: &ReportFilter, page: u32, limit: usize) -> Result<Vec<Report>, sqlx::Error> {
+        let offset = (page.max(1) as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(Report,
+            r#" SELECT r.id, r.target_type as "target_type: ReportTarget", r.target_id, r.target_author, a.name as "target_author_name?",
+                    r.content, r.status as "status: ReportStatus", r.created_at,
+                    e.reports as "report_count!", e.reasons as "reasons!: Vec<ReportReason>", e.last_reported_at,
+                    r.claimed_by, c.name as "claimed_by_name?", r.claimed_at, r.resolved_by, r.resolved_at, r.resolution, r.hidden_at
+                FROM forum.reports r
+                CROSS JOIN LATERAL (
+                    SELECT COUNT(*) AS reports,
+                        COALESCE(array_agg(DISTINCT x.reason), '{}') AS reasons,
+                        MAX(x.created_at) AS last_reported_at
+                    FROM forum.report_entries x
+                    WHERE x.report_id = r.id) e
+                LEFT JOIN forum.users a ON a.id = r.target_author
+                LEFT JOIN forum.users c ON c.id = r.claimed_by
+                WHERE ($1::int8 IS NULL OR r.id = $1)
+                    AND ($2::forum.report_status IS NULL OR r.status = $2)
+                    AND ($3::forum.report_target IS NULL OR r.target_type = $3)
+                    AND ($4::forum.report_reason IS NULL OR $4 = ANY(e.reasons))
+                    AND ($5::uuid IS NULL OR r.claimed_by = $5)
+                    AND (NOT $6 OR r.claimed_by IS NULL)
+                ORDER BY r.created_at, r.id
+                LIMIT $7
+                OFFSET $8"#,
+            report_id, filter.status as Option<ReportStatus>, filter.target_type as Option<ReportTarget>,
+            filter.reason as Option<ReportReason>, filter.claimed_by, filter.unclaimed, limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_report(&self, report_id: i64) -> Result<Option<Report>, sqlx::Error> {
+        Ok(self.get_reports(Some(report_id), &ReportFilter::default(), 1, 1).await?.pop())
+    }
+
+    async fn get_report_entries(&self, report_id: i64) -> Result<Vec<ReportEntry>, sqlx::Error> {
+        sqlx::query_as!(ReportEntry,
+            r#" SELECT e.reporter, u.name as reporter_name, e.reason as "reason: ReportReason", e.comment, e.created_at
+                FROM forum.report_entries e
+                INNER JOIN forum.users u ON u.id = e.reporter
+                WHERE e.report_id = $1
+                ORDER BY e.created_at"#, report_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// False when the report is closed or someone else claimed it first.
+    async fn claim_report(&self, report_id: i64, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" UPDATE forum.reports SET claimed_by = $2, claimed_at = NOW()
+                WHERE id = $1 AND status = 'open' AND (claimed_by IS NULL OR claimed_by = $2)"#, report_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn release_report(&self, report_id: i64, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" UPDATE forum.reports SET claimed_by = NULL, claimed_at = NULL
+                WHERE id = $1 AND status = 'open' AND claimed_by = $2"#, report_id, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Resolves or dismisses an open report, a dismissed one brings back what it hid.
+    /// `warn` is the comment and ban length in days for the target's author, warned in the same transaction.
+    async fn close_report(&self, report_id: i64, status: ReportStatus, closed_by: Uuid, resolution: Option<&str>, warn: Option<(Option<&str>, Option<i32>)>) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let closed = sqlx::query!(
+            r#" UPDATE forum.reports SET status = $2, resolved_by = $3, resolved_at = NOW(), resolution = $4
+                WHERE id = $1 AND status = 'open'
+                RETURNING target_type as "target_type: ReportTarget", target_id, target_author, hidden_at"#,
+            report_id, status as ReportStatus, closed_by, resolution)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let Some(closed) = closed else {
+            return Ok(false);
+        };
+
+        if let (ReportStatus::Dismissed, Some(hidden_at)) = (status, closed.hidden_at) {
+            unhide_target(&mut tx, closed.target_type, closed.target_id, hidden_at).await?;
+        }
+
+        if let (Some((comment, ban)), Some(author)) = (warn, closed.target_author) {
+            insert_warning(&mut tx, author, comment, closed_by, ban).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+}
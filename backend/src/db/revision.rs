@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+
+use crate::models::Revision;
+
+/// Revisions are written by `ForumExt::update_post` and `update_thread`, this only reads them back.
+#[async_trait]
+pub trait RevisionExt {
+    async fn get_post_revisions(&self, post_id: i64) -> Result<Vec<Revision>, sqlx::Error>;
+    async fn get_thread_revisions(&self, thread_id: i64) -> Result<Vec<Revision>, sqlx::Error>;
+    async fn get_revision(&self, rev_id: i64) -> Result<Option<Revision>, sqlx::Error>;
+}
+
+#[async_trait]
+impl RevisionExt for crate::db::DBClient {
+    /// Newest first.
+    async fn get_post_revisions(&self, post_id: i64) -> Result<Vec<Revision>, sqlx::Error> {
+        sqlx::query_as!(Revision,
+            r#" SELECT r.id, r.post_id, r.thread_id, r.title, r.content, r.editor, u.name as "editor_name?", r.edited_at, r.reason
+                FROM forum.revisions r
+                LEFT JOIN forum.users u ON u.id = r.editor
+                WHERE r.post_id = $1
+                ORDER BY r.id DESC"#, post_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Newest first.
+    async fn get_thread_revisions(&self, thread_id: i64) -> Result<Vec<Revision>, sqlx::Error> {
+        sqlx::query_as!(Revision,
+            r#" SELECT r.id, r.post_id, r.thread_id, r.title, r.content, r.editor, u.name as "editor_name?", r.edited_at, r.reason
+                FROM forum.revisions r
+                LEFT JOIN forum.users u ON u.id = r.editor
+                WHERE r.thread_id = $1
+                ORDER BY r.id DESC"#, thread_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_revision(&self, rev_id: i64) -> Result<Option<Revision>, sqlx::Error> {
+        sqlx::query_as!(Revision,
+            r#" SELECT r.id, r.post_id, r.thread_id, r.title, r.content, r.editor, u.name as "editor_name?", r.edited_at, r.reason
+                FROM forum.revisions r
+                LEFT JOIN forum.users u ON u.id = r.editor
+                WHERE r.id = $1"#, rev_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+}
@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::models::Session;
+
+#[async_trait]
+pub trait SessionExt {
+    async fn create_session(&self, user_id: Uuid, refresh_token: &str, expires_at: DateTime<Utc>) -> Result<Session, sqlx::Error>;
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>, sqlx::Error>;
+    async fn get_session_by_token(&self, refresh_token: &str) -> Result<Option<Session>, sqlx::Error>;
+    async fn get_session_by_previous_token(&self, refresh_token: &str) -> Result<Option<Session>, sqlx::Error>;
+    async fn rotate_session(&self, session_id: Uuid, old_token: &str, new_token: &str, expires_at: DateTime<Utc>) -> Result<Option<Session>, sqlx::Error>;
+    async fn revoke_session(&self, session_id: Uuid) -> Result<(), sqlx::Error>;
+    async fn revoke_user_sessions(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+impl SessionExt for crate::db::DBClient {
+    async fn create_session(&self, user_id: Uuid, refresh_token: &str, expires_at: DateTime<Utc>) -> Result<Session, sqlx::Error> {
+        sqlx::query_as!(Session,
+            r#" INSERT INTO forum.sessions(user_id, refresh_token, created_at, last_used_at, expires_at)
+                VALUES ($1, $2, LOCALTIMESTAMP, LOCALTIMESTAMP, $3)
+                RETURNING id, user_id, created_at, last_used_at, expires_at, revoked_at"#,
+            user_id, refresh_token, expires_at)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>, sqlx::Error> {
+        sqlx::query_as!(Session,
+            r#" SELECT id, user_id, created_at, last_used_at, expires_at, revoked_at
+                FROM forum.sessions WHERE id = $1"#, session_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_session_by_token(&self, refresh_token: &str) -> Result<Option<Session>, sqlx::Error> {
+        sqlx::query_as!(Session,
+            r#" SELECT id, user_id, created_at, last_used_at, expires_at, revoked_at
+                FROM forum.sessions WHERE refresh_token = $1"#, refresh_token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_session_by_previous_token(&self, refresh_token: &str) -> Result<Option<Session>, sqlx::Error> {
+        sqlx::query_as!(Session,
+            r#" SELECT id, user_id, created_at, last_used_at, expires_at, revoked_at
+                FROM forum.sessions WHERE previous_token = $1"#, refresh_token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn rotate_session(&self, session_id: Uuid, old_token: &str, new_token: &str, expires_at: DateTime<Utc>) -> Result<Option<Session>, sqlx::Error> {
+        // Matching on the old token makes two concurrent refreshes race for a single winner
+        sqlx::query_as!(Session,
+            r#" UPDATE forum.sessions
+                SET
+                    previous_token = refresh_token,
+                    refresh_token = $3,
+                    last_used_at = LOCALTIMESTAMP,
+                    expires_at = $4
+                WHERE id = $1 AND refresh_token = $2 AND revoked_at IS NULL
+                RETURNING id, user_id, created_at, last_used_at, expires_at, revoked_at"#,
+            session_id, old_token, new_token, expires_at)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn revoke_session(&self, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" UPDATE forum.sessions SET revoked_at = LOCALTIMESTAMP
+                WHERE id = $1 AND revoked_at IS NULL"#, session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" UPDATE forum.sessions SET revoked_at = LOCALTIMESTAMP
+                WHERE user_id = $1 AND revoked_at IS NULL"#, user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Setup for the database tests. They are ignored unless asked for and need the schema at `DATABASE_URL`.
+
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use uuid::Uuid;
+
+use crate::db::DBClient;
+
+/// A user, section and thread of their own, so the tests don't depend on what else is stored.
+pub struct Fixture {
+    pub pool: PgPool,
+    pub user: Uuid,
+    pub section: i64,
+    pub thread: i64,
+}
+
+impl Fixture {
+    pub async fn new() -> Self {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPoolOptions::new().max_connections(1).connect(&url).await.expect("connect");
+
+        let name = format!("forum-test-{}", Uuid::new_v4().simple());
+        let user = sqlx::query_scalar("INSERT INTO forum.users(name, email, password) VALUES ($1, $1, '') RETURNING id")
+            .bind(&name).fetch_one(&pool).await.expect("insert user");
+        let section = sqlx::query_scalar("INSERT INTO forum.sections(name) VALUES ($1) RETURNING id")
+            .bind(&name).fetch_one(&pool).await.expect("insert section");
+        let thread = sqlx::query_scalar("INSERT INTO forum.threads(title, content, author, section_id) VALUES ($1, '', $2, $3) RETURNING id")
+            .bind(&name).bind(user).bind(section).fetch_one(&pool).await.expect("insert thread");
+
+        Fixture { pool, user, section, thread }
+    }
+
+    pub fn client(&self) -> DBClient {
+        DBClient::new(self.pool.clone())
+    }
+
+    pub async fn add_post(&self, parent: Option<i64>, deleted: bool) -> i64 {
+        sqlx::query_scalar(
+            r#" INSERT INTO forum.posts(content, author, topic, comments, deleted_at)
+                VALUES ('post', $1, $2, $3, CASE WHEN $4 THEN NOW() END)
+                RETURNING id"#)
+            .bind(self.user).bind(self.thread).bind(parent).bind(deleted)
+            .fetch_one(&self.pool)
+            .await
+            .expect("insert post")
+    }
+
+    /// Removes everything the test stored, call it before asserting so a failure leaves nothing behind.
+    pub async fn clean_up(&self) {
+        for cleanup in [
+            "DELETE FROM forum.revisions WHERE thread_id IN (SELECT id FROM forum.threads WHERE section_id = $1)",
+            "DELETE FROM forum.posts WHERE topic IN (SELECT id FROM forum.threads WHERE section_id = $1)",
+            "DELETE FROM forum.threads WHERE section_id = $1",
+            "DELETE FROM forum.sections WHERE id = $1",
+        ] {
+            sqlx::query(cleanup).bind(self.section).execute(&self.pool).await.expect("clean up");
+        }
+        for cleanup in [
+            "DELETE FROM forum.report_entries WHERE reporter = $1",
+            "DELETE FROM forum.reports WHERE target_author = $1",
+            "DELETE FROM forum.user_warning WHERE user_id = $1",
+            "DELETE FROM forum.users WHERE id = $1",
+        ] {
+            sqlx::query(cleanup).bind(self.user).execute(&self.pool).await.expect("clean up");
+        }
+    }
+}
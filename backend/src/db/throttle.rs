@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::models::LoginThrottle;
+
+#[async_trait]
+pub trait ThrottleExt {
+    async fn get_throttles(&self, keys: &[String]) -> Result<Vec<LoginThrottle>, sqlx::Error>;
+    async fn record_failure(&self, key: &str) -> Result<LoginThrottle, sqlx::Error>;
+    async fn lock_key(&self, key: &str, until: DateTime<Utc>) -> Result<(), sqlx::Error>;
+    async fn clear_throttle(&self, key: &str) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl ThrottleExt for crate::db::DBClient {
+    async fn get_throttles(&self, keys: &[String]) -> Result<Vec<LoginThrottle>, sqlx::Error> {
+        sqlx::query_as!(LoginThrottle,
+            r#" SELECT key, failures, last_failure, locked_until
+                FROM forum.login_throttle
+                WHERE key = ANY($1)"#, keys)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn record_failure(&self, key: &str) -> Result<LoginThrottle, sqlx::Error> {
+        // Failures older than a day no longer count, and an expired lock starts a fresh count
+        sqlx::query_as!(LoginThrottle,
+            r#" INSERT INTO forum.login_throttle(key, failures, last_failure)
+                VALUES ($1, 1, LOCALTIMESTAMP)
+                ON CONFLICT (key) DO UPDATE
+                SET failures = CASE
+                        WHEN forum.login_throttle.last_failure < LOCALTIMESTAMP - interval '1 day'
+                            OR forum.login_throttle.locked_until < LOCALTIMESTAMP THEN 1
+                        ELSE forum.login_throttle.failures + 1
+                    END,
+                    locked_until = CASE
+                        WHEN forum.login_throttle.locked_until < LOCALTIMESTAMP THEN NULL
+                        ELSE forum.login_throttle.locked_until
+                    END,
+                    last_failure = LOCALTIMESTAMP
+                RETURNING key, failures, last_failure, locked_until"#, key)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn lock_key(&self, key: &str, until: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#" UPDATE forum.login_throttle SET locked_until = $2
+                WHERE key = $1"#, key, until)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_throttle(&self, key: &str) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(r#"DELETE FROM forum.login_throttle WHERE key = $1"#, key)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,178 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{TrashItem, TrashKind};
+
+/// Threads and posts land here through `ForumExt::delete_thread` and `delete_post`.
+/// A post in a deleted thread is not listed on its own, it comes back with the thread.
+#[async_trait]
+pub trait TrashExt {
+    async fn get_trash(&self, user: Uuid, section: Option<i64>, kind: Option<TrashKind>, page: u32, limit: usize) -> Result<Vec<TrashItem>, sqlx::Error>;
+    async fn get_trashed_section(&self, kind: TrashKind, id: i64) -> Result<Option<i64>, sqlx::Error>;
+    async fn restore_thread(&self, thread_id: i64) -> Result<bool, sqlx::Error>;
+    async fn restore_post(&self, post_id: i64) -> Result<bool, sqlx::Error>;
+    async fn purge_trash(&self, retention_days: u32) -> Result<u64, sqlx::Error>;
+}
+
+#[async_trait]
+impl TrashExt for crate::db::DBClient {
+    /// Newest deletions first, only from sections the user moderates.
+    async fn get_trash(&self, user: Uuid, section: Option<i64>, kind: Option<TrashKind>, page: u32, limit: usize) -> Result<Vec<TrashItem>, sqlx::Error> {
+        let offset = (page.max(1) as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(TrashItem,
+            r#" SELECT x.kind as "kind!: TrashKind", x.id as "id!", x.thread_id as "thread_id!", x.section_id as "section_id!",
+                    x.title as "title!", x.content as "content!", x.author, a.name as "author_name?", x.created_at as "created_at!",
+                    x.deleted_at as "deleted_at!", x.deleted_by, d.name as "deleted_by_name?"
+                FROM (
+                    SELECT 'thread' AS kind, t.id, t.id AS thread_id, t.section_id, t.title, t.content, t.author,
+                        t.created_at, t.deleted_at, t.deleted_by
+                    FROM forum.threads t
+                    WHERE t.deleted_at IS NOT NULL
+                    UNION ALL
+                    SELECT 'post', p.id, p.topic, t.section_id, t.title, p.content, p.author,
+                        p.created_at, p.deleted_at, p.deleted_by
+                    FROM forum.posts p
+                    INNER JOIN forum.threads t ON t.id = p.topic
+                    WHERE p.deleted_at IS NOT NULL AND t.deleted_at IS NULL) x
+                LEFT JOIN forum.users a ON a.id = x.author
+                LEFT JOIN forum.users d ON d.id = x.deleted_by
+                WHERE ($2::int8 IS NULL OR x.section_id = $2)
+                    AND ($3::text IS NULL OR x.kind = $3)
+                    AND x.section_id IN (
+                        SELECT sa.section_id FROM forum.section_access sa
+                        WHERE sa.user_id = $1 AND sa.can_moderate)
+                ORDER BY x.deleted_at DESC, x.id DESC
+                LIMIT $4
+                OFFSET $5"#,
+            user, section, kind.map(|k| k.to_str()), limit as i64, offset)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Section of a thread or post that is in the trash, `None` when it isn't there.
+    async fn get_trashed_section(&self, kind: TrashKind, id: i64) -> Result<Option<i64>, sqlx::Error> {
+        match kind {
+            TrashKind::Thread => sqlx::query_scalar!(
+                r#"SELECT section_id FROM forum.threads WHERE id = $1 AND deleted_at IS NOT NULL"#, id)
+                .fetch_optional(&self.pool)
+                .await,
+            TrashKind::Post => sqlx::query_scalar!(
+                r#" SELECT t.section_id FROM forum.posts p
+                    INNER JOIN forum.threads t ON t.id = p.topic
+                    WHERE p.id = $1 AND p.deleted_at IS NOT NULL"#, id)
+                .fetch_optional(&self.pool)
+                .await,
+        }
+    }
+
+    async fn restore_thread(&self, thread_id: i64) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" UPDATE forum.threads SET deleted_at = NULL, deleted_by = NULL
+                WHERE id = $1 AND deleted_at IS NOT NULL"#, thread_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn restore_post(&self, post_id: i64) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" UPDATE forum.posts SET deleted_at = NULL, deleted_by = NULL
+                WHERE id = $1 AND deleted_at IS NOT NULL"#, post_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Deletes for good what has been in the trash longer than `retention_days`, returns how many threads and posts went.
+    /// Authors lose the likes those posts brought them. A post still answered by a reply stays as its placeholder.
+    async fn purge_trash(&self, retention_days: u32) -> Result<u64, sqlx::Error> {
+        let days = retention_days as i32;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#" WITH l AS (
+                    SELECT p.author, SUM(p.likes) AS likes
+                    FROM forum.posts p
+                    INNER JOIN forum.threads t ON t.id = p.topic
+                    WHERE t.deleted_at < NOW() - make_interval(days => $1)
+                        AND NOT EXISTS (SELECT 1 FROM forum.reports r
+                            WHERE r.status = 'open' AND r.hidden_at IS NOT NULL AND r.target_type = 'thread' AND r.target_id = t.id)
+                    GROUP BY p.author)
+                UPDATE forum.users u SET received_likes = GREATEST(received_likes - l.likes, 0)
+                FROM l WHERE u.id = l.author"#, days)
+            .execute(&mut *tx)
+            .await?;
+
+        // Posts go with their thread through tr_delete_threads_posts. What an open report hid waits for
+        // the report, dismissing it brings the content back.
+        let mut purged = sqlx::query!(
+            r#" DELETE FROM forum.threads t
+                WHERE t.deleted_at < NOW() - make_interval(days => $1)
+                    AND NOT EXISTS (SELECT 1 FROM forum.reports r
+                        WHERE r.status = 'open' AND r.hidden_at IS NOT NULL AND r.target_type = 'thread' AND r.target_id = t.id)"#, days)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        // Replies have to go before the post they answer, so each round takes one more level of a deleted branch
+        loop {
+            let posts = sqlx::query_scalar!(
+                r#" WITH d AS (
+                        DELETE FROM forum.posts p
+                        WHERE p.deleted_at < NOW() - make_interval(days => $1)
+                            AND NOT EXISTS (SELECT 1 FROM forum.posts r WHERE r.comments = p.id)
+                            AND NOT EXISTS (SELECT 1 FROM forum.reports r
+                                WHERE r.status = 'open' AND r.hidden_at IS NOT NULL AND r.target_type = 'post' AND r.target_id = p.id)
+                        RETURNING p.author, p.likes),
+                    l AS (
+                        UPDATE forum.users u SET received_likes = GREATEST(received_likes - l.likes, 0)
+                        FROM (SELECT author, SUM(likes) AS likes FROM d GROUP BY author) l
+                        WHERE u.id = l.author)
+                    SELECT COUNT(*) as "count!" FROM d"#, days)
+                .fetch_one(&mut *tx)
+                .await?;
+
+            if posts == 0 {
+                break;
+            }
+            purged += posts as u64;
+        }
+
+        tx.commit().await?;
+
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::Fixture;
+
+    #[tokio::test]
+    #[ignore = "needs the forum schema at DATABASE_URL"]
+    async fn purge_waits_for_open_reports() {
+        let db = Fixture::new().await;
+        let hidden = db.add_post(None, false).await;
+        let trashed = db.add_post(None, false).await;
+
+        // Older than any retention, so other rows in the trash aren't touched
+        sqlx::query("UPDATE forum.posts SET deleted_at = NOW() - interval '200 years' WHERE id = ANY($1)")
+            .bind(vec![hidden, trashed]).execute(&db.pool).await.expect("trash posts");
+        sqlx::query(
+            r#" INSERT INTO forum.reports(target_type, target_id, target_author, content, hidden_at)
+                VALUES ('post', $1, $2, 'post', NOW())"#)
+            .bind(hidden).bind(db.user).execute(&db.pool).await.expect("insert report");
+
+        let purged = db.client().purge_trash(365 * 150).await;
+        let left: Vec<i64> = sqlx::query_scalar("SELECT id FROM forum.posts WHERE id = ANY($1)")
+            .bind(vec![hidden, trashed]).fetch_all(&db.pool).await.expect("posts left");
+        db.clean_up().await;
+
+        assert_eq!(purged.expect("purge"), 1);
+        assert_eq!(left, vec![hidden]);
+    }
+}
@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{RecoveryCode, UserTotp};
+
+#[async_trait]
+pub trait TwoFactorExt {
+    async fn get_totp(&self, user_id: Uuid) -> Result<Option<UserTotp>, sqlx::Error>;
+    async fn save_totp_secret(&self, user_id: Uuid, secret: &str) -> Result<(), sqlx::Error>;
+    async fn enable_totp(&self, user_id: Uuid, step: i64, code_hashes: &[String]) -> Result<(), sqlx::Error>;
+    async fn disable_totp(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
+    async fn use_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool, sqlx::Error>;
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<(), sqlx::Error>;
+    async fn get_recovery_codes(&self, user_id: Uuid) -> Result<Vec<RecoveryCode>, sqlx::Error>;
+    async fn use_recovery_code(&self, code_id: i64) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl TwoFactorExt for crate::db::DBClient {
+    async fn get_totp(&self, user_id: Uuid) -> Result<Option<UserTotp>, sqlx::Error> {
+        sqlx::query_as!(UserTotp,
+            r#" SELECT user_id, secret, enabled, last_used_step, created_at, confirmed_at
+                FROM forum.user_totp WHERE user_id = $1"#, user_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn save_totp_secret(&self, user_id: Uuid, secret: &str) -> Result<(), sqlx::Error> {
+        // An already confirmed secret is never overwritten, it has to be disabled first
+        sqlx::query!(
+            r#" INSERT INTO forum.user_totp(user_id, secret, enabled, created_at)
+                VALUES ($1, $2, false, LOCALTIMESTAMP)
+                ON CONFLICT (user_id) DO UPDATE
+                SET secret = $2, created_at = LOCALTIMESTAMP, last_used_step = NULL
+                WHERE forum.user_totp.enabled = false"#, user_id, secret)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn enable_totp(&self, user_id: Uuid, step: i64, code_hashes: &[String]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#" UPDATE forum.user_totp
+                SET enabled = true, confirmed_at = LOCALTIMESTAMP, last_used_step = $2
+                WHERE user_id = $1"#, user_id, step)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.recovery_codes WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.recovery_codes(user_id, code_hash)
+                SELECT $1, * FROM UNNEST($2::varchar[])"#, user_id, code_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    async fn disable_totp(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(r#"DELETE FROM forum.recovery_codes WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(r#"DELETE FROM forum.user_totp WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    async fn use_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" UPDATE forum.user_totp SET last_used_step = $2
+                WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)"#,
+            user_id, step)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(r#"DELETE FROM forum.recovery_codes WHERE user_id = $1"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#" INSERT INTO forum.recovery_codes(user_id, code_hash)
+                SELECT $1, * FROM UNNEST($2::varchar[])"#, user_id, code_hashes)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    async fn get_recovery_codes(&self, user_id: Uuid) -> Result<Vec<RecoveryCode>, sqlx::Error> {
+        sqlx::query_as!(RecoveryCode,
+            r#" SELECT id, user_id, code_hash, used_at
+                FROM forum.recovery_codes
+                WHERE user_id = $1 AND used_at IS NULL"#, user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn use_recovery_code(&self, code_id: i64) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#" UPDATE forum.recovery_codes SET used_at = LOCALTIMESTAMP
+                WHERE id = $1 AND used_at IS NULL"#, code_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+}
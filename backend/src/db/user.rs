@@ -2,23 +2,24 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::models::{User, UserRole, Thread, Post, UserWarning, PrivateMessage};
+use crate::models::{User, UserRole, Thread, Post, UserWarning, PrivateMessage, ProfileUpdate, ProfileHistory, UserSearch};
 
 #[async_trait]
 pub trait UserExt {
     async fn get_user(&self, user_id: Option<Uuid>, name: Option<&str>, email: Option<&str>, token: Option<&str>) -> Result<Option<User>, sqlx::Error>;
     async fn get_users(&self, page: u32, limit: usize) -> Result<Vec<User>, sqlx::Error>;
     async fn recently_online(&self, since: DateTime<Utc>, page: u32, limit: usize) -> Result<Vec<User>, sqlx::Error>;
+    async fn search_users(&self, search: &UserSearch, page: u32, limit: usize) -> Result<(Vec<User>, i64), sqlx::Error>;
     async fn add_user(&self, name: &str, email: &str, password: &str, verification_token: &str, token_expires_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
-    async fn save_user(&self, name: &str, email: &str, password: &str,  
-        description: Option<&str>, facebook: Option<&str>, x_id: Option<&str>) -> Result<(), sqlx::Error>;
-    async fn update_user_avatar(&self, id: Uuid, avatar: Option<&str>) -> Result<(), sqlx::Error>;
+    async fn update_user_avatar(&self, id: Uuid, avatar: Option<&str>) -> Result<Option<String>, sqlx::Error>;
     async fn get_user_count(&self) -> Result<i64, sqlx::Error>;
-    async fn update_user_name(&self, user_id: Uuid, name: &str) -> Result<(), sqlx::Error>;
-    async fn update_user_role(&self, user_id: Uuid, role: UserRole) -> Result<(), sqlx::Error>;
+    async fn update_user_profile(&self, user_id: Uuid, changed_by: Uuid, update: &ProfileUpdate) -> Result<User, sqlx::Error>;
     async fn update_user_password(&self, user_id: Uuid, password: &str) -> Result<(), sqlx::Error>;
     async fn warn_user(&self, user_id: Uuid, comment: Option<&str>, warned_by: Uuid, ban: Option<i32>) -> Result<(), sqlx::Error>;
     async fn unban_user(&self, user_id: Uuid) -> Result<(), sqlx::Error>;
+    async fn get_ban_reason(&self, user_id: Uuid) -> Result<Option<String>, sqlx::Error>;
+    async fn get_profile_history(&self, user_id: Uuid, page: u32, limit: usize) -> Result<Vec<ProfileHistory>, sqlx::Error>;
+    async fn last_profile_change(&self, user_id: Uuid, field: &str) -> Result<Option<DateTime<Utc>>, sqlx::Error>;
     async fn verifed_token(&self, token: &str) -> Result<(), sqlx::Error>;
     async fn add_verifed_token(&self, user_id: Uuid, token: &str, expires_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
     async fn get_user_posts(&self, user_id: Option<Uuid>, user_name: Option<&str>) -> Result<Vec<Post>, sqlx::Error>;
@@ -26,6 +27,28 @@ pub trait UserExt {
     async fn get_user_warnings(&self, user_id: Uuid, since: Option<DateTime<Utc>>) -> Result<Vec<UserWarning>, sqlx::Error>;
     async fn send_pm(&self, user_id: Uuid, send_to: Uuid, content: &str) -> Result<(), sqlx::Error>;
     async fn get_pms(&self, user: Uuid, page: u32, limit: usize) -> Result<Vec<PrivateMessage>, sqlx::Error>;
+    async fn get_pm(&self, pm_id: i64) -> Result<Option<PrivateMessage>, sqlx::Error>;
+}
+
+/// Records the warning and starts the ban, if any, on the caller's transaction.
+pub(crate) async fn insert_warning(tx: &mut sqlx::PgConnection, user_id: Uuid, comment: Option<&str>, warned_by: Uuid, ban: Option<i32>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO forum.user_warning(user_id,warn_time,comment,warned_by,banned) VALUES($1,LOCALTIMESTAMP,$2,$3,$4)"#,
+        user_id, comment, warned_by, ban.is_some())
+        .execute(&mut *tx)
+        .await?;
+
+    if let Some(days) = ban {
+        let days = sqlx::postgres::types::PgInterval { months: 0, days, microseconds: 0 };
+        sqlx::query!(
+            r#"UPDATE forum.users SET banned_until = LOCALTIMESTAMP + $2::interval
+            WHERE id = $1"#,
+            user_id, days)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    Ok(())
 }
 
 #[async_trait]
@@ -118,36 +141,95 @@ impl UserExt for crate::db::DBClient {
         .await
     }
 
-    async fn save_user(&self, name: &str, email: &str, password: &str,  
-        description: Option<&str>, facebook: Option<&str>, x_id: Option<&str>) -> Result<(), sqlx::Error> {
-        // Example implementation (adjust SQL to your schema):
-        sqlx::query!(
+    async fn search_users(&self, search: &UserSearch, page: u32, limit: usize) -> Result<(Vec<User>, i64), sqlx::Error> {
+        let offset = (page as i64 - 1) * (limit as i64);
+        // Wildcards typed by the admin are matched literally
+        let pattern = search.name.as_ref()
+            .map(|n| n.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+
+        let users = sqlx::query_as!(
+            User,
             r#"
-            UPDATE forum.users
-            SET 
-                email = $2,
-                password = $3,
-                description = $4,
-                facebook = $5,
-                x_id = $6,
-                updated_at = LOCALTIMESTAMP
-            WHERE name = $1
+            SELECT id, name, email, password, verified, created_at, updated_at, verification_token, token_expires_at, 
+                   role as "role: UserRole", description, avatar, facebook, x_id, banned_until, last_online 
+            FROM forum.users 
+            WHERE ($1::text IS NULL OR CASE WHEN $2 THEN name % $1 OR name ILIKE '%' || $3 || '%' ELSE name ILIKE $3 || '%' END)
+              AND ($4::text IS NULL OR lower(email) = lower($4))
+              AND ($5::forum.user_role IS NULL OR role = $5)
+              AND ($6::bool IS NULL OR (banned_until IS NOT NULL AND banned_until > NOW()) = $6)
+              AND ($7::timestamptz IS NULL OR created_at >= $7)
+              AND ($8::timestamptz IS NULL OR created_at <= $8)
+              AND ($9::timestamptz IS NULL OR last_online >= $9)
+              AND ($10::timestamptz IS NULL OR last_online <= $10)
+            ORDER BY
+                CASE WHEN $11 = 'relevance' THEN similarity(name, $1) END DESC,
+                CASE WHEN $11 = 'name' AND NOT $12 THEN name END ASC,
+                CASE WHEN $11 = 'name' AND $12 THEN name END DESC,
+                CASE WHEN $11 = 'created' AND NOT $12 THEN created_at END ASC,
+                CASE WHEN $11 = 'created' AND $12 THEN created_at END DESC,
+                CASE WHEN $11 = 'last_online' AND NOT $12 THEN last_online END ASC NULLS LAST,
+                CASE WHEN $11 = 'last_online' AND $12 THEN last_online END DESC NULLS LAST,
+                id
+            LIMIT $13 
+            OFFSET $14
             "#,
-            name,
-            email,
-            password,
-            description,
-            facebook,
-            x_id)
-        .execute(&self.pool)
+            search.name.as_deref(),
+            search.fuzzy,
+            pattern.as_deref(),
+            search.email.as_deref(),
+            search.role as Option<UserRole>,
+            search.banned,
+            search.registered_from,
+            search.registered_to,
+            search.online_from,
+            search.online_to,
+            search.sort.to_str(),
+            search.descending,
+            limit as i64,
+            offset
+        )
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(())
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM forum.users 
+            WHERE ($1::text IS NULL OR CASE WHEN $2 THEN name % $1 OR name ILIKE '%' || $3 || '%' ELSE name ILIKE $3 || '%' END)
+              AND ($4::text IS NULL OR lower(email) = lower($4))
+              AND ($5::forum.user_role IS NULL OR role = $5)
+              AND ($6::bool IS NULL OR (banned_until IS NOT NULL AND banned_until > NOW()) = $6)
+              AND ($7::timestamptz IS NULL OR created_at >= $7)
+              AND ($8::timestamptz IS NULL OR created_at <= $8)
+              AND ($9::timestamptz IS NULL OR last_online >= $9)
+              AND ($10::timestamptz IS NULL OR last_online <= $10)
+            "#,
+            search.name.as_deref(),
+            search.fuzzy,
+            pattern.as_deref(),
+            search.email.as_deref(),
+            search.role as Option<UserRole>,
+            search.banned,
+            search.registered_from,
+            search.registered_to,
+            search.online_from,
+            search.online_to
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((users, total))
     }
 
-    async fn update_user_avatar(&self, id: Uuid, avatar: Option<&str>) -> Result<(), sqlx::Error> { 
-        sqlx::query_as!(
-            User,
+    async fn update_user_avatar(&self, id: Uuid, avatar: Option<&str>) -> Result<Option<String>, sqlx::Error> { 
+        let mut tx = self.pool.begin().await?;
+
+        let previous = sqlx::query_scalar!(
+            r#"SELECT avatar FROM forum.users WHERE id = $1 FOR UPDATE"#,
+            id
+        ).fetch_one(&mut *tx).await?;
+
+        sqlx::query!(
             r#"
             UPDATE forum.users
             SET 
@@ -157,10 +239,20 @@ impl UserExt for crate::db::DBClient {
             "#,
             id,
             avatar)
-        .fetch_one(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(())
+        sqlx::query!(
+            r#"INSERT INTO forum.profile_history(user_id, changed_by, field, old_value, new_value) VALUES($1, $1, 'avatar', $2, $3)"#,
+            id,
+            previous,
+            avatar)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(previous)
     }
 
     async fn get_user_count(&self) -> Result<i64, sqlx::Error> {
@@ -195,45 +287,98 @@ impl UserExt for crate::db::DBClient {
         Ok(())
     }
 
-    async fn update_user_name(&self, id: Uuid, name: &str) -> Result<(), sqlx::Error> { 
-        sqlx::query_as!(
+    async fn update_user_profile(&self, user_id: Uuid, changed_by: Uuid, update: &ProfileUpdate) -> Result<User, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query_as!(
+            User,
+            r#"SELECT id, name, email, password, verified, created_at, updated_at, verification_token, token_expires_at, 
+             role as "role: UserRole", description, avatar, facebook, x_id, banned_until, last_online 
+            FROM forum.users WHERE id = $1 FOR UPDATE"#,
+            user_id
+        ).fetch_one(&mut *tx).await?;
+
+        let mut fields: Vec<String> = Vec::new();
+        let mut old_values: Vec<Option<String>> = Vec::new();
+        let mut new_values: Vec<Option<String>> = Vec::new();
+
+        let mut record = |field: &str, old: Option<String>, new: Option<String>| {
+            if old != new {
+                fields.push(field.to_string());
+                old_values.push(old);
+                new_values.push(new);
+            }
+        };
+
+        if let Some(name) = &update.name {
+            record("name", Some(current.name.clone()), Some(name.clone()));
+        }
+        if let Some(role) = update.role {
+            record("role", Some(current.role.to_str()), Some(role.to_str()));
+        }
+        if let Some(description) = &update.description {
+            record("description", current.description.clone(), description.clone());
+        }
+        if let Some(facebook) = &update.facebook {
+            record("facebook", current.facebook.clone(), facebook.clone());
+        }
+        if let Some(x_id) = &update.x_id {
+            record("x_id", current.x_id.clone(), x_id.clone());
+        }
+
+        if fields.is_empty() {
+            return Ok(current);
+        }
+
+        let user = sqlx::query_as!(
             User,
             r#"
             UPDATE forum.users
             SET 
-                name = $2,
+                name = COALESCE($2, name),
+                role = COALESCE($3, role),
+                description = CASE WHEN $4 THEN $5 ELSE description END,
+                facebook = CASE WHEN $6 THEN $7 ELSE facebook END,
+                x_id = CASE WHEN $8 THEN $9 ELSE x_id END,
                 updated_at = LOCALTIMESTAMP
             WHERE id = $1
+            RETURNING id, name, email, password, verified, created_at, updated_at, verification_token, token_expires_at, 
+             role as "role: UserRole", description, avatar, facebook, x_id, banned_until, last_online
             "#,
-            id,
-            name)
-        .execute(&self.pool)
+            user_id,
+            update.name.as_deref(),
+            update.role as Option<UserRole>,
+            update.description.is_some(),
+            update.description.clone().flatten(),
+            update.facebook.is_some(),
+            update.facebook.clone().flatten(),
+            update.x_id.is_some(),
+            update.x_id.clone().flatten())
+        .fetch_one(&mut *tx)
         .await?;
 
-        Ok(())
-    }
-
-    async fn update_user_role(&self, user_id: Uuid, role: UserRole) -> Result<(), sqlx::Error> {
-        sqlx::query_as!(
-            User,
+        sqlx::query!(
             r#"
-            UPDATE forum.users
-            SET 
-                role = $2,
-                updated_at = LOCALTIMESTAMP
-            WHERE id = $1
+            INSERT INTO forum.profile_history(user_id, changed_by, field, old_value, new_value)
+            SELECT $1, $2, * FROM UNNEST($3::varchar[], $4::varchar[], $5::varchar[])
             "#,
             user_id,
-            role as UserRole)
-        .execute(&self.pool)
+            changed_by,
+            &fields,
+            &old_values as &[Option<String>],
+            &new_values as &[Option<String>])
+        .execute(&mut *tx)
         .await?;
 
-        Ok(())
+        tx.commit().await?;
+
+        Ok(user)
     }
 
     async fn update_user_password(&self, user_id: Uuid, password: &str) -> Result<(), sqlx::Error> { 
-        sqlx::query_as!(
-            User,
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
             r#"
             UPDATE forum.users
             SET 
@@ -243,28 +388,25 @@ impl UserExt for crate::db::DBClient {
             "#,
             user_id,
             password)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        // Only the fact that the password changed is kept, never the hash.
+        sqlx::query!(
+            r#"INSERT INTO forum.profile_history(user_id, changed_by, field) VALUES($1, $1, 'password')"#,
+            user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
 
     async fn warn_user(&self, user_id: Uuid, comment: Option<&str>, warned_by: Uuid, ban: Option<i32>) -> Result<(), sqlx::Error> {
-        sqlx::query!(
-            r#"INSERT INTO forum.user_warning(user_id,warn_time,comment,warned_by) VALUES($1,LOCALTIMESTAMP,$2,$3)"#,
-            user_id, comment, warned_by)
-            .fetch_optional(&self.pool)
-            .await?;
-
-        if let Some(days) = ban {
-            let days = sqlx::postgres::types::PgInterval { months: 0, days, microseconds: 0 };
-            sqlx::query!(
-                r#"UPDATE forum.users SET banned_until = LOCALTIMESTAMP + $2::interval
-                WHERE id = $1"#,
-                user_id, days)
-                .execute(&self.pool)
-                .await?;
-        }
+        let mut tx = self.pool.begin().await?;
+        insert_warning(&mut tx, user_id, comment, warned_by, ban).await?;
+        tx.commit().await?;
 
         Ok(())
     }
@@ -279,6 +421,53 @@ impl UserExt for crate::db::DBClient {
         Ok(())
     }
 
+    async fn get_ban_reason(&self, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+        struct Helper {
+            comment: Option<String>,
+        }
+
+        let res = sqlx::query_as!(Helper,
+            r#" SELECT comment FROM forum.user_warning
+                WHERE user_id = $1 AND banned
+                ORDER BY warn_time DESC
+                LIMIT 1"#, user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(res.and_then(|r| r.comment))
+    }
+
+    async fn get_profile_history(&self, user_id: Uuid, page: u32, limit: usize) -> Result<Vec<ProfileHistory>, sqlx::Error> {
+        let offset = (page as i64 - 1) * (limit as i64);
+
+        sqlx::query_as!(
+            ProfileHistory,
+            r#"
+            SELECT id, user_id, changed_by, field, old_value, new_value, changed_at
+            FROM forum.profile_history
+            WHERE user_id = $1
+            ORDER BY changed_at DESC, id DESC
+            LIMIT $2
+            OFFSET $3
+            "#,
+            user_id,
+            limit as i64,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn last_profile_change(&self, user_id: Uuid, field: &str) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT MAX(changed_at) FROM forum.profile_history WHERE user_id = $1 AND field = $2"#,
+            user_id,
+            field
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
     async fn verifed_token(&self, token: &str) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
@@ -317,16 +506,16 @@ impl UserExt for crate::db::DBClient {
         if let Some(id) = user_id {
             return sqlx::query_as!(
                 Post,
-                r#"SELECT * FROM forum.posts WHERE author = $1"#, id)
+                r#"SELECT id, content, author, topic, comments, created_at, modified_at, likes, content_html, edit_count FROM forum.posts WHERE author = $1 AND deleted_at IS NULL"#, id)
                 .fetch_all(&self.pool)
                 .await;
         } else {
             let name = user_name.unwrap();
             return sqlx::query_as!(
                 Post,
-                r#" SELECT forum.posts.id,content,author,topic,comments,forum.posts.created_at,modified_at,likes 
+                r#" SELECT forum.posts.id,content,author,topic,comments,forum.posts.created_at,modified_at,likes,content_html,edit_count
                     FROM forum.posts INNER JOIN forum.users ON forum.users.id = author
-                    WHERE forum.users.name = $1"#, name)
+                    WHERE forum.users.name = $1 AND forum.posts.deleted_at IS NULL"#, name)
                 .fetch_all(&self.pool)
                 .await;
         }
@@ -336,16 +525,16 @@ impl UserExt for crate::db::DBClient {
       if let Some(id) = user_id {
             return sqlx::query_as!(
                 Thread,
-                r#"SELECT * FROM forum.threads WHERE author = $1"#, id)
+                r#"SELECT id, title, created_at, content, author, section_id, locked, sticky, content_html, announcement, redirect_to, modified_at, edit_count FROM forum.threads WHERE author = $1 AND redirect_to IS NULL AND deleted_at IS NULL"#, id)
                 .fetch_all(&self.pool)
                 .await;
         } else {
             let name = user_name.unwrap();
             return sqlx::query_as!(
                 Thread,
-                r#" SELECT forum.threads.id, title, forum.threads.created_at, content, author, section_id, locked, sticky
+                r#" SELECT forum.threads.id, title, forum.threads.created_at, content, author, section_id, locked, sticky, content_html, announcement, redirect_to, modified_at, edit_count
                     FROM forum.threads INNER JOIN forum.users ON forum.users.id = author
-                    WHERE forum.users.name = $1"#, name)
+                    WHERE forum.users.name = $1 AND redirect_to IS NULL AND forum.threads.deleted_at IS NULL"#, name)
                 .fetch_all(&self.pool)
                 .await;
         }
@@ -377,10 +566,17 @@ impl UserExt for crate::db::DBClient {
        let offset = offset as i64;
 
         return sqlx::query_as!(PrivateMessage,
-            r#" SELECT id,author,receiver,content FROM forum.private_messages WHERE receiver = $1
+            r#" SELECT id,author,receiver,content FROM forum.private_messages WHERE receiver = $1 AND hidden_at IS NULL
                 LIMIT $2 OFFSET $3"#,
             user, limit, offset)
             .fetch_all(&self.pool)
             .await;
     }
+
+    async fn get_pm(&self, pm_id: i64) -> Result<Option<PrivateMessage>, sqlx::Error> {
+        sqlx::query_as!(PrivateMessage,
+            r#"SELECT id, author, receiver, content FROM forum.private_messages WHERE id = $1 AND hidden_at IS NULL"#, pm_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
 }
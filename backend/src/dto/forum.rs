@@ -1,15 +1,24 @@
 use core::str;
 use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
-use crate::models::UserRole;
+use chrono::{DateTime, Utc};
+use crate::models::{AuditAction, AuditFilter, AuditTarget, ChatRoomAccess, ForumSearch, ReportFilter, ReportReason, ReportStatus, ReportTarget, SearchScope, SearchSort, SectionRights, TrashKind, UserRole};
+use crate::utils::search;
 
-pub fn validate_roles<T>(v: &Vec<T>) -> Result<(), ValidationError> {
-    if v.len() == 0 {
+pub fn validate_roles<T>(v: &[T]) -> Result<(), ValidationError> {
+    if v.is_empty() {
         return Err(ValidationError::new("Section must be allowed for at least one role"));
     }
     Ok(())
 }
 
+pub fn validate_hash_tags(tags: &[String]) -> Result<(), ValidationError> {
+    if tags.iter().any(|t| crate::render::normalize_hashtag(t).is_none()) {
+        return Err(ValidationError::new("Tags must start with a letter and contain at most 25 letters, digits or underscores"));
+    }
+    Ok(())
+}
+
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CreateThreadDto {
     #[validate(length(min = 3, message = "Title too short"))]
@@ -17,6 +26,8 @@ pub struct CreateThreadDto {
     #[validate(length(min = 10, message = "A post must contain at least 10 characters"))]
     pub content: String,
     pub section: i64,
+    #[serde(default)]
+    #[validate(length(max = 10, message = "A thread can have at most 10 tags"), custom(function = "validate_hash_tags"))]
     pub hash_tags: Vec<String>,
 }
 
@@ -32,6 +43,8 @@ pub struct UpdateThreadDto {
     pub title: String,
     #[validate(length(min = 10, message = "A post must contain at least 10 characters"))]
     pub content: String,
+    #[validate(length(max = 255, message = "Reason can be at most 255 characters"))]
+    pub reason: Option<String>,
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
@@ -40,37 +53,174 @@ pub struct LockThreadDto {
     pub locked: bool,
 }
 
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StickyThreadDto {
+    pub thread_id: i64,
+    pub sticky: bool,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AnnounceThreadDto {
+    pub thread_id: i64,
+    pub announcement: bool,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MoveThreadDto {
+    pub thread_id: i64,
+    pub section_id: i64,
+    /// Leave a stub pointing to the thread in its old section, defaults to true
+    pub leave_stub: Option<bool>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MergeThreadsDto {
+    /// Thread whose posts are moved, it is left as a stub
+    pub thread_id: i64,
+    pub into: i64,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SplitThreadDto {
+    pub thread_id: i64,
+    #[validate(length(min = 1, max = 100, message = "Between 1 and 100 posts can be split off at once"))]
+    pub post_ids: Vec<i64>,
+    #[validate(length(min = 3, max = 255, message = "Title must be between 3 and 255 characters"))]
+    pub title: String,
+    /// Section of the new thread, the one of the old thread when missing
+    pub section_id: Option<i64>,
+}
+
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CreateSectionDto {
-    #[validate(length(min = 3, message = "Title too short"))]
-    name: String,
-    description: String,
+    #[validate(length(min = 3, max = 100, message = "Title must be between 3 and 100 characters"))]
+    pub name: String,
+    #[validate(length(max = 255, message = "Description must be at most 255 characters"))]
+    pub description: Option<String>,
     #[validate(custom(function = "validate_roles"))]
-    allowed_for: Vec<UserRole>,
+    pub allowed_for: Vec<UserRole>,
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub category: bool,
 }
 
+/// A section that still has subsections or threads needs either `cascade` or `move_to`.
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+#[validate(schema(function = "validate_section_delete"))]
 pub struct DeleteSectionDto {
     #[validate(range(min=0))]
-    s_id: i64,
+    pub s_id: i64,
+    /// Delete subsections, threads and posts along with the section
+    #[serde(default)]
+    pub cascade: bool,
+    /// Section that takes over the subsections and threads
+    pub move_to: Option<i64>,
+}
+
+fn validate_section_delete(body: &DeleteSectionDto) -> Result<(), ValidationError> {
+    if body.cascade && body.move_to.is_some() {
+        return Err(ValidationError::new("Content can either be deleted or moved, not both"));
+    }
+    Ok(())
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MoveSectionDto {
+    /// `None` moves the section to the top level
+    pub parent_id: Option<i64>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReorderSectionsDto {
+    pub parent_id: Option<i64>,
+    /// Every section below the parent, in the new order
+    #[validate(length(min = 1, max = 500))]
+    pub order: Vec<i64>,
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct GetChatDto {
     #[validate(range(min=0, max=100))]
-    limit: usize,
+    pub limit: usize,
+    /// Cursor from a previous page, only messages older than this id are returned.
+    pub before: Option<i32>,
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PostChatDto {
-    #[validate(length(min = 3, message = "Message too short"))]
-    content: String,
+    #[validate(length(min = 3, max = 255, message = "A message must be between 3 and 255 characters"))]
+    pub content: String,
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct DeleteChatDto {
     #[validate(range(min=0))]
-    post_id: i32,
+    pub post_id: i32,
+}
+
+fn validate_room_access(room: &CreateChatRoomDto) -> Result<(), ValidationError> {
+    match room.access {
+        ChatRoomAccess::Section if room.section_id.is_none() =>
+            Err(ValidationError::new("section_required").with_message("A section room needs a section_id".into())),
+        ChatRoomAccess::Roles if room.allowed_for.is_empty() =>
+            Err(ValidationError::new("roles_required").with_message("A room must be allowed for at least one role".into())),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+#[validate(schema(function = "validate_room_access"))]
+pub struct CreateChatRoomDto {
+    #[validate(length(min = 3, max = 50, message = "Name must be between 3 and 50 characters"))]
+    pub name: String,
+    #[validate(length(max = 255, message = "Description must be at most 255 characters"))]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub access: ChatRoomAccess,
+    pub section_id: Option<i64>,
+    #[serde(default)]
+    pub allowed_for: Vec<UserRole>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ArchiveChatRoomDto {
+    pub archived: bool,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetRoomMembersDto {
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatResponseDto {
+    pub status: String,
+    pub room_id: i32,
+    pub posts: Vec<crate::models::ChatPost>,
+    /// Pass as `before` to load the previous page, absent once the start of the room is reached.
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatRoomResponseDto {
+    pub status: String,
+    pub room: crate::models::ChatRoom,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatRoomListResponseDto {
+    pub status: String,
+    pub rooms: Vec<crate::models::ChatRoom>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatRoomMembersResponseDto {
+    pub status: String,
+    pub members: Vec<crate::models::ChatRoomMember>,
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
@@ -82,11 +232,164 @@ pub struct GetThreadDto {
     pub limit: usize,
 }
 
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetThreadsDto {
+    #[validate(range(min = 1))]
+    pub page: Option<i32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadView {
+    #[default]
+    Flat,
+    Tree,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetThreadPostsDto {
+    #[serde(default)]
+    pub view: ThreadView,
+    /// Flat view only.
+    #[validate(range(min = 1))]
+    pub page: Option<i32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+    /// Tree view only: levels of replies loaded below each post.
+    #[validate(range(max = 10))]
+    pub depth: Option<u32>,
+    /// Tree view only: start at the replies to this post instead of the top level posts.
+    pub parent: Option<i64>,
+    /// Tree view only: cursor from `nextCursor`, skips the posts up to and including this id.
+    pub after: Option<i64>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReplyThreadDto {
+    #[validate(length(min = 10, message = "A post must contain at least 10 characters"))]
+    pub content: String,
+    /// Post being replied to, a reply to the thread itself when absent.
+    pub post_id: Option<i64>,
+}
+
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct UpdatePostDto {
     #[validate(range(min=0))]
     pub post_id: i64,
     pub content: String,
+    #[validate(length(max = 255, message = "Reason can be at most 255 characters"))]
+    pub reason: Option<String>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetRevisionDiffDto {
+    pub from: i64,
+    /// Revision to compare with, the current content when missing
+    pub to: Option<i64>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RollbackRevisionDto {
+    #[validate(length(max = 255, message = "Reason can be at most 255 characters"))]
+    pub reason: Option<String>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetTrashDto {
+    pub section: Option<i64>,
+    pub kind: Option<TrashKind>,
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct ReportContentDto {
+    #[serde(rename = "targetType")]
+    pub target_type: ReportTarget,
+    #[serde(rename = "targetId")]
+    pub target_id: i64,
+    pub reason: ReportReason,
+    #[validate(length(max = 255, message = "Comment can be at most 255 characters"))]
+    pub comment: Option<String>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetReportsDto {
+    /// Open reports unless asked otherwise
+    pub status: Option<ReportStatus>,
+    #[serde(rename = "targetType")]
+    pub target_type: Option<ReportTarget>,
+    pub reason: Option<ReportReason>,
+    #[serde(rename = "claimedBy")]
+    pub claimed_by: Option<uuid::Uuid>,
+    pub unclaimed: Option<bool>,
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+impl GetReportsDto {
+    pub fn to_filter(&self) -> ReportFilter {
+        ReportFilter {
+            status: Some(self.status.unwrap_or(ReportStatus::Open)),
+            target_type: self.target_type,
+            reason: self.reason,
+            claimed_by: self.claimed_by,
+            unclaimed: self.unclaimed.unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetAuditLogDto {
+    pub actor: Option<uuid::Uuid>,
+    pub action: Option<AuditAction>,
+    #[serde(rename = "targetType")]
+    pub target_type: Option<AuditTarget>,
+    #[serde(rename = "targetId")]
+    pub target_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+impl GetAuditLogDto {
+    pub fn to_filter(&self) -> AuditFilter {
+        AuditFilter {
+            actor: self.actor,
+            action: self.action,
+            target_type: self.target_type,
+            target_id: self.target_id.clone(),
+            from: self.from,
+            to: self.to,
+        }
+    }
+}
+
+/// Warning for the author of the reported content, `banned` is the length of a ban in days.
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReportWarningDto {
+    #[validate(length(max = 255, message = "Comment can be at most 255 characters"))]
+    pub comment: Option<String>,
+    #[validate(range(min = 1))]
+    pub banned: Option<i32>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CloseReportDto {
+    #[validate(length(max = 255, message = "Resolution can be at most 255 characters"))]
+    pub resolution: Option<String>,
+    /// Only when resolving
+    #[validate(nested)]
+    pub warn: Option<ReportWarningDto>,
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
@@ -95,6 +398,122 @@ pub struct DeletePostDto {
     pub post_id: i64,
 }
 
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetReactorsDto {
+    pub emoji: Option<String>,
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SearchDto {
+    #[validate(length(min = 1, max = 200, message = "Query must be between 1 and 200 characters"))]
+    pub q: String,
+    pub kind: Option<SearchScope>,
+    pub section: Option<i64>,
+    #[validate(length(min = 1, max = 100))]
+    pub author: Option<String>,
+    #[validate(length(min = 1, max = 25))]
+    pub tag: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub sort: Option<SearchSort>,
+    pub cursor: Option<String>,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+impl SearchDto {
+    /// `None` when the query has no words left to search for.
+    pub fn to_forum_search(&self) -> Option<ForumSearch> {
+        Some(ForumSearch {
+            query: search::to_tsquery(&self.q)?,
+            scope: self.kind.unwrap_or_default(),
+            section_id: self.section,
+            author: self.author.as_deref().map(str::trim).filter(|a| !a.is_empty()).map(str::to_string),
+            tag: self.tag.as_deref().map(|t| t.trim_start_matches('#').to_string()).filter(|t| !t.is_empty()),
+            from: self.from,
+            to: self.to,
+            sort: self.sort.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetTagStatsDto {
+    /// Only threads created in the last `days` count, all of them when missing
+    #[validate(range(min = 1, max = 365))]
+    pub days: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AutocompleteTagsDto {
+    #[validate(length(min = 1, max = 26))]
+    pub prefix: String,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RenameTagDto {
+    #[validate(custom(function = "validate_hash_tag"))]
+    pub name: String,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MergeTagDto {
+    #[validate(custom(function = "validate_hash_tag"))]
+    pub into: String,
+}
+
+fn validate_hash_tag(tag: &str) -> Result<(), ValidationError> {
+    validate_hash_tags(&[tag.to_string()])
+}
+
+/// Rights left out are not granted.
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SectionRightsDto {
+    #[serde(default)]
+    pub can_view: bool,
+    #[serde(default)]
+    pub can_create_thread: bool,
+    #[serde(default)]
+    pub can_reply: bool,
+    #[serde(default)]
+    pub can_moderate: bool,
+}
+
+impl SectionRightsDto {
+    pub fn to_rights(&self) -> SectionRights {
+        SectionRights {
+            can_view: self.can_view,
+            can_create_thread: self.can_create_thread,
+            can_reply: self.can_reply,
+            can_moderate: self.can_moderate,
+        }
+    }
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CreateGroupDto {
+    #[validate(length(min = 3, max = 50, message = "Name must be between 3 and 50 characters"))]
+    pub name: String,
+    #[validate(length(max = 255, message = "Description can be at most 255 characters"))]
+    pub description: Option<String>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GetGroupMembersDto {
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<usize>,
+}
+
 //----- Output ------
 
 #[derive(Serialize, Deserialize)]
@@ -107,14 +526,206 @@ pub struct Response {
 pub struct GetThreadResponseDto {
     pub info: crate::models::Thread,
     pub posts: Vec<crate::models::Post>,
+    /// The stub that was asked for, when it led to this thread
+    #[serde(rename = "redirectedFrom", skip_serializing_if = "Option::is_none")]
+    pub redirected_from: Option<i64>,
+}
+
+/// Where to continue loading replies, pass both as query parameters of the tree view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyCursor {
+    pub parent: Option<i64>,
+    pub after: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostNode {
+    #[serde(flatten)]
+    pub post: crate::models::Post,
+    /// A deleted post kept in place for its replies, without content or author.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub deleted: bool,
+    #[serde(rename = "replyCount")]
+    pub reply_count: i64,
+    pub replies: Vec<PostNode>,
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<ReplyCursor>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetThreadTreeResponseDto {
+    pub info: crate::models::Thread,
+    pub posts: Vec<PostNode>,
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<ReplyCursor>,
+    #[serde(rename = "redirectedFrom", skip_serializing_if = "Option::is_none")]
+    pub redirected_from: Option<i64>,
+}
+
+/// Counts and the latest activity include the subsections the user can see.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionNode {
+    #[serde(flatten)]
+    pub section: crate::models::Section,
+    #[serde(rename = "threadCount")]
+    pub thread_count: i64,
+    #[serde(rename = "postCount")]
+    pub post_count: i64,
+    pub latest: Option<crate::models::LatestActivity>,
+    pub children: Vec<SectionNode>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetSectionsResponseDto {
-    pub sections: Vec<crate::models::Section>,
+    pub sections: Vec<SectionNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionResponseDto {
+    pub status: String,
+    pub section: crate::models::Section,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetSectionResponseDto {
+    /// Only on the first page
+    pub announcements: Vec<crate::models::Thread>,
+    pub threads: Vec<crate::models::Thread>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThreadResponseDto {
+    pub status: String,
+    pub thread: crate::models::Thread,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactionSetResponseDto {
+    pub status: String,
+    pub reactions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactionCountsResponseDto {
+    pub status: String,
+    pub reactions: Vec<crate::models::ReactionCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactorsResponseDto {
+    pub status: String,
+    pub users: Vec<crate::models::Reactor>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResponseDto {
+    pub status: String,
+    pub results: Vec<crate::models::SearchHit>,
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagThreadsResponseDto {
+    pub status: String,
+    pub tag: String,
     pub threads: Vec<crate::models::Thread>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagCountsResponseDto {
+    pub status: String,
+    pub tags: Vec<crate::models::TagCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrendingTagsResponseDto {
+    pub status: String,
+    pub days: u32,
+    pub tags: Vec<crate::models::TrendingTag>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionPermissionsResponseDto {
+    pub status: String,
+    pub roles: Vec<crate::models::RoleSectionRights>,
+    pub groups: Vec<crate::models::GroupSectionRights>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupResponseDto {
+    pub status: String,
+    pub group: crate::models::UserGroup,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupListResponseDto {
+    pub status: String,
+    pub groups: Vec<crate::models::UserGroup>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupMembersResponseDto {
+    pub status: String,
+    pub members: Vec<crate::models::UserGroupMember>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionModeratorsResponseDto {
+    pub status: String,
+    pub moderators: Vec<crate::models::SectionModerator>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionsResponseDto {
+    pub status: String,
+    pub revisions: Vec<crate::models::Revision>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionDiffResponseDto {
+    pub status: String,
+    pub from: i64,
+    /// Missing when compared with the current content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<i64>,
+    /// Both titles, only for threads whose title changed in between
+    #[serde(rename = "titleChange", skip_serializing_if = "Option::is_none")]
+    pub title_change: Option<(String, String)>,
+    pub lines: Vec<crate::models::DiffLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashResponseDto {
+    pub status: String,
+    /// Deleted items are purged after this many days
+    #[serde(rename = "retentionDays")]
+    pub retention_days: u32,
+    pub items: Vec<crate::models::TrashItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportFiledResponseDto {
+    pub status: String,
+    #[serde(rename = "reportId")]
+    pub report_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportsResponseDto {
+    pub status: String,
+    pub reports: Vec<crate::models::Report>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogResponseDto {
+    pub status: String,
+    pub entries: Vec<crate::models::AuditLogEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportResponseDto {
+    pub status: String,
+    pub report: crate::models::Report,
+    pub entries: Vec<crate::models::ReportEntry>,
+}
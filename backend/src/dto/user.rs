@@ -1,14 +1,13 @@
 use core::str;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use validator::{Validate, ValidationError};
 
-use crate::models::{User, UserRole};
+use crate::models::{ProfileHistory, ProfileUpdate, User, UserRole, UserSearch, UserSort};
 
 pub fn validate_password(s: &str) -> Result<(), ValidationError> {
     let mut r: u16 = 0;
-    let mut s_it = s.chars();
-    while let Some(c) = s_it.next() {
+    for c in s.chars() {
         r |= match c {
             ':'..='@' => 1,
             '!'..='/' => 2,
@@ -66,6 +65,8 @@ pub struct RequestQueryDto {
 pub struct FilterUserDto {
     pub id: String,
     pub name: String,
+    /// Left empty, and so out of the response, where the caller may not see it
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub email: String,
     pub role: String,
     pub verified: bool,
@@ -79,6 +80,9 @@ pub struct FilterUserDto {
     pub avatar: Option<String>,
     pub facebook: Option<String>,
     pub x_id: Option<String>,
+    /// Only filled in where the profile is shown, lists leave it out
+    #[serde(rename = "receivedLikes", skip_serializing_if = "Option::is_none")]
+    pub received_likes: Option<i64>,
 }
 
 impl FilterUserDto {
@@ -96,6 +100,7 @@ impl FilterUserDto {
             avatar: user.avatar.to_owned(),
             facebook: user.facebook.to_owned(),
             x_id: user.x_id.to_owned(),
+            received_likes: None,
         }
     }
 
@@ -105,23 +110,139 @@ impl FilterUserDto {
 
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameMatch {
+    #[default]
+    Prefix,
+    Fuzzy,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
 #[derive(Validate, Debug, Clone, Serialize, Deserialize)]
 pub struct SearchUsersDto {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
     pub name: Option<String>,
+    pub name_match: Option<NameMatch>,
+    #[validate(email(message = "Email is invalid"))]
     pub email: Option<String>,
     pub role: Option<UserRole>,
+    pub banned: Option<bool>,
+    pub registered_from: Option<DateTime<Utc>>,
+    pub registered_to: Option<DateTime<Utc>>,
+    pub online_from: Option<DateTime<Utc>>,
+    pub online_to: Option<DateTime<Utc>>,
+    pub sort: Option<UserSort>,
+    pub order: Option<SortOrder>,
     #[validate(range(min = 1))]
-    pub page: u32,
-    #[validate(range(min = 1))]
-    pub limit: usize,
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+impl SearchUsersDto {
+    pub fn to_user_search(&self) -> UserSearch {
+        let fuzzy = self.name_match.unwrap_or_default() == NameMatch::Fuzzy;
+        // Best matches first is the only useful order for a fuzzy search
+        let default_sort = if fuzzy && self.name.is_some() { UserSort::Relevance } else { UserSort::Name };
+
+        UserSearch {
+            name: self.name.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string),
+            fuzzy,
+            email: self.email.clone(),
+            role: self.role,
+            banned: self.banned,
+            registered_from: self.registered_from,
+            registered_to: self.registered_to,
+            online_from: self.online_from,
+            online_to: self.online_to,
+            sort: self.sort.unwrap_or(default_sort),
+            descending: self.order.unwrap_or_default() == SortOrder::Desc,
+        }
+    }
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct NameUpdateDto {
-    #[validate(length(min = 1, message = "Name is required"))]
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
     pub name: String,
 }
 
+/// Tells an absent field apart from an explicit `null`, which clears it.
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn validate_facebook(s: &str) -> Result<(), ValidationError> {
+    let len = s.chars().count();
+    if (5..=50).contains(&len) && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '.') {
+        return Ok(());
+    }
+    Err(ValidationError::new("facebook").with_message("Facebook must be a user name of 5 to 50 letters, numbers or dots".into()))
+}
+
+fn validate_x_id(s: &str) -> Result<(), ValidationError> {
+    let len = s.chars().count();
+    if (1..=15).contains(&len) && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Ok(());
+    }
+    Err(ValidationError::new("x_id").with_message("X handle must be 1 to 15 letters, numbers or underscores, without the @".into()))
+}
+
+fn validate_profile_update(dto: &UpdateProfileDto) -> Result<(), ValidationError> {
+    let update = dto.to_profile_update();
+
+    if let Some(Some(description)) = &update.description
+        && description.chars().count() > 255 {
+        return Err(ValidationError::new("description").with_message("Description must be at most 255 characters".into()));
+    }
+    if let Some(Some(facebook)) = &update.facebook {
+        validate_facebook(facebook)?;
+    }
+    if let Some(Some(x_id)) = &update.x_id {
+        validate_x_id(x_id)?;
+    }
+    Ok(())
+}
+
+/// PATCH body, fields left out are kept and `null` or an empty string clears them.
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+#[validate(schema(function = "validate_profile_update"))]
+pub struct UpdateProfileDto {
+    #[serde(default, deserialize_with = "double_option")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub facebook: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub x_id: Option<Option<String>>,
+}
+
+impl UpdateProfileDto {
+    pub fn to_profile_update(&self) -> ProfileUpdate {
+        let normalize = |field: &Option<Option<String>>| field.as_ref().map(|value| {
+            value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+        });
+
+        ProfileUpdate {
+            description: normalize(&self.description),
+            facebook: normalize(&self.facebook),
+            x_id: normalize(&self.x_id),
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Validate, Debug, Serialize, Deserialize)]
 pub struct AddUserDto {
     #[validate(length(min = 1, message = "Name is required"))]
@@ -133,13 +254,9 @@ pub struct AddUserDto {
     pub password_confirm: String,
 }
 
-#[derive(Validate, Debug, Serialize, Deserialize)]
-pub struct SaveUserDto {
-    pub user: FilterUserDto,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct RoleUpdateDto {
+    pub user_id: uuid::Uuid,
     pub role: UserRole,
 }
 
@@ -172,6 +289,33 @@ pub struct ForgotPasswordRequestDto {
     pub email: String,
 }
 
+#[derive(Deserialize, Serialize, Validate, Debug, Clone)]
+pub struct EmailChangeRequestDto {
+    #[validate(length(min = 1, max = 100, message = "Email is required"), email(message = "Email is invalid"))]
+    pub new_email: String,
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Validate, Debug, Clone)]
+pub struct DeleteAccountDto {
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    #[default]
+    Zip,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ExportQueryDto {
+    pub format: Option<ExportFormat>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
 pub struct ResetPasswordRequestDto {
     #[validate(length(min = 1, message = "Token is required."),)]
@@ -189,6 +333,41 @@ pub struct ResetPasswordRequestDto {
     pub new_password_confirm: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct RefreshTokenDto {
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct TwoFactorLoginDto {
+    #[validate(length(min = 1, message = "Token is required."))]
+    pub pending_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct TwoFactorCodeDto {
+    #[validate(length(equal = 6, message = "Code must have 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct DisableTwoFactorDto {
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+    #[validate(length(equal = 6, message = "Code must have 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct OidcCallbackQueryDto {
+    #[validate(length(min = 1, message = "Code is required."))]
+    pub code: String,
+    #[validate(length(min = 1, message = "State is required."))]
+    pub state: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
 pub struct RecentlyOnlineDto {
     pub since: DateTime<Utc>,
@@ -209,6 +388,20 @@ pub struct UnbanUserDto {
     pub uuid: uuid::Uuid,
 }
 
+fn validate_lockout_target(dto: &ClearLockoutDto) -> Result<(), ValidationError> {
+    if dto.name.is_none() && dto.ip.is_none() {
+        return Err(ValidationError::new("Either a user name or an IP address is required"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+#[validate(schema(function = "validate_lockout_target"))]
+pub struct ClearLockoutDto {
+    pub name: Option<String>,
+    pub ip: Option<std::net::IpAddr>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
 pub struct SendPmDto {
     pub recipient_id: uuid::Uuid,
@@ -250,7 +443,44 @@ pub struct UserListResponseDto {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserLoginResponseDto {
     pub status: String,
+    pub role: UserRole,
     pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorPendingResponseDto {
+    pub status: String,
+    pub pending_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorSetupResponseDto {
+    pub status: String,
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponseDto {
+    pub status: String,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcProvidersResponseDto {
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcLinkResponseDto {
+    pub status: String,
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserIdentitiesResponseDto {
+    pub identities: Vec<crate::models::UserIdentity>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -272,3 +502,41 @@ pub struct UserWarningsResponseDto {
 pub struct UserPmsResponseDto {
     pub pms: Vec<crate::models::PrivateMessage>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileHistoryResponseDto {
+    pub status: String,
+    pub history: Vec<ProfileHistory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AvatarUrlDto {
+    pub size: u32,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AvatarResponseDto {
+    pub status: String,
+    pub avatar: Option<String>,
+    pub urls: Vec<AvatarUrlDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountDeletionResponseDto {
+    pub status: String,
+    pub deletion: Option<crate::models::AccountDeletion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserExportDto {
+    #[serde(rename = "exportedAt")]
+    pub exported_at: DateTime<Utc>,
+    pub profile: FilterUserDto,
+    pub posts: Vec<crate::models::Post>,
+    pub threads: Vec<crate::models::Thread>,
+    pub warnings: Vec<crate::models::UserWarning>,
+    pub private_messages: Vec<crate::models::PrivateMessage>,
+    pub profile_history: Vec<ProfileHistory>,
+    pub identities: Vec<crate::models::UserIdentity>,
+}
@@ -11,6 +11,10 @@ use crate::make_enum;
 pub struct ErrorResponse {
     pub status: String,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
 impl fmt::Display for ErrorResponse {
@@ -22,12 +26,24 @@ impl fmt::Display for ErrorResponse {
 make_enum!(ErrorMessage, 
     [ServerError, WrongCredentials, InvalidToken, EmailAlreadyExists, 
     NoSuchUser, InvalidPassword, TokenNotProvided, PermissionDenied, 
-    NotAuthenticated, HashingError, EmptyPassword, InvalidHashFormat]);
+    NotAuthenticated, HashingError, EmptyPassword, InvalidHashFormat,
+    SessionRevoked, AccountBanned, AccountNotVerified, TwoFactorRequired,
+    InvalidTwoFactorCode, UnknownProvider, OidcProviderError, InvalidIdToken,
+    InvalidOidcState, IdentityAlreadyLinked, TooManyAttempts, NameAlreadyExists,
+    NameChangeCooldown, UnsupportedImage, ImageTooLarge, InvalidImage,
+    NoSuchChatRoom, ChatRoomArchived, ThreadLocked, UnknownReaction,
+    OwnPostReaction, EmptySearchQuery, InvalidCursor, NoSuchTag,
+    TagAlreadyExists, NoSuchSection, SectionNotEmpty, SectionIsCategory,
+    SectionPermissionDenied, NoSuchGroup, GroupAlreadyExists, ThreadIsRedirect,
+    NoSuchRevision, NotInTrash, NoSuchReport, AlreadyReported, OwnContentReport,
+    ReportClaimed, ReportClosed, RevisionIsCurrent]);
 
 #[derive(Debug, Clone)]
 pub struct HttpError {
     pub message: String,
     pub status: StatusCode,
+    pub code: Option<String>,
+    pub details: Option<serde_json::Value>,
 }
 
 impl HttpError {
@@ -35,48 +51,48 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status,
+            code: None,
+            details: None,
         }
     }
 
     pub fn server_error(message: impl Into<String>) -> Self {
-        HttpError {
-            message: message.into(),
-            status: StatusCode::INTERNAL_SERVER_ERROR,
-        }
+        Self::new(message, StatusCode::INTERNAL_SERVER_ERROR)
     }
 
     pub fn bad_request(message: impl Into<String>) -> Self {
-        HttpError {
-            message: message.into(),
-            status: StatusCode::BAD_REQUEST,
-        }
+        Self::new(message, StatusCode::BAD_REQUEST)
     }
 
     pub fn unique_constraint_violation(message: impl Into<String>) -> Self {
-        HttpError { 
-            message: message.into(), 
-            status: StatusCode::CONFLICT 
-        }
+        Self::new(message, StatusCode::CONFLICT)
     }
 
     pub fn unauthorized(message: impl Into<String>) -> Self {
-        HttpError {
-            message: message.into(),
-            status: StatusCode::UNAUTHORIZED,
-        }
+        Self::new(message, StatusCode::UNAUTHORIZED)
     }
 
-    pub fn answered_post_deletion(message: impl Into<String>) -> Self {
-        HttpError {
-            message: message.into(),
-            status: StatusCode::NOT_MODIFIED,
-        }
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(message, StatusCode::FORBIDDEN)
+    }
+
+    /// Attaches a machine readable code so clients don't have to match on the message.
+    pub fn with_code(mut self, code: ErrorMessage) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
     }
 
     pub fn into_http_response(self) -> Response {
         let json_response = Json(ErrorResponse {
             status: "fail".to_string(),
             message: self.message.clone(),
+            code: self.code,
+            details: self.details,
         });
 
         (self.status, json_response).into_response()
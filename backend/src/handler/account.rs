@@ -0,0 +1,181 @@
+use std::{io::{Cursor, Write}, sync::Arc};
+
+use axum::{
+    extract::Query,
+    http::header,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use validator::Validate;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{db::{account::AccountExt, oidc::OidcExt, user::UserExt},
+    dto::user,
+    error::{ErrorMessage, HttpError},
+    mail::mails::send_account_deletion_email,
+    middleware::JWTAuthMiddeware,
+    utils::password,
+    AppState};
+
+pub const DELETION_GRACE_DAYS: i64 = 14;
+/// Upper bound for the paginated queries reused by the export, far above any real account.
+const EXPORT_ROW_LIMIT: usize = 100_000;
+
+pub async fn request_deletion(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<user::DeleteAccountDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = &user.user;
+
+    let password_match = password::compare(&body.password, &user.password)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !password_match {
+        return Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()));
+    }
+
+    let deletion = app_state.db_client
+        .request_deletion(user.id, Utc::now() + Duration::days(DELETION_GRACE_DAYS))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let delete_after = deletion.delete_after.format("%Y-%m-%d %H:%M UTC").to_string();
+    if let Err(e) = send_account_deletion_email(&user.email, &user.name, &delete_after).await {
+        eprintln!("Failed to send account deletion email: {}", e);
+    }
+
+    let response = user::AccountDeletionResponseDto {
+        status: "success".to_string(),
+        deletion: Some(deletion),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_deletion(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let deletion = app_state.db_client
+        .get_deletion(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = user::AccountDeletionResponseDto {
+        status: "success".to_string(),
+        deletion,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn cancel_deletion(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let cancelled = app_state.db_client
+        .cancel_deletion(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !cancelled {
+        return Err(HttpError::bad_request("No pending account deletion"));
+    }
+
+    let response = user::Response {
+        status: "success",
+        message: "Account deletion cancelled".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+/// Anonymizes every account whose grace period is over, run periodically from `jobs`.
+pub async fn purge_deleted_accounts(app_state: &AppState) {
+    let due = match app_state.db_client.get_due_deletions().await {
+        Ok(due) => due,
+        Err(e) => {
+            eprintln!("Failed to load due account deletions: {}", e);
+            return;
+        }
+    };
+
+    for user_id in due {
+        // Nobody knows this password, so the scrubbed account can never be signed into again
+        let placeholder = match password::hash(uuid::Uuid::new_v4().to_string()) {
+            Ok(hash) => hash,
+            Err(e) => {
+                eprintln!("Failed to hash placeholder password: {}", e);
+                return;
+            }
+        };
+
+        match app_state.db_client.anonymize_user(user_id, &placeholder).await {
+            Ok(avatar) => super::avatar::remove_avatar_files(app_state, avatar).await,
+            Err(e) => eprintln!("Failed to anonymize user {}: {}", user_id, e),
+        }
+    }
+}
+
+pub async fn export_data(
+    Query(query_params): Query<user::ExportQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user = &user.user;
+    let db = &app_state.db_client;
+    let to_http = |e: sqlx::Error| HttpError::server_error(e.to_string());
+
+    let export = user::UserExportDto {
+        exported_at: Utc::now(),
+        profile: user::FilterUserDto::filter_user(user),
+        posts: db.get_user_posts(Some(user.id), None).await.map_err(to_http)?,
+        threads: db.get_user_threads(Some(user.id), None).await.map_err(to_http)?,
+        warnings: db.get_user_warnings(user.id, None).await.map_err(to_http)?,
+        private_messages: db.get_all_pms(user.id).await.map_err(to_http)?,
+        profile_history: db.get_profile_history(user.id, 1, EXPORT_ROW_LIMIT).await.map_err(to_http)?,
+        identities: db.get_user_identities(user.id).await.map_err(to_http)?,
+    };
+
+    if query_params.format.unwrap_or_default() == user::ExportFormat::Json {
+        return Ok(Json(export).into_response());
+    }
+
+    let archive = export_archive(&export)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let disposition = format!("attachment; filename=\"forum-export-{}.zip\"", user.id);
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/zip".to_string()), (header::CONTENT_DISPOSITION, disposition)],
+        archive,
+    ).into_response())
+}
+
+/// One JSON file per section so the archive is readable without any tooling.
+fn export_archive(export: &user::UserExportDto) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let files = [
+        ("profile.json", serde_json::to_vec_pretty(&export.profile)?),
+        ("posts.json", serde_json::to_vec_pretty(&export.posts)?),
+        ("threads.json", serde_json::to_vec_pretty(&export.threads)?),
+        ("warnings.json", serde_json::to_vec_pretty(&export.warnings)?),
+        ("private_messages.json", serde_json::to_vec_pretty(&export.private_messages)?),
+        ("profile_history.json", serde_json::to_vec_pretty(&export.profile_history)?),
+        ("identities.json", serde_json::to_vec_pretty(&export.identities)?),
+    ];
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, content) in files {
+        zip.start_file(name, options)?;
+        zip.write_all(&content)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{db::audit::AuditExt,
+    dto::forum,
+    error::HttpError,
+    AppState};
+
+pub async fn get_audit_log(
+    Query(query_params): Query<forum::GetAuditLogDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let entries = app_state.db_client
+        .get_audit_log(&query_params.to_filter(), query_params.page.unwrap_or(1), query_params.limit.unwrap_or(20))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::AuditLogResponseDto {
+        status: "success".to_string(),
+        entries,
+    };
+
+    Ok(Json(response))
+}
@@ -1,23 +1,105 @@
-use std::sync::Arc;
+use std::{net::{IpAddr, SocketAddr}, sync::{Arc, OnceLock}};
 
-use axum::{extract::Query, http::{header, HeaderMap, StatusCode}, response::{IntoResponse, Redirect}, routing::{get, post}, Extension, Json, Router};
-use axum_extra::extract::cookie::Cookie;
+use axum::{extract::{ConnectInfo, Query}, http::{header, HeaderMap, StatusCode}, middleware::from_fn, response::{IntoResponse, Redirect}, routing::{get, post}, Extension, Json, Router};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
 use chrono::{Utc, Duration};
 use validator::Validate;
 
-use crate::{db::user::UserExt, 
+use crate::{db::{email_change::EmailChangeExt, session::SessionExt, throttle::ThrottleExt, two_factor::TwoFactorExt, user::UserExt}, 
     dto::{user, Response}, 
     error::{ErrorMessage, HttpError}, 
-    mail::mails::{send_forgot_password_email, send_verification_email, send_welcome_email}, 
-    utils::{password, token}, AppState};
+    mail::mails::{send_account_locked_email, send_forgot_password_email, send_verification_email, send_welcome_email}, 
+    middleware::{self, JWTAuthMiddeware},
+    models::User,
+    policy,
+    utils::{password, token, totp}, AppState};
+
+pub const TWO_FACTOR_PENDING_MINUTES: i64 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// Failed logins before backoff starts and before a temporary lock
+const ACCOUNT_BACKOFF_AFTER: i32 = 3;
+const ACCOUNT_LOCK_AFTER: i32 = 10;
+const IP_BACKOFF_AFTER: i32 = 10;
+const IP_LOCK_AFTER: i32 = 50;
+const MAX_BACKOFF_SECONDS: i64 = 300;
+const LOCKOUT_MINUTES: i64 = 15;
 
 pub fn auth_handler() -> Router {
     Router::new()
         .route("/register", post(register))
         .route("/login", post(login))
+        .route("/login/2fa", post(login_two_factor))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout).layer(from_fn(middleware::auth)))
+        .route("/logout-all", post(logout_all).layer(from_fn(middleware::auth)))
+        .route("/2fa/setup", post(setup_two_factor).layer(from_fn(middleware::auth)))
+        .route("/2fa/confirm", post(confirm_two_factor).layer(from_fn(middleware::auth)))
+        .route("/2fa/recovery-codes", post(regenerate_recovery_codes).layer(from_fn(middleware::auth)))
+        .route("/2fa/disable", post(disable_two_factor).layer(from_fn(middleware::auth)))
         .route("/verify", get(verify_email))
+        .route("/email/confirm", get(confirm_email_change))
+        .route("/email/revert", get(revert_email_change))
         .route("/forgot-password", post(forgot_password))
         .route("/reset-password", post(reset_password))
+        .nest("/oidc", super::oidc::oidc_handler())
+}
+
+/// Opens a new session for the user and returns its access and refresh tokens.
+pub async fn start_session(app_state: &AppState, user_id: uuid::Uuid) -> Result<(String, String), HttpError> {
+    let refresh_token = token::create_refresh_token();
+    let expires_at = Utc::now() + Duration::minutes(app_state.env.refresh_maxage);
+
+    let session = app_state.db_client
+        .create_session(user_id, &token::hash_refresh_token(&refresh_token), expires_at)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let access_token = token::create_token(
+        &user_id.to_string(),
+        &session.id.to_string(),
+        app_state.env.jwt_secret.as_bytes(),
+        app_state.env.jwt_maxage
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok((access_token, refresh_token))
+}
+
+pub fn session_cookies(app_state: &AppState, access_token: &str, refresh_token: &str) -> HeaderMap {
+    let access_cookie = Cookie::build(("token", access_token.to_owned()))
+        .path("/")
+        .max_age(time::Duration::minutes(app_state.env.jwt_maxage))
+        .http_only(true)
+        .build();
+
+    let refresh_cookie = Cookie::build(("refresh_token", refresh_token.to_owned()))
+        .path("/")
+        .max_age(time::Duration::minutes(app_state.env.refresh_maxage))
+        .http_only(true)
+        .build();
+
+    let mut headers = HeaderMap::new();
+
+    headers.append(header::SET_COOKIE, access_cookie.to_string().parse().unwrap());
+    headers.append(header::SET_COOKIE, refresh_cookie.to_string().parse().unwrap());
+
+    headers
+}
+
+fn cleared_session_cookies() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for name in ["token", "refresh_token"] {
+        let cookie = Cookie::build((name, ""))
+            .path("/")
+            .max_age(time::Duration::ZERO)
+            .http_only(true)
+            .build();
+        headers.append(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+    }
+
+    headers
 }
 
 pub async fn register(
@@ -46,8 +128,8 @@ pub async fn register(
                 if let Err(e) = send_email_result {
                     eprintln!("Failed to send verification email: {}", e);
                 }
-            } else {
-                let _ = app_state.db_client.verifed_token(verification_token.as_str());
+            } else if let Err(e) = app_state.db_client.verifed_token(verification_token.as_str()).await {
+                eprintln!("Failed to verify {} without email verification: {}", body.name, e);
             }
 
             Ok((StatusCode::CREATED, Json(Response {
@@ -70,56 +152,384 @@ pub async fn register(
 
 pub async fn login(
     Extension(app_state): Extension<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Json(body): Json<user::LoginUserDto>
 ) -> Result<impl IntoResponse, HttpError> {
     body.validate()
        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
+    let account_key = account_throttle_key(&body.username);
+    let ip_key = ip_throttle_key(&addr.ip());
+
+    check_throttle(&app_state, &account_key, &ip_key).await?;
+
     let result = app_state.db_client
-        .get_user(None, Some(&body.username), None)
+        .get_user(None, Some(&body.username), None, None)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let user = result.ok_or(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))?;
+    // Unknown users still pay for a hash so timing doesn't tell them apart
+    let password_matched = match &result {
+        Some(user) => password::compare(&body.password, &user.password).unwrap_or(false),
+        None => {
+            let _ = password::compare(&body.password, dummy_hash());
+            false
+        }
+    };
 
-    let password_matched = password::compare(&body.password, &user.password)
-        .map_err(|_| HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))?;
+    let user = match result {
+        Some(user) if password_matched => user,
+        user => {
+            register_failure(&app_state, &account_key, &ip_key, user.as_ref()).await?;
+            return Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()));
+        }
+    };
+
+    // Banned users may still log in and read, the policy layer stops their writes
+    policy::evaluate(&user, &app_state.env)?;
+
+    let totp = app_state.db_client
+        .get_totp(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    if password_matched {
-        let token = token::create_token(
-            &user.id.to_string(), 
-            &app_state.env.jwt_secret.as_bytes(), 
-            app_state.env.jwt_maxage
+    if totp.is_some_and(|totp| totp.enabled) {
+        let pending_token = token::create_pending_token(
+            &user.id.to_string(),
+            app_state.env.jwt_secret.as_bytes(),
+            TWO_FACTOR_PENDING_MINUTES
         )
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-        let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
-        let cookie = Cookie::build(("token", token.clone()))
-            .path("/")
-            .max_age(cookie_duration)
-            .http_only(true)
-            .build();
+        return Ok(Json(user::TwoFactorPendingResponseDto {
+            status: "2fa_required".to_string(),
+            pending_token,
+        }).into_response());
+    }
 
-        let response = axum::response::Json(user::UserLoginResponseDto {
-            role: user.role,
-            status: "success".to_string(),
-            token,
-        });
+    app_state.db_client
+        .clear_throttle(&account_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-        let mut headers = HeaderMap::new();
+    login_response(&app_state, &user).await
+}
 
-        headers.append(
-            header::SET_COOKIE,
-            cookie.to_string().parse().unwrap(), 
-        );
+pub async fn login_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(body): Json<user::TwoFactorLoginDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+       .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-        let mut response = response.into_response();
-        response.headers_mut().extend(headers);
+    let user_id = token::decode_pending_token(&body.pending_token, app_state.env.jwt_secret.as_bytes())?;
+    let user_id = uuid::Uuid::parse_str(&user_id)
+        .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
 
-        Ok(response)
-    } else {
-        Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))
+    let user = app_state.db_client
+        .get_user(Some(user_id), None, None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(ErrorMessage::NoSuchUser.to_string()))?;
+
+    policy::evaluate(&user, &app_state.env)?;
+
+    let account_key = account_throttle_key(&user.name);
+    let ip_key = ip_throttle_key(&addr.ip());
+
+    check_throttle(&app_state, &account_key, &ip_key).await?;
+
+    let verified = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => check_totp_code(&app_state, user.id, code).await?,
+        (None, Some(recovery_code)) => check_recovery_code(&app_state, user.id, recovery_code).await?,
+        (None, None) => false,
+    };
+
+    if !verified {
+        register_failure(&app_state, &account_key, &ip_key, Some(&user)).await?;
+        return Err(HttpError::unauthorized(ErrorMessage::InvalidTwoFactorCode.to_string())
+            .with_code(ErrorMessage::InvalidTwoFactorCode));
+    }
+
+    app_state.db_client
+        .clear_throttle(&account_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    login_response(&app_state, &user).await
+}
+
+pub fn account_throttle_key(name: &str) -> String {
+    format!("account:{}", name.to_lowercase())
+}
+
+pub fn ip_throttle_key(ip: &IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| password::hash(uuid::Uuid::new_v4().to_string()).unwrap_or_default())
+}
+
+/// Rejects the attempt while the account or the client address is backing off or locked.
+/// Keys exist for unknown names too, so a lockout says nothing about whether the account exists.
+async fn check_throttle(app_state: &AppState, account_key: &str, ip_key: &str) -> Result<(), HttpError> {
+    let throttles = app_state.db_client
+        .get_throttles(&[account_key.to_string(), ip_key.to_string()])
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let now = Utc::now();
+    let wait = throttles.iter()
+        .filter_map(|t| {
+            let backoff_after = if t.key == ip_key { IP_BACKOFF_AFTER } else { ACCOUNT_BACKOFF_AFTER };
+            t.retry_after(now, backoff_after, MAX_BACKOFF_SECONDS)
+        })
+        .max();
+
+    match wait {
+        Some(wait) => Err(HttpError::new(ErrorMessage::TooManyAttempts.to_string(), StatusCode::TOO_MANY_REQUESTS)
+            .with_code(ErrorMessage::TooManyAttempts)
+            .with_details(serde_json::json!({ "retryAfter": wait.num_seconds().max(1) }))),
+        None => Ok(()),
+    }
+}
+
+async fn register_failure(app_state: &AppState, account_key: &str, ip_key: &str, user: Option<&User>) -> Result<(), HttpError> {
+    let lock_until = Utc::now() + Duration::minutes(LOCKOUT_MINUTES);
+
+    let account = app_state.db_client
+        .record_failure(account_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if account.should_lock(ACCOUNT_LOCK_AFTER) {
+        app_state.db_client
+            .lock_key(account_key, lock_until)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        if let Some(user) = user {
+            let until = lock_until.format("%Y-%m-%d %H:%M UTC").to_string();
+            if let Err(e) = send_account_locked_email(&user.email, &user.name, &until).await {
+                eprintln!("Failed to send account locked email: {}", e);
+            }
+        }
+    }
+
+    let ip = app_state.db_client
+        .record_failure(ip_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if ip.should_lock(IP_LOCK_AFTER) {
+        app_state.db_client
+            .lock_key(ip_key, lock_until)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Starts a session for a fully authenticated user and returns the login body with its cookies.
+async fn login_response(app_state: &AppState, user: &User) -> Result<axum::response::Response, HttpError> {
+    let (token, refresh_token) = start_session(app_state, user.id).await?;
+
+    let headers = session_cookies(app_state, &token, &refresh_token);
+
+    let response = axum::response::Json(user::UserLoginResponseDto {
+        role: user.role,
+        status: "success".to_string(),
+        token,
+        refresh_token,
+    });
+
+    let mut response = response.into_response();
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+}
+
+/// Verifies a TOTP code of an enabled enrollment and burns its time step.
+async fn check_totp_code(app_state: &AppState, user_id: uuid::Uuid, code: &str) -> Result<bool, HttpError> {
+    let totp = app_state.db_client
+        .get_totp(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let totp = match totp {
+        Some(totp) if totp.enabled => totp,
+        _ => return Ok(false),
+    };
+
+    let step = match totp::verify(&totp.secret, code, Utc::now().timestamp(), totp.last_used_step) {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    app_state.db_client
+        .use_totp_step(user_id, step)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))
+}
+
+async fn check_recovery_code(app_state: &AppState, user_id: uuid::Uuid, recovery_code: &str) -> Result<bool, HttpError> {
+    let codes = app_state.db_client
+        .get_recovery_codes(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    for code in codes {
+        if totp::verify_recovery_code(recovery_code, &code.code_hash) {
+            return app_state.db_client
+                .use_recovery_code(code.id)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()));
+        }
+    }
+
+    Ok(false)
+}
+
+fn hash_recovery_codes(codes: &[String]) -> Result<Vec<String>, HttpError> {
+    codes.iter()
+        .map(|code| totp::hash_recovery_code(code).map_err(|e| HttpError::server_error(e.to_string())))
+        .collect()
+}
+
+pub async fn setup_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user = &user.user;
+
+    let totp = app_state.db_client
+        .get_totp(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if totp.is_some_and(|totp| totp.enabled) {
+        return Err(HttpError::bad_request("Two-factor authentication is already enabled"));
     }
+
+    let secret = totp::generate_secret();
+
+    app_state.db_client
+        .save_totp_secret(user.id, &secret)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let otpauth_url = totp::otpauth_uri(&app_state.env.totp_issuer, &user.name, &secret);
+
+    Ok(Json(user::TwoFactorSetupResponseDto {
+        status: "success".to_string(),
+        secret,
+        otpauth_url,
+    }))
+}
+
+pub async fn confirm_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<user::TwoFactorCodeDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+       .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = &user.user;
+
+    let totp = app_state.db_client
+        .get_totp(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let totp = match totp {
+        Some(totp) if !totp.enabled => totp,
+        Some(_) => return Err(HttpError::bad_request("Two-factor authentication is already enabled")),
+        None => return Err(HttpError::bad_request("Two-factor setup has not been started")),
+    };
+
+    let step = totp::verify(&totp.secret, &body.code, Utc::now().timestamp(), None)
+        .ok_or(HttpError::bad_request(ErrorMessage::InvalidTwoFactorCode.to_string())
+            .with_code(ErrorMessage::InvalidTwoFactorCode))?;
+
+    let recovery_codes = totp::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let code_hashes = hash_recovery_codes(&recovery_codes)?;
+
+    app_state.db_client
+        .enable_totp(user.id, step, &code_hashes)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(user::RecoveryCodesResponseDto {
+        status: "success".to_string(),
+        recovery_codes,
+    }))
+}
+
+pub async fn regenerate_recovery_codes(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<user::TwoFactorCodeDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+       .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = &user.user;
+
+    if !check_totp_code(&app_state, user.id, &body.code).await? {
+        return Err(HttpError::bad_request(ErrorMessage::InvalidTwoFactorCode.to_string())
+            .with_code(ErrorMessage::InvalidTwoFactorCode));
+    }
+
+    let recovery_codes = totp::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let code_hashes = hash_recovery_codes(&recovery_codes)?;
+
+    app_state.db_client
+        .replace_recovery_codes(user.id, &code_hashes)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(user::RecoveryCodesResponseDto {
+        status: "success".to_string(),
+        recovery_codes,
+    }))
+}
+
+pub async fn disable_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<user::DisableTwoFactorDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+       .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = &user.user;
+
+    let password_matched = password::compare(&body.password, &user.password)
+        .map_err(|_| HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))?;
+
+    if !password_matched {
+        return Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()));
+    }
+
+    if !check_totp_code(&app_state, user.id, &body.code).await? {
+        return Err(HttpError::bad_request(ErrorMessage::InvalidTwoFactorCode.to_string())
+            .with_code(ErrorMessage::InvalidTwoFactorCode));
+    }
+
+    app_state.db_client
+        .disable_totp(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(Response {
+        message: "Two-factor authentication disabled".to_string(),
+        status: "success",
+    }))
 }
 
 pub async fn verify_email(
@@ -130,7 +540,7 @@ pub async fn verify_email(
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let result = app_state.db_client
-        .get_user(None, None, Some(&query_params.token))
+        .get_user(None, None, None, Some(&query_params.token))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -147,31 +557,13 @@ pub async fn verify_email(
     app_state.db_client.verifed_token(&query_params.token).await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let send_welcome_email_result = send_welcome_email(&user.email, &user.name).await;
-
-    if let Err(e) = send_welcome_email_result {
+    if let Err(e) = send_welcome_email(&user.email, &user.name).await {
         eprintln!("Failed to send welcome email: {}", e);
     }
 
-    let token = token::create_token(
-        &user.id.to_string(), 
-        app_state.env.jwt_secret.as_bytes(),
-        app_state.env.jwt_maxage 
-    ).map_err(|e| HttpError::server_error(e.to_string()))?;
+    let (token, refresh_token) = start_session(&app_state, user.id).await?;
 
-    let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
-    let cookie = Cookie::build(("token", token.clone()))
-        .path("/")
-        .max_age(cookie_duration)
-        .http_only(true)
-        .build();
-
-    let mut headers = HeaderMap::new();
-
-    headers.append(
-        header::SET_COOKIE,
-        cookie.to_string().parse().unwrap() 
-    );
+    let headers = session_cookies(&app_state, &token, &refresh_token);
 
     let frontend_url = format!("{}/settings", app_state.env.host_url);
 
@@ -184,6 +576,83 @@ pub async fn verify_email(
     Ok(response)
 }
 
+pub async fn confirm_email_change(
+    Query(query_params): Query<user::VerifyEmailQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let change = app_state.db_client
+        .get_email_change(Some(&query_params.token), None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|c| c.confirmed_at.is_none() && c.reverted_at.is_none())
+        .ok_or(HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+    if Utc::now() > change.expires_at {
+        return Err(HttpError::bad_request("Confirmation link has expired".to_string()));
+    }
+
+    match app_state.db_client.confirm_email_change(&change).await {
+        Ok(true) => {},
+        Ok(false) => return Err(HttpError::bad_request("This email change is no longer valid".to_string())),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return Err(HttpError::unique_constraint_violation(ErrorMessage::EmailAlreadyExists.to_string()));
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    }
+
+    app_state.db_client
+        .revoke_user_sessions(change.user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let mut response = Redirect::to(&format!("{}/login", app_state.env.host_url)).into_response();
+    response.headers_mut().extend(cleared_session_cookies());
+
+    Ok(response)
+}
+
+pub async fn revert_email_change(
+    Query(query_params): Query<user::VerifyEmailQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let change = app_state.db_client
+        .get_email_change(None, Some(&query_params.token))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|c| c.reverted_at.is_none())
+        .ok_or(HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+    if Utc::now() > change.revert_expires_at {
+        return Err(HttpError::bad_request("Revert link has expired".to_string()));
+    }
+
+    match app_state.db_client.revert_email_change(&change).await {
+        Ok(true) => {},
+        Ok(false) => return Err(HttpError::bad_request("This email change can no longer be reverted".to_string())),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return Err(HttpError::unique_constraint_violation(ErrorMessage::EmailAlreadyExists.to_string()));
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    }
+
+    // Whoever asked for the change may still be signed in
+    app_state.db_client
+        .revoke_user_sessions(change.user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let mut response = Redirect::to(&format!("{}/forgot-password", app_state.env.host_url)).into_response();
+    response.headers_mut().extend(cleared_session_cookies());
+
+    Ok(response)
+}
+
 pub async fn forgot_password(
     Extension(app_state): Extension<Arc<AppState>>,
     Json(body): Json<user::ForgotPasswordRequestDto>
@@ -192,7 +661,7 @@ pub async fn forgot_password(
        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let result = app_state.db_client
-            .get_user(None, Some(&body.email), None)
+            .get_user(None, None, Some(&body.email), None)
             .await
             .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -233,7 +702,7 @@ pub async fn reset_password(
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let result = app_state.db_client
-        .get_user(None, None, Some(&body.token))
+        .get_user(None, None, None, Some(&body.token))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -253,7 +722,7 @@ pub async fn reset_password(
             .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     app_state.db_client
-        .update_user_password(user_id.clone(), hash_password.as_str())
+        .update_user_password(user_id, hash_password.as_str())
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -262,6 +731,11 @@ pub async fn reset_password(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    app_state.db_client
+        .revoke_user_sessions(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
     let response = Response {
         message: "Password has been successfully reset.".to_string(),
         status: "success",
@@ -270,3 +744,118 @@ pub async fn reset_password(
     Ok(Json(response))
 }
 
+pub async fn refresh(
+    Extension(app_state): Extension<Arc<AppState>>,
+    cookie_jar: CookieJar,
+    body: Option<Json<user::RefreshTokenDto>>
+) -> Result<impl IntoResponse, HttpError> {
+    let refresh_token = cookie_jar
+        .get("refresh_token")
+        .map(|cookie| cookie.value().to_string())
+        .or_else(|| body.and_then(|Json(body)| body.refresh_token))
+        .ok_or_else(|| HttpError::unauthorized(ErrorMessage::TokenNotProvided.to_string()))?;
+
+    let token_hash = token::hash_refresh_token(&refresh_token);
+
+    let session = app_state.db_client
+        .get_session_by_token(&token_hash)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let session = match session {
+        Some(session) => session,
+        None => {
+            // A rotated-out token showing up again means it leaked, so kill the whole session
+            let reused = app_state.db_client
+                .get_session_by_previous_token(&token_hash)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            if let Some(reused) = reused {
+                app_state.db_client
+                    .revoke_session(reused.id)
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?;
+            }
+
+            return Err(HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()));
+        }
+    };
+
+    if !session.is_active() {
+        return Err(HttpError::unauthorized(ErrorMessage::SessionRevoked.to_string()));
+    }
+
+    let user = app_state.db_client
+        .get_user(Some(session.user_id), None, None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(ErrorMessage::NoSuchUser.to_string()))?;
+
+    policy::evaluate(&user, &app_state.env)?;
+
+    let new_refresh_token = token::create_refresh_token();
+    let expires_at = Utc::now() + Duration::minutes(app_state.env.refresh_maxage);
+
+    app_state.db_client
+        .rotate_session(session.id, &token_hash, &token::hash_refresh_token(&new_refresh_token), expires_at)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(ErrorMessage::SessionRevoked.to_string()))?;
+
+    let token = token::create_token(
+        &user.id.to_string(),
+        &session.id.to_string(),
+        app_state.env.jwt_secret.as_bytes(),
+        app_state.env.jwt_maxage
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let headers = session_cookies(&app_state, &token, &new_refresh_token);
+
+    let mut response = Json(user::UserLoginResponseDto {
+        role: user.role,
+        status: "success".to_string(),
+        token,
+        refresh_token: new_refresh_token,
+    }).into_response();
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+}
+
+pub async fn logout(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>
+) -> Result<impl IntoResponse, HttpError> {
+    app_state.db_client
+        .revoke_session(user.session_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let mut response = Json(Response {
+        message: "Logged out".to_string(),
+        status: "success",
+    }).into_response();
+    response.headers_mut().extend(cleared_session_cookies());
+
+    Ok(response)
+}
+
+pub async fn logout_all(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>
+) -> Result<impl IntoResponse, HttpError> {
+    app_state.db_client
+        .revoke_user_sessions(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let mut response = Json(Response {
+        message: "Logged out of all sessions".to_string(),
+        status: "success",
+    }).into_response();
+    response.headers_mut().extend(cleared_session_cookies());
+
+    Ok(response)
+}
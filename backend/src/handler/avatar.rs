@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Multipart, Path},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Extension, Json, Router,
+};
+use uuid::Uuid;
+
+use crate::{db::user::UserExt,
+    dto::user,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    utils::avatar::{self, AvatarError, AVATAR_CONTENT_TYPE, AVATAR_SIZES},
+    AppState};
+
+const FORM_FIELD: &str = "avatar";
+/// Keys are never reused, so a served thumbnail can be cached for good.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+pub fn avatar_handler() -> Router {
+    Router::new()
+        .route("/{version}/{size}", get(serve_avatar))
+}
+
+fn avatar_urls(app_state: &AppState, version: &str) -> Vec<user::AvatarUrlDto> {
+    AVATAR_SIZES.iter()
+        .map(|&size| user::AvatarUrlDto {
+            size,
+            url: format!("{}/avatars/{}/{}", app_state.env.api_url, version, size),
+        })
+        .collect()
+}
+
+/// Removes the thumbnails of a replaced avatar, a leftover file only wastes space so errors are just logged.
+pub async fn remove_avatar_files(app_state: &AppState, version: Option<String>) {
+    let Some(version) = version.filter(|v| avatar::is_uploaded(v)) else {
+        return;
+    };
+
+    for size in AVATAR_SIZES {
+        if let Err(e) = app_state.storage.delete(&avatar::avatar_key(&version, size)).await {
+            eprintln!("Failed to delete avatar {}: {}", version, e);
+        }
+    }
+}
+
+pub async fn upload_avatar(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, HttpError> {
+    let mut data = None;
+
+    while let Some(field) = multipart.next_field()
+        .await
+        .map_err(|e| HttpError::bad_request(e.body_text()))? {
+        if field.name() == Some(FORM_FIELD) {
+            data = Some(field.bytes()
+                .await
+                .map_err(|e| HttpError::new(e.body_text(), e.status()))?);
+            break;
+        }
+    }
+
+    let data = data.ok_or(HttpError::bad_request(format!("Missing {} file", FORM_FIELD)))?;
+
+    // Decoding and resizing is CPU bound, keep it off the async workers.
+    let thumbnails = tokio::task::spawn_blocking(move || avatar::process(&data))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|e| {
+            let (code, status) = match e {
+                AvatarError::UnsupportedFormat => (ErrorMessage::UnsupportedImage, StatusCode::BAD_REQUEST),
+                AvatarError::FileTooLarge => (ErrorMessage::ImageTooLarge, StatusCode::PAYLOAD_TOO_LARGE),
+                AvatarError::TooLarge => (ErrorMessage::ImageTooLarge, StatusCode::BAD_REQUEST),
+                AvatarError::Invalid(_) => (ErrorMessage::InvalidImage, StatusCode::BAD_REQUEST),
+            };
+            HttpError::new(e.to_string(), status).with_code(code)
+        })?;
+
+    let version = Uuid::new_v4().simple().to_string();
+
+    for (size, bytes) in thumbnails {
+        app_state.storage
+            .put(&avatar::avatar_key(&version, size), bytes, AVATAR_CONTENT_TYPE)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
+    let previous = app_state.db_client
+        .update_user_avatar(user.user.id, Some(&version))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    remove_avatar_files(&app_state, previous).await;
+
+    let response = user::AvatarResponseDto {
+        status: "success".to_string(),
+        urls: avatar_urls(&app_state, &version),
+        avatar: Some(version),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn delete_avatar(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let previous = app_state.db_client
+        .update_user_avatar(user.user.id, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    remove_avatar_files(&app_state, previous).await;
+
+    let response = user::AvatarResponseDto {
+        status: "success".to_string(),
+        avatar: None,
+        urls: Vec::new(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn serve_avatar(
+    Path((version, size)): Path<(String, u32)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, HttpError> {
+    let not_found = || HttpError::new("Avatar not found", StatusCode::NOT_FOUND);
+
+    if !avatar::is_uploaded(&version) || !AVATAR_SIZES.contains(&size) {
+        return Err(not_found());
+    }
+
+    let etag = format!("\"{}-{}\"", version, size);
+    let cache_headers = [
+        (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+        (header::ETAG, etag.clone()),
+    ];
+
+    if headers.get(header::IF_NONE_MATCH).is_some_and(|v| v.as_bytes() == etag.as_bytes()) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let data = app_state.storage
+        .get(&avatar::avatar_key(&version, size))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(not_found)?;
+
+    Ok((cache_headers, [(header::CONTENT_TYPE, AVATAR_CONTENT_TYPE)], data).into_response())
+}
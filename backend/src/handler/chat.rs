@@ -0,0 +1,552 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, ConnectInfo, Path, Query},
+    http::{Method, StatusCode},
+    middleware::from_fn,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{audit::{self, AuditEntry},
+    chat::ChatEvent,
+    db::{chat::ChatExt, session::SessionExt, user::UserExt},
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::{check_roles, role_check, JWTAuthMiddeware},
+    models::{AuditAction, AuditTarget, ChatPost, ChatRoom, ChatRoomAccess, User, UserRole},
+    policy,
+    AppState};
+
+/// Room that took over the old global shoutbox, seeded by schema.sql.
+pub const DEFAULT_ROOM_ID: i32 = 1;
+
+/// Messages sent to a client right after it connects.
+pub const CHAT_HISTORY: usize = 50;
+
+pub fn chat_handler() -> Router {
+    let admin_mod_only = from_fn(|state, req, next|
+        role_check(state, req, next, vec![UserRole::Admin, UserRole::Mod]) );
+    let admin_only = from_fn(|state, req, next|
+        role_check(state, req, next, vec![UserRole::Admin]) );
+
+    Router::new()
+        .route("/", get(get_chat).post(post_chat))
+        .route("/", delete(delete_chat).layer(admin_mod_only.clone()) )
+        .route("/ws", get(chat_socket))
+        .route("/rooms", get(get_rooms))
+        .route("/rooms", post(create_room).layer(admin_only.clone()) )
+        .route("/rooms/{room_id}", delete(delete_room).layer(admin_only.clone()) )
+        .route("/rooms/{room_id}/archive", put(archive_room).layer(admin_only.clone()) )
+        .route("/rooms/{room_id}/messages", get(get_room_chat).post(post_room_chat))
+        .route("/rooms/{room_id}/members", get(get_room_members).put(join_room).delete(leave_room))
+        .route("/rooms/{room_id}/ws", get(room_socket))
+}
+
+/// Commands a client can send over the socket.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChatCommand {
+    Post { content: String },
+    Delete { id: i32 },
+}
+
+/// Socket messages that are not `ChatEvent`s.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    History { room: ChatRoom, posts: Vec<ChatPost> },
+    Error {
+        status: u16,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        details: Option<serde_json::Value>,
+    },
+}
+
+impl From<HttpError> for ServerMessage {
+    fn from(e: HttpError) -> Self {
+        ServerMessage::Error {
+            status: e.status.as_u16(),
+            message: e.message,
+            code: e.code,
+            details: e.details,
+        }
+    }
+}
+
+fn no_such_room() -> HttpError {
+    HttpError::new(ErrorMessage::NoSuchChatRoom.to_string(), StatusCode::NOT_FOUND)
+        .with_code(ErrorMessage::NoSuchChatRoom)
+}
+
+/// Loads a room the user may read. Rooms they can't see are reported as missing, not forbidden.
+async fn room_for(app_state: &AppState, user_id: Uuid, room_id: i32) -> Result<ChatRoom, HttpError> {
+    app_state.db_client
+        .get_room(user_id, room_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(no_such_room)
+}
+
+/// Stores a message and broadcasts it, shared by the REST routes and the socket.
+async fn publish_message(app_state: &AppState, room: &ChatRoom, user_id: Uuid, content: &str) -> Result<ChatPost, HttpError> {
+    if room.archived_at.is_some() {
+        return Err(HttpError::new(ErrorMessage::ChatRoomArchived.to_string(), StatusCode::CONFLICT)
+            .with_code(ErrorMessage::ChatRoomArchived));
+    }
+
+    if let Some(wait) = app_state.chat.flood.check(user_id) {
+        return Err(HttpError::new(ErrorMessage::TooManyAttempts.to_string(), StatusCode::TOO_MANY_REQUESTS)
+            .with_code(ErrorMessage::TooManyAttempts)
+            .with_details(json!({ "retryAfter": wait.as_secs() + 1 })));
+    }
+
+    let post = app_state.db_client
+        .post_chat(room.id, user_id, content)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state.chat.bus
+        .publish(ChatEvent::Message { post: post.clone() })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(post)
+}
+
+/// Only moderators delete messages, the returned entry is what goes into the audit log.
+async fn remove_message(app_state: &AppState, post_id: i32) -> Result<AuditEntry, HttpError> {
+    // Missing for a message a report already hid
+    let post = app_state.db_client
+        .get_chat_post(post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let room_id = app_state.db_client
+        .delete_chat(post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::bad_request("No such message"))?;
+
+    app_state.chat.bus
+        .publish(ChatEvent::Deleted { room_id, id: post_id })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(AuditEntry::new(AuditAction::ChatDelete, AuditTarget::Chat, post_id)
+        .before(json!({ "roomId": room_id, "author": post.as_ref().map(|p| p.author), "content": post.map(|p| p.content) })))
+}
+
+async fn recent_messages(app_state: &AppState, room_id: i32, before: Option<i32>, limit: usize) -> Result<Vec<ChatPost>, HttpError> {
+    let mut posts = app_state.db_client
+        .get_chat(room_id, before, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Newest first from the database, clients want them in reading order
+    posts.reverse();
+
+    Ok(posts)
+}
+
+async fn room_history(app_state: &AppState, user_id: Uuid, room_id: i32, query: forum::GetChatDto) -> Result<forum::ChatResponseDto, HttpError> {
+    query.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let room = room_for(app_state, user_id, room_id).await?;
+    let posts = recent_messages(app_state, room.id, query.before, query.limit).await?;
+
+    // A short page means there is nothing older left
+    let next_cursor = if query.limit > 0 && posts.len() == query.limit {
+        posts.first().map(|p| p.id)
+    } else {
+        None
+    };
+
+    Ok(forum::ChatResponseDto {
+        status: "success".to_string(),
+        room_id: room.id,
+        posts,
+        next_cursor,
+    })
+}
+
+pub async fn get_chat(
+    Query(query_params): Query<forum::GetChatDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    Ok(Json(room_history(&app_state, user.user.id, DEFAULT_ROOM_ID, query_params).await?))
+}
+
+pub async fn get_room_chat(
+    Path(room_id): Path<i32>,
+    Query(query_params): Query<forum::GetChatDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    Ok(Json(room_history(&app_state, user.user.id, room_id, query_params).await?))
+}
+
+async fn post_to_room(app_state: &AppState, user_id: Uuid, room_id: i32, body: forum::PostChatDto) -> Result<forum::Response, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let room = room_for(app_state, user_id, room_id).await?;
+    publish_message(app_state, &room, user_id, body.content.trim()).await?;
+
+    Ok(forum::Response {
+        status: "success",
+        message: "message posted".to_string(),
+    })
+}
+
+pub async fn post_chat(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::PostChatDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    Ok(Json(post_to_room(&app_state, user.user.id, DEFAULT_ROOM_ID, body).await?))
+}
+
+pub async fn post_room_chat(
+    Path(room_id): Path<i32>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::PostChatDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    Ok(Json(post_to_room(&app_state, user.user.id, room_id, body).await?))
+}
+
+pub async fn delete_chat(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::DeleteChatDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let entry = remove_message(&app_state, body.post_id).await?;
+
+    let response = forum::Response {
+        status: "success",
+        message: "message deleted".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+pub async fn get_rooms(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let rooms = app_state.db_client
+        .get_rooms(user.user.id, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(forum::ChatRoomListResponseDto {
+        status: "success".to_string(),
+        rooms,
+    }))
+}
+
+pub async fn create_room(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::CreateChatRoomDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let description = body.description.as_deref().map(str::trim).filter(|d| !d.is_empty());
+    // Only the access mode's own field is kept, the check constraint rejects a stray section
+    let section_id = body.section_id.filter(|_| body.access == ChatRoomAccess::Section);
+    let allowed_for: &[UserRole] = if body.access == ChatRoomAccess::Roles { &body.allowed_for } else { &[] };
+
+    let result = app_state.db_client
+        .create_room(body.name.trim(), description, body.access, section_id, allowed_for, user.user.id)
+        .await;
+
+    let room_id = match result {
+        Ok(room_id) => room_id,
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return Err(HttpError::unique_constraint_violation("A room with this name already exists"));
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            return Err(HttpError::bad_request("No such section"));
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    let room = room_for(&app_state, user.user.id, room_id).await?;
+
+    Ok((StatusCode::CREATED, Json(forum::ChatRoomResponseDto {
+        status: "success".to_string(),
+        room,
+    })))
+}
+
+pub async fn archive_room(
+    Path(room_id): Path<i32>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::ArchiveChatRoomDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    if room_id == DEFAULT_ROOM_ID {
+        return Err(HttpError::bad_request("The default room can't be archived"));
+    }
+
+    let updated = app_state.db_client
+        .set_room_archived(room_id, body.archived)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !updated {
+        return Err(no_such_room());
+    }
+
+    app_state.chat.bus
+        .publish(ChatEvent::RoomArchived { room_id, archived: body.archived })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let room = room_for(&app_state, user.user.id, room_id).await?;
+
+    Ok(Json(forum::ChatRoomResponseDto {
+        status: "success".to_string(),
+        room,
+    }))
+}
+
+pub async fn delete_room(
+    Path(room_id): Path<i32>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    if room_id == DEFAULT_ROOM_ID {
+        return Err(HttpError::bad_request("The default room can't be deleted"));
+    }
+
+    let deleted = app_state.db_client
+        .delete_room(room_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(no_such_room());
+    }
+
+    app_state.chat.bus
+        .publish(ChatEvent::RoomDeleted { room_id })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: "room deleted".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_room_members(
+    Path(room_id): Path<i32>,
+    Query(query_params): Query<forum::GetRoomMembersDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let room = room_for(&app_state, user.user.id, room_id).await?;
+
+    let members = app_state.db_client
+        .get_room_members(room.id, query_params.page.unwrap_or(1), query_params.limit.unwrap_or(50))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(forum::ChatRoomMembersResponseDto {
+        status: "success".to_string(),
+        members,
+    }))
+}
+
+pub async fn join_room(
+    Path(room_id): Path<i32>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let room = room_for(&app_state, user.user.id, room_id).await?;
+
+    app_state.db_client
+        .join_room(room.id, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: "joined room".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn leave_room(
+    Path(room_id): Path<i32>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let left = app_state.db_client
+        .leave_room(room_id, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !left {
+        return Err(HttpError::bad_request("Not a member of this room"));
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: "left room".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+/// Authenticated by the `auth` layer of the forum router, so the JWT cookie and bearer token both work.
+pub async fn chat_socket(
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    open_socket(ws, app_state, user, addr, DEFAULT_ROOM_ID).await
+}
+
+pub async fn room_socket(
+    ws: WebSocketUpgrade,
+    Path(room_id): Path<i32>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    open_socket(ws, app_state, user, addr, room_id).await
+}
+
+async fn open_socket(ws: WebSocketUpgrade, app_state: Arc<AppState>, user: JWTAuthMiddeware, addr: SocketAddr, room_id: i32) -> Result<impl IntoResponse, HttpError> {
+    // Refuse the upgrade itself so the client gets a proper status code
+    let room = room_for(&app_state, user.user.id, room_id).await?;
+
+    Ok(ws.on_upgrade(move |socket| chat_session(socket, app_state, user, addr, room)))
+}
+
+async fn send_json<T: Serialize>(socket: &mut WebSocket, message: &T) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text.into())).await
+}
+
+async fn chat_session(mut socket: WebSocket, app_state: Arc<AppState>, auth: JWTAuthMiddeware, addr: SocketAddr, room: ChatRoom) {
+    let room_id = room.id;
+    // Subscribe before loading the history so nothing posted in between is lost
+    let mut events = app_state.chat.bus.subscribe();
+
+    let history = match recent_messages(&app_state, room_id, None, CHAT_HISTORY).await {
+        Ok(posts) => ServerMessage::History { room, posts },
+        Err(e) => ServerMessage::from(e),
+    };
+
+    if send_json(&mut socket, &history).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum, binary frames are not part of the protocol
+                    Some(Ok(_)) => continue,
+                };
+
+                if let Err(e) = handle_command(&app_state, &auth, addr, room_id, text.as_str()).await {
+                    let fatal = e.status == StatusCode::UNAUTHORIZED;
+                    if send_json(&mut socket, &ServerMessage::from(e)).await.is_err() || fatal {
+                        break;
+                    }
+                }
+            }
+            event = events.recv() => {
+                match event {
+                    Ok(event) if event.room_id() == room_id => {
+                        let closed = matches!(event, ChatEvent::RoomDeleted { .. });
+                        if send_json(&mut socket, &event).await.is_err() || closed {
+                            break;
+                        }
+                    }
+                    Ok(_) => continue,
+                    // A client too slow to keep up misses some messages rather than stalling everyone
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+}
+
+/// Loads the user again, the socket can outlive its session or a ban handed out since connecting.
+async fn current_user(app_state: &AppState, auth: &JWTAuthMiddeware) -> Result<User, HttpError> {
+    let session = app_state.db_client
+        .get_session(auth.session_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !session.is_some_and(|s| s.user_id == auth.user.id && s.is_active()) {
+        return Err(HttpError::unauthorized(ErrorMessage::SessionRevoked.to_string()));
+    }
+
+    app_state.db_client
+        .get_user(Some(auth.user.id), None, None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(ErrorMessage::NoSuchUser.to_string()))
+}
+
+/// `addr` is where the socket was opened from, commands don't pass `audit::audited` and are logged here.
+async fn handle_command(app_state: &AppState, auth: &JWTAuthMiddeware, addr: SocketAddr, room_id: i32, text: &str) -> Result<(), HttpError> {
+    let command: ChatCommand = serde_json::from_str(text)
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = current_user(app_state, auth).await?;
+
+    match command {
+        ChatCommand::Post { content } => {
+            let body = forum::PostChatDto { content };
+            body.validate()
+                .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+            if !policy::evaluate(&user, &app_state.env)?.allows(&Method::POST) {
+                return Err(policy::ban_error(&app_state.db_client, &user).await);
+            }
+
+            // A role change or archive since connecting takes effect right away
+            let room = room_for(app_state, user.id, room_id).await?;
+            publish_message(app_state, &room, user.id, body.content.trim()).await?;
+        }
+        ChatCommand::Delete { id } => {
+            check_roles(app_state, &user, &[UserRole::Admin, UserRole::Mod]).await?;
+            let entry = remove_message(app_state, id).await?;
+            audit::record(app_state, user.id, Some(&addr.ip().to_string()), &entry).await;
+        }
+    }
+
+    Ok(())
+}
@@ -1,30 +1,86 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use axum::{extract::{Query, Path}, middleware::from_fn, response::IntoResponse, routing::{get, put, post, delete}, Extension, Json, Router};
+use axum::{extract::{Query, Path}, http::StatusCode, middleware::from_fn, response::IntoResponse, routing::{get, put, post, delete}, Extension, Json, Router};
 use validator::Validate;
-use crate::AppState;
-use crate::{db::forum::ForumExt,
-    models::UserRole,
+use crate::{AppState, render, utils::search as search_query};
+use crate::{audit::AuditEntry,
+    db::forum::ForumExt,
+    models::{AuditAction, AuditTarget, ReplyRow, SectionRight, SectionRow, Thread, UserRole},
+    policy::{self, SectionTarget},
     dto::forum,
-    error::HttpError,
+    error::{ErrorMessage, HttpError},
     middleware::{role_check, JWTAuthMiddeware},
 };
 
+/// Levels of replies loaded below each post in the tree view unless asked otherwise.
+pub const DEFAULT_REPLY_DEPTH: u32 = 3;
+/// Replies shown per post below the top level, the rest is loaded through the post's cursor.
+pub const REPLIES_PER_POST: usize = 5;
+
 pub fn forum_handler() -> Router {
     let admin_mod_only = from_fn(|state, req, next| 
         role_check(state, req, next, vec![UserRole::Admin, UserRole::Mod]) );
+    let admin_only = from_fn(|state, req, next|
+        role_check(state, req, next, vec![UserRole::Admin]) );
 
     Router::new()
         .route("/list", get(get_sections))
+        .route("/section", post(create_section).layer(admin_only.clone()) )
+        .route("/section", delete(delete_section).layer(admin_only.clone()) )
+        .route("/section/order", put(reorder_sections).layer(admin_only.clone()) )
+        .route("/section/{s_id}/move", put(move_section).layer(admin_only.clone()) )
         .route("/section/{s_id}", get(get_threads))
+        .route("/section/{s_id}/permissions", get(super::permission::get_section_permissions).layer(admin_only.clone()) )
+        .route("/section/{s_id}/permissions/roles/{role}", put(super::permission::set_role_permissions).delete(super::permission::remove_role_permissions).layer(admin_only.clone()) )
+        .route("/section/{s_id}/permissions/groups/{group_id}", put(super::permission::set_group_permissions).delete(super::permission::remove_group_permissions).layer(admin_only.clone()) )
+        .route("/section/{s_id}/moderators", get(super::permission::get_section_moderators).layer(admin_only.clone()) )
+        .route("/section/{s_id}/moderators/{user_id}", put(super::permission::add_section_moderator).delete(super::permission::remove_section_moderator).layer(admin_only.clone()) )
+        .route("/groups", get(super::permission::get_groups).post(super::permission::create_group).layer(admin_only.clone()) )
+        .route("/groups/{group_id}", delete(super::permission::delete_group).layer(admin_only.clone()) )
+        .route("/groups/{group_id}/members", get(super::permission::get_group_members).layer(admin_only.clone()) )
+        .route("/groups/{group_id}/members/{user_id}", put(super::permission::add_group_member).delete(super::permission::remove_group_member).layer(admin_only.clone()) )
         .route("/threads", post(create_thread))
-        .route("/threads", delete(delete_thread).layer(admin_mod_only.clone()) )
+        .route("/threads", delete(delete_thread))
         .route("/threads", put(update_thread))
         .route("/threads/{thread_id}", get(get_thread))
         .route("/threads/{thread_id}", post(reply_thread))
-        .route("/threads/lock", put(lock_thread).layer(admin_mod_only.clone()) )
+        .route("/threads/lock", put(lock_thread))
+        .route("/threads/sticky", put(sticky_thread))
+        .route("/threads/announce", put(announce_thread).layer(admin_only.clone()) )
+        .route("/threads/move", put(move_thread))
+        .route("/threads/merge", post(merge_threads))
+        .route("/threads/split", post(split_thread))
         .route("/post", put(update_post))
+        .route("/post/{post_id}/revisions", get(super::revision::get_post_revisions))
+        .route("/post/{post_id}/revisions/diff", get(super::revision::get_post_diff))
+        .route("/post/{post_id}/revisions/{rev_id}/rollback", post(super::revision::rollback_post))
+        .route("/threads/{thread_id}/revisions", get(super::revision::get_thread_revisions))
+        .route("/threads/{thread_id}/revisions/diff", get(super::revision::get_thread_diff))
+        .route("/threads/{thread_id}/revisions/{rev_id}/rollback", post(super::revision::rollback_thread))
         .route("/post", delete(delete_post))
+        .route("/trash", get(super::trash::get_trash))
+        .route("/threads/{thread_id}/restore", post(super::trash::restore_thread))
+        .route("/post/{post_id}/restore", post(super::trash::restore_post))
+        .route("/reports", post(super::report::report_content))
+        .route("/reports", get(super::report::get_reports).layer(admin_mod_only.clone()) )
+        .route("/reports/{report_id}", get(super::report::get_report).layer(admin_mod_only.clone()) )
+        .route("/reports/{report_id}/claim", put(super::report::claim_report).delete(super::report::release_report).layer(admin_mod_only.clone()) )
+        .route("/reports/{report_id}/resolve", put(super::report::resolve_report).layer(admin_mod_only.clone()) )
+        .route("/reports/{report_id}/dismiss", put(super::report::dismiss_report).layer(admin_mod_only.clone()) )
+        .route("/audit", get(super::audit::get_audit_log).layer(admin_only.clone()) )
+        .route("/threads/{thread_id}/reactions", get(super::reaction::get_thread_reactions))
+        .route("/post/{post_id}/reactions", get(super::reaction::get_post_reactions))
+        .route("/post/{post_id}/reactions/users", get(super::reaction::get_reactors))
+        .route("/post/{post_id}/reactions/{emoji}", put(super::reaction::add_reaction).delete(super::reaction::remove_reaction))
+        .route("/reactions", get(super::reaction::get_reaction_set))
+        .route("/search", get(search))
+        .route("/tags/popular", get(super::hashtag::get_popular_tags))
+        .route("/tags/trending", get(super::hashtag::get_trending_tags))
+        .route("/tags/autocomplete", get(super::hashtag::autocomplete_tags))
+        .route("/tags/{tag}", put(super::hashtag::rename_tag).layer(admin_mod_only.clone()) )
+        .route("/tags/{tag}/merge", post(super::hashtag::merge_tag).layer(admin_mod_only.clone()) )
+        .route("/tags/{tag}/threads", get(super::hashtag::get_tag_threads))
+        .nest("/chat", super::chat::chat_handler())
 }
 
 pub async fn create_thread(Extension(app_state): Extension<Arc<AppState>>,
@@ -33,9 +89,18 @@ pub async fn create_thread(Extension(app_state): Extension<Arc<AppState>>,
 ) -> Result<impl IntoResponse, HttpError> {
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
     let user = &user.user;
+
+    policy::section_access(&app_state, user, SectionTarget::Section(body.section), SectionRight::CreateThread).await?;
+
+    if section_info(&app_state, body.section).await?.category {
+        return Err(HttpError::bad_request(ErrorMessage::SectionIsCategory.to_string())
+            .with_code(ErrorMessage::SectionIsCategory));
+    }
+
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
-    let hash_tags = body.hash_tags;
-    app_state.db_client.create_thread(user_id, body.section, body.title.as_str(), body.content.as_str(), &hash_tags )
+    let hash_tags: Vec<String> = body.hash_tags.iter().filter_map(|t| render::normalize_hashtag(t)).collect();
+    let content_html = render::render(&body.content, app_state.env.bbcode);
+    app_state.db_client.create_thread(user_id, body.section, body.title.as_str(), body.content.as_str(), &content_html, &hash_tags )
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -49,19 +114,26 @@ pub async fn create_thread(Extension(app_state): Extension<Arc<AppState>>,
 }
 
 pub async fn delete_thread(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
     Json(body): Json<forum::DeleteThreadDto>,
 ) -> Result<impl IntoResponse, HttpError> {
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
-    app_state.db_client.delete_thread(body.thread_id)
+    policy::section_access(&app_state, &user.user, SectionTarget::Thread(body.thread_id), SectionRight::Moderate).await?;
+    let thread = thread_info(&app_state, body.thread_id).await?;
+
+    app_state.db_client.delete_thread(body.thread_id, user.user.id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    let entry = AuditEntry::new(AuditAction::ThreadDelete, AuditTarget::Thread, body.thread_id)
+        .before(serde_json::json!({ "title": thread.title, "sectionId": thread.section_id, "author": thread.author }));
+
     let response = forum::Response {
         status: "success",
         message: "thread deleted".to_string(),
     };
 
-    Ok(Json(response))
+    Ok((Extension(entry), Json(response)))
 }
 
 
@@ -73,63 +145,400 @@ pub async fn update_thread(Extension(app_state): Extension<Arc<AppState>>,
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
     let user = &user.user;
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
-    let user_role = user.role;
 
-    if user_role != UserRole::Admin && 
-        user_role != UserRole::Mod && 
-        app_state.db_client.get_thread_author(body.thread_id).await.map_err(|e| HttpError::server_error(e.to_string()))? != user_id {
+    let access = policy::section_access(&app_state, user, SectionTarget::Thread(body.thread_id), SectionRight::View).await?;
+    let own_thread = app_state.db_client.get_thread_author(body.thread_id).await.map_err(|e| HttpError::server_error(e.to_string()))? == user_id;
+
+    // Authors can edit for as long as they may still post in the section, anyone else has to moderate it
+    if !(access.can_moderate || (own_thread && access.can_create_thread)) {
             return Err(HttpError::unauthorized("Not authorized to edit this thread"));
     }
 
-    app_state.db_client.update_thread(body.thread_id, body.title.as_str(), body.content.as_str() )
+    // Editing one's own thread is no moderator action, even for a moderator
+    let before = match own_thread {
+        true => None,
+        false => {
+            policy::moderator_action(&app_state, user).await?;
+            Some(thread_info(&app_state, body.thread_id).await?)
+        }
+    };
+
+    let content_html = render::render(&body.content, app_state.env.bbcode);
+    let changed = app_state.db_client.update_thread(body.thread_id, body.title.as_str(), body.content.as_str(), &content_html, user_id, body.reason.as_deref())
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    // A moderator edit that changed nothing is not worth an audit entry
+    let entry = before.filter(|_| changed).map(|thread| Extension(AuditEntry::new(AuditAction::ThreadEdit, AuditTarget::Thread, body.thread_id)
+        .before(serde_json::json!({ "title": thread.title, "content": thread.content }))
+        .after(serde_json::json!({ "title": body.title, "content": body.content, "reason": body.reason }))));
+
     let response = forum::Response {
         status: "success",
         message: "thread updated".to_string(),
     };
 
-    Ok(Json(response))
+    Ok((entry, Json(response)))
 
 }
 
 pub async fn get_thread(
     Path(thread_id) : Path<i64>,
-    Query(query_params): Query<forum::GetThreadsDto>,
+    Query(query_params): Query<forum::GetThreadPostsDto>,
     Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
 ) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    policy::section_access(&app_state, &user.user, SectionTarget::Thread(thread_id), SectionRight::View).await?;
 
+    let bbcode = app_state.env.bbcode;
+    let mut thread = thread_info(&app_state, thread_id).await?;
 
-    let thread = app_state.db_client.get_thread_info(thread_id)
-        .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    // Stubs of moved or merged threads always point straight at the live thread
+    let redirected_from = thread.redirect_to.map(|_| thread_id);
+    if let Some(target) = thread.redirect_to {
+        policy::section_access(&app_state, &user.user, SectionTarget::Thread(target), SectionRight::View).await?;
+        thread = thread_info(&app_state, target).await?;
+    }
+    let thread_id = thread.id;
+
+    render::fill_cached(&thread.content, &mut thread.content_html, bbcode);
+    let limit = query_params.limit.unwrap_or(10);
+
+    if query_params.view == forum::ThreadView::Flat {
+        let mut posts = app_state.db_client.get_thread(thread_id,query_params.page.unwrap_or(1),limit)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        for post in posts.iter_mut() {
+            render::fill_cached(&post.content, &mut post.content_html, bbcode);
+        }
+
+        let response = forum::GetThreadResponseDto {
+            info: thread,
+            posts,
+            redirected_from,
+        };
 
-    let posts = app_state.db_client.get_thread(thread_id,query_params.page.unwrap_or(1),query_params.limit.unwrap_or(10))
+        return Ok(Json(response).into_response());
+    }
+
+    let depth = query_params.depth.unwrap_or(DEFAULT_REPLY_DEPTH);
+    let mut rows = app_state.db_client
+        .get_reply_tree(thread_id, query_params.parent, query_params.after, limit, depth, REPLIES_PER_POST)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let response = forum::GetThreadResponseDto {
+    for row in rows.iter_mut() {
+        render::fill_cached(&row.content, &mut row.content_html, bbcode);
+    }
+
+    let (posts, next_cursor) = build_reply_tree(rows, query_params.parent, limit);
+
+    let response = forum::GetThreadTreeResponseDto {
         info: thread,
         posts,
+        next_cursor,
+        redirected_from,
     };
 
-    Ok(Json(response))
+    Ok(Json(response).into_response())
+}
+
+/// Nests the rows of `get_reply_tree` under their parents. A post with more replies than were loaded
+/// gets a cursor to the rest.
+fn build_reply_tree(rows: Vec<ReplyRow>, parent: Option<i64>, limit: usize) -> (Vec<forum::PostNode>, Option<forum::ReplyCursor>) {
+    let mut roots = Vec::new();
+    let mut children: HashMap<i64, Vec<ReplyRow>> = HashMap::new();
+
+    // Rows come ordered by depth and time, so every list below is already chronological
+    for row in rows {
+        match row.comments {
+            Some(parent_id) if row.depth > 0 => children.entry(parent_id).or_default().push(row),
+            _ => roots.push(row),
+        }
+    }
+
+    fn build(row: ReplyRow, children: &mut HashMap<i64, Vec<ReplyRow>>) -> forum::PostNode {
+        let replies = children.remove(&row.id).unwrap_or_default();
+
+        let next_cursor = (row.reply_count > replies.len() as i64).then(|| forum::ReplyCursor {
+            parent: Some(row.id),
+            after: replies.last().map(|r| r.id),
+        });
+        let reply_count = row.reply_count;
+        let replies = replies.into_iter().map(|r| build(r, children)).collect();
+
+        let deleted = row.deleted;
+        forum::PostNode {
+            post: row.into_post(),
+            deleted,
+            reply_count,
+            replies,
+            next_cursor,
+        }
+    }
+
+    // A full page may be followed by more, an extra empty page is the price for not counting
+    let next_cursor = (roots.len() == limit).then(|| forum::ReplyCursor {
+        parent,
+        after: roots.last().map(|r| r.id),
+    });
+    let posts = roots.into_iter().map(|r| build(r, &mut children)).collect();
+
+    (posts, next_cursor)
+}
+
+async fn thread_info(app_state: &AppState, thread_id: i64) -> Result<Thread, HttpError> {
+    match app_state.db_client.get_thread_info(thread_id).await {
+        Ok(thread) => Ok(thread),
+        Err(sqlx::Error::RowNotFound) => Err(HttpError::new("No such thread", StatusCode::NOT_FOUND)),
+        Err(e) => Err(HttpError::server_error(e.to_string())),
+    }
 }
 
 pub async fn lock_thread(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
     Json(body): Json<forum::LockThreadDto>,
 ) -> Result<impl IntoResponse, HttpError> {
+    policy::section_access(&app_state, &user.user, SectionTarget::Thread(body.thread_id), SectionRight::Moderate).await?;
+    let thread = thread_info(&app_state, body.thread_id).await?;
+
     app_state.db_client.lock_thread(body.thread_id, body.locked)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    let entry = AuditEntry::new(AuditAction::ThreadLock, AuditTarget::Thread, body.thread_id)
+        .before(serde_json::json!({ "locked": thread.locked }))
+        .after(serde_json::json!({ "locked": body.locked }));
+
     let response = forum::Response {
         status: "success",
         message: "thread updated".to_string(),
     };
 
-    Ok(Json(response))
+    Ok((Extension(entry), Json(response)))
+}
+
+async fn post_info(app_state: &AppState, post_id: i64) -> Result<crate::models::Post, HttpError> {
+    app_state.db_client.get_post(post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::new("No such post", StatusCode::NOT_FOUND))
+}
+
+/// Thread a moderation tool works on, stubs only point elsewhere and can't be changed.
+async fn moderated_thread(app_state: &AppState, user: &crate::models::User, thread_id: i64) -> Result<Thread, HttpError> {
+    policy::section_access(app_state, user, SectionTarget::Thread(thread_id), SectionRight::Moderate).await?;
+
+    let thread = thread_info(app_state, thread_id).await?;
+    if thread.redirect_to.is_some() {
+        return Err(HttpError::bad_request(ErrorMessage::ThreadIsRedirect.to_string())
+            .with_code(ErrorMessage::ThreadIsRedirect)
+            .with_details(serde_json::json!({ "redirectTo": thread.redirect_to })));
+    }
+
+    Ok(thread)
+}
+
+pub async fn sticky_thread(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::StickyThreadDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    let thread = moderated_thread(&app_state, &user.user, body.thread_id).await?;
+
+    app_state.db_client.set_thread_sticky(body.thread_id, body.sticky)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = AuditEntry::new(AuditAction::ThreadSticky, AuditTarget::Thread, body.thread_id)
+        .before(serde_json::json!({ "sticky": thread.sticky }))
+        .after(serde_json::json!({ "sticky": body.sticky }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "thread updated".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+/// Announcements show on top of every section, so only admins pin them.
+pub async fn announce_thread(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::AnnounceThreadDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    let thread = moderated_thread(&app_state, &user.user, body.thread_id).await?;
+
+    app_state.db_client.set_thread_announcement(body.thread_id, body.announcement)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = AuditEntry::new(AuditAction::ThreadAnnounce, AuditTarget::Thread, body.thread_id)
+        .before(serde_json::json!({ "announcement": thread.announcement }))
+        .after(serde_json::json!({ "announcement": body.announcement }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "thread updated".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+/// Moderators need the right in both sections, moving a thread takes it out of one and into the other.
+pub async fn move_thread(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::MoveThreadDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    let thread = moderated_thread(&app_state, &user.user, body.thread_id).await?;
+    policy::section_access(&app_state, &user.user, SectionTarget::Section(body.section_id), SectionRight::Moderate).await?;
+
+    if section_info(&app_state, body.section_id).await?.category {
+        return Err(HttpError::bad_request(ErrorMessage::SectionIsCategory.to_string())
+            .with_code(ErrorMessage::SectionIsCategory));
+    }
+
+    if thread.section_id == body.section_id {
+        return Err(HttpError::bad_request("The thread already is in this section"));
+    }
+
+    let leave_stub = body.leave_stub.unwrap_or(true);
+    app_state.db_client.move_thread(body.thread_id, body.section_id, leave_stub)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = AuditEntry::new(AuditAction::ThreadMove, AuditTarget::Thread, body.thread_id)
+        .before(serde_json::json!({ "sectionId": thread.section_id }))
+        .after(serde_json::json!({ "sectionId": body.section_id, "leaveStub": leave_stub }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "thread moved".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+pub async fn merge_threads(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::MergeThreadsDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    if body.thread_id == body.into {
+        return Err(HttpError::bad_request("A thread can't be merged into itself"));
+    }
+
+    moderated_thread(&app_state, &user.user, body.thread_id).await?;
+    moderated_thread(&app_state, &user.user, body.into).await?;
+
+    let posts = app_state.db_client.merge_threads(body.thread_id, body.into)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = AuditEntry::new(AuditAction::ThreadMerge, AuditTarget::Thread, body.thread_id)
+        .after(serde_json::json!({ "into": body.into, "posts": posts }));
+
+    let response = forum::Response {
+        status: "success",
+        message: format!("{} posts merged", posts),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+pub async fn split_thread(Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::SplitThreadDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    let user = &user.user;
+
+    let thread = moderated_thread(&app_state, user, body.thread_id).await?;
+    let section_id = body.section_id.unwrap_or(thread.section_id);
+
+    if section_id != thread.section_id {
+        policy::section_access(&app_state, user, SectionTarget::Section(section_id), SectionRight::Moderate).await?;
+
+        if section_info(&app_state, section_id).await?.category {
+            return Err(HttpError::bad_request(ErrorMessage::SectionIsCategory.to_string())
+                .with_code(ErrorMessage::SectionIsCategory));
+        }
+    }
+
+    let split = app_state.db_client
+        .split_thread(body.thread_id, &body.post_ids, body.title.trim(), section_id, user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::bad_request("Every post must be part of the thread"))?;
+
+    let entry = AuditEntry::new(AuditAction::ThreadSplit, AuditTarget::Thread, body.thread_id)
+        .after(serde_json::json!({
+            "postIds": body.post_ids,
+            "newThread": split.thread_id,
+            "sectionId": section_id,
+            "droppedLikes": split.dropped_likes,
+            "droppedReactions": split.dropped_reactions,
+        }));
+
+    let mut thread = thread_info(&app_state, split.thread_id).await?;
+    render::fill_cached(&thread.content, &mut thread.content_html, app_state.env.bbcode);
+
+    Ok((StatusCode::CREATED, Extension(entry), Json(forum::ThreadResponseDto {
+        status: "success".to_string(),
+        thread,
+    })))
+}
+
+/// Nests the sections under their parents. A section whose parent the user can't see is left out
+/// with everything below it.
+fn build_section_tree(rows: Vec<SectionRow>) -> Vec<forum::SectionNode> {
+    let mut roots = Vec::new();
+    let mut children: HashMap<i64, Vec<SectionRow>> = HashMap::new();
+
+    // Rows come ordered by position, so every list below is already in display order
+    for row in rows {
+        match row.parent_id {
+            Some(parent_id) => children.entry(parent_id).or_default().push(row),
+            None => roots.push(row),
+        }
+    }
+
+    fn build(row: SectionRow, children: &mut HashMap<i64, Vec<SectionRow>>) -> forum::SectionNode {
+        let mut thread_count = row.thread_count;
+        let mut post_count = row.post_count;
+        let (section, mut latest) = row.into_section();
+
+        let nodes: Vec<forum::SectionNode> = children.remove(&section.id).unwrap_or_default()
+            .into_iter()
+            .map(|r| build(r, children))
+            .collect();
+
+        for node in nodes.iter() {
+            thread_count += node.thread_count;
+            post_count += node.post_count;
+            if node.latest.as_ref().is_some_and(|l| latest.as_ref().is_none_or(|own| l.created_at > own.created_at)) {
+                latest = node.latest.clone();
+            }
+        }
+
+        forum::SectionNode {
+            section,
+            thread_count,
+            post_count,
+            latest,
+            children: nodes,
+        }
+    }
+
+    roots.into_iter().map(|r| build(r, &mut children)).collect()
+}
+
+pub(crate) async fn section_info(app_state: &AppState, s_id: i64) -> Result<crate::models::Section, HttpError> {
+    app_state.db_client.get_section_info(s_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::new(ErrorMessage::NoSuchSection.to_string(), StatusCode::NOT_FOUND)
+            .with_code(ErrorMessage::NoSuchSection))
 }
 
 pub async fn get_sections(
@@ -139,41 +548,231 @@ pub async fn get_sections(
     let user = &user.user;
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
 
-    let sections = app_state.db_client.get_sections(user_id)
+    let rows = app_state.db_client.get_sections(user_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let response = forum::GetSectionsResponseDto { sections };
+    let response = forum::GetSectionsResponseDto { sections: build_section_tree(rows) };
+
+    Ok(Json(response))
+}
+
+pub async fn create_section(Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::CreateSectionDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if let Some(parent_id) = body.parent_id {
+        section_info(&app_state, parent_id).await?;
+    }
+
+    let description = body.description.as_deref().map(str::trim).filter(|d| !d.is_empty());
+    let s_id = app_state.db_client
+        .create_section(body.name.trim(), description, body.parent_id, body.category, &body.allowed_for)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::SectionResponseDto {
+        status: "success".to_string(),
+        section: section_info(&app_state, s_id).await?,
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn move_section(Extension(app_state): Extension<Arc<AppState>>,
+    Path(s_id): Path<i64>,
+    Json(body): Json<forum::MoveSectionDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    if let Some(parent_id) = body.parent_id {
+        section_info(&app_state, parent_id).await?;
+
+        let below_itself = app_state.db_client.is_in_section_tree(parent_id, s_id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        if below_itself {
+            return Err(HttpError::bad_request("A section can't be moved below itself"));
+        }
+    }
+
+    app_state.db_client.move_section(s_id, body.parent_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::SectionResponseDto {
+        status: "success".to_string(),
+        section: section_info(&app_state, s_id).await?,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn reorder_sections(Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::ReorderSectionsDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let reordered = app_state.db_client.reorder_sections(body.parent_id, &body.order)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !reordered {
+        return Err(HttpError::bad_request("The order must list every section below the parent exactly once"));
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: "sections reordered".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn delete_section(Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::DeleteSectionDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    section_info(&app_state, body.s_id).await?;
+
+    let content = app_state.db_client.get_section_content(body.s_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(move_to) = body.move_to {
+        let target = section_info(&app_state, move_to).await?;
+
+        let below_itself = app_state.db_client.is_in_section_tree(move_to, body.s_id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        if below_itself {
+            return Err(HttpError::bad_request("Content can't be moved into the section being deleted"));
+        }
+
+        if target.category && content.threads > 0 {
+            return Err(HttpError::bad_request(ErrorMessage::SectionIsCategory.to_string())
+                .with_code(ErrorMessage::SectionIsCategory));
+        }
+    } else if !body.cascade && (content.children > 0 || content.threads > 0) {
+        return Err(HttpError::new(ErrorMessage::SectionNotEmpty.to_string(), StatusCode::CONFLICT)
+            .with_code(ErrorMessage::SectionNotEmpty)
+            .with_details(serde_json::json!(content)));
+    }
+
+    if body.cascade {
+        app_state.db_client.delete_section_tree(body.s_id).await
+    } else {
+        app_state.db_client.delete_section(body.s_id, body.move_to).await
+    }
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: "section deleted".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn search(
+    Query(query_params): Query<forum::SearchDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let Some(search) = query_params.to_forum_search() else {
+        return Err(HttpError::bad_request(ErrorMessage::EmptySearchQuery.to_string())
+            .with_code(ErrorMessage::EmptySearchQuery));
+    };
+
+    let cursor = match query_params.cursor.as_deref() {
+        Some(cursor) => Some(search_query::decode_cursor(cursor)
+            .ok_or_else(|| HttpError::bad_request(ErrorMessage::InvalidCursor.to_string()).with_code(ErrorMessage::InvalidCursor))?),
+        None => None,
+    };
+
+    let limit = query_params.limit.unwrap_or(20);
+
+    let mut results = app_state.db_client
+        .search(user.user.id, &search, cursor.as_ref(), limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // A short page means there is nothing left
+    let next_cursor = if results.len() == limit {
+        results.last().map(|hit| search_query::encode_cursor(&hit.cursor()))
+    } else {
+        None
+    };
+
+    for hit in results.iter_mut() {
+        hit.snippet = search_query::highlight(&hit.snippet);
+    }
+
+    let response = forum::SearchResponseDto {
+        status: "success".to_string(),
+        results,
+        next_cursor,
+    };
 
     Ok(Json(response))
 }
 
 pub async fn get_threads(
-    Path(thread_id) : Path<i64>,
+    Path(s_id) : Path<i64>,
     Query(query_params): Query<forum::GetThreadsDto>,
     Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
 ) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    policy::section_access(&app_state, &user.user, SectionTarget::Section(s_id), SectionRight::View).await?;
 
-    let threads = app_state.db_client.get_section(thread_id, query_params.page.unwrap_or(1), query_params.limit.unwrap_or(10))
+    let page = query_params.page.unwrap_or(1);
+    let threads = app_state.db_client.get_section(s_id, page, query_params.limit.unwrap_or(10))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let response = forum::GetSectionResponseDto { threads };
+    let announcements = match page {
+        1 => app_state.db_client.get_announcements(user.user.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?,
+        _ => Vec::new(),
+    };
+
+    let response = forum::GetSectionResponseDto { announcements, threads };
 
     Ok(Json(response))
 }
 
 pub async fn reply_thread(Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddeware>,
+    Path(thread_id) : Path<i64>,
     Json(body): Json<forum::ReplyThreadDto>,
 ) -> Result<impl IntoResponse, HttpError> {
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
     let user = &user.user;
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
 
-    app_state.db_client.add_post(user_id, body.t_id, body.content.as_str(), body.post_id)
+    policy::section_access(&app_state, user, SectionTarget::Thread(thread_id), SectionRight::Reply).await?;
+
+    if thread_info(&app_state, thread_id).await?.locked {
+        return Err(HttpError::forbidden(ErrorMessage::ThreadLocked.to_string())
+            .with_code(ErrorMessage::ThreadLocked));
+    }
+
+    let content_html = render::render(&body.content, app_state.env.bbcode);
+    let post = app_state.db_client.add_post(user_id, thread_id, body.content.as_str(), &content_html, body.post_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if post.is_none() {
+        return Err(HttpError::bad_request("The post replied to is not part of this thread"));
+    }
+
     let response = forum::Response {
         status: "success",
         message: "post added".to_string(),
@@ -190,24 +789,37 @@ pub async fn update_post(Extension(app_state): Extension<Arc<AppState>>,
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
     let user = &user.user;
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
-    let user_role = user.role;
 
-    if user_role != UserRole::Admin && 
-        user_role != UserRole::Mod && 
-        app_state.db_client.get_post_author(body.post_id).await.map_err(|e| HttpError::server_error(e.to_string()))?.unwrap_or_default() != user_id {
+    let access = policy::section_access(&app_state, user, SectionTarget::Post(body.post_id), SectionRight::View).await?;
+    let own_post = app_state.db_client.get_post_author(body.post_id).await.map_err(|e| HttpError::server_error(e.to_string()))?.unwrap_or_default() == user_id;
+
+    if !(access.can_moderate || (own_post && access.can_reply)) {
             return Err(HttpError::unauthorized("Not authorized to edit this thread"));
     }
 
-    app_state.db_client.update_post(body.post_id, body.content.as_str())
+    let before = match own_post {
+        true => None,
+        false => {
+            policy::moderator_action(&app_state, user).await?;
+            Some(post_info(&app_state, body.post_id).await?)
+        }
+    };
+
+    let content_html = render::render(&body.content, app_state.env.bbcode);
+    let changed = app_state.db_client.update_post(body.post_id, body.content.as_str(), &content_html, user_id, body.reason.as_deref())
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    let entry = before.filter(|_| changed).map(|post| Extension(AuditEntry::new(AuditAction::PostEdit, AuditTarget::Post, body.post_id)
+        .before(serde_json::json!({ "content": post.content, "author": post.author }))
+        .after(serde_json::json!({ "content": body.content, "reason": body.reason }))));
+
     let response = forum::Response {
         status: "success",
         message: "post updated".to_string(),
     };
 
-    Ok(Json(response))
+    Ok((entry, Json(response)))
 
 }
 
@@ -218,28 +830,214 @@ pub async fn delete_post(Extension(app_state): Extension<Arc<AppState>>,
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
     let user = &user.user;
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
-    let user_role = user.role;
 
-    if user_role != UserRole::Admin && 
-        user_role != UserRole::Mod && 
-        app_state.db_client.get_post_author(body.post_id).await.map_err(|e| HttpError::server_error(e.to_string()))?.unwrap_or_default() != user_id {
+    let access = policy::section_access(&app_state, user, SectionTarget::Post(body.post_id), SectionRight::View).await?;
+    let own_post = app_state.db_client.get_post_author(body.post_id).await.map_err(|e| HttpError::server_error(e.to_string()))?.unwrap_or_default() == user_id;
+
+    if !(access.can_moderate || (own_post && access.can_reply)) {
             return Err(HttpError::unauthorized("Not authorized to edit this thread"));
     } 
 
-    if app_state.db_client.posts_since(body.post_id).await.map_err(|e| HttpError::server_error(e.to_string()))? != 0 {
-        return Err(HttpError::answered_post_deletion("Cannot delete posts that have answers"));
-    }
+    let before = match own_post {
+        true => None,
+        false => {
+            policy::moderator_action(&app_state, user).await?;
+            Some(post_info(&app_state, body.post_id).await?)
+        }
+    };
 
-    app_state.db_client.delete_post(body.post_id)
+    // Replies stay, the post is shown as a placeholder above them until it is purged
+    app_state.db_client.delete_post(body.post_id, user_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    let entry = before.map(|post| Extension(AuditEntry::new(AuditAction::PostDelete, AuditTarget::Post, body.post_id)
+        .before(serde_json::json!({ "content": post.content, "author": post.author, "threadId": post.topic }))));
+
     let response = forum::Response {
         status: "success",
         message: "post updated".to_string(),
     };
 
-    Ok(Json(response))
+    Ok((entry, Json(response)))
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::*;
+
+    fn reply(id: i64, comments: Option<i64>, depth: i32, reply_count: i64) -> ReplyRow {
+        ReplyRow {
+            id,
+            content: format!("post {}", id),
+            author: None,
+            topic: 1,
+            comments,
+            created_at: Utc::now(),
+            modified_at: None,
+            likes: 0,
+            content_html: None,
+            edit_count: 0,
+            deleted: false,
+            depth,
+            reply_count,
+        }
+    }
+
+    fn section(id: i64, parent_id: Option<i64>, threads: i64, posts: i64, latest_at: Option<DateTime<Utc>>) -> SectionRow {
+        SectionRow {
+            id,
+            name: format!("section {}", id),
+            description: None,
+            parent_id,
+            position: 0,
+            category: false,
+            thread_count: threads,
+            post_count: posts,
+            latest_thread_id: latest_at.map(|_| id * 100),
+            latest_post_id: None,
+            latest_title: latest_at.map(|_| format!("thread in {}", id)),
+            latest_author: None,
+            latest_author_name: None,
+            latest_at,
+        }
+    }
+
+    fn ids(nodes: &[forum::PostNode]) -> Vec<i64> {
+        nodes.iter().map(|n| n.post.id).collect()
+    }
+
+    #[test]
+    fn nests_replies_under_their_parents() {
+        let rows = vec![
+            reply(1, None, 0, 2),
+            reply(4, None, 0, 0),
+            reply(2, Some(1), 1, 1),
+            reply(3, Some(1), 1, 0),
+            reply(5, Some(2), 2, 0),
+        ];
+
+        let (posts, _) = build_reply_tree(rows, None, 10);
+
+        assert_eq!(ids(&posts), vec![1, 4]);
+        assert_eq!(ids(&posts[0].replies), vec![2, 3]);
+        assert_eq!(ids(&posts[0].replies[0].replies), vec![5]);
+        assert!(posts[1].replies.is_empty());
+    }
+
+    #[test]
+    fn rows_below_a_cursor_start_at_depth_zero() {
+        // Loading the rest of post 1's replies, they still name it as their parent
+        let rows = vec![reply(7, Some(1), 0, 1), reply(8, Some(7), 1, 0)];
+
+        let (posts, next_cursor) = build_reply_tree(rows, Some(1), 10);
+
+        assert_eq!(ids(&posts), vec![7]);
+        assert_eq!(ids(&posts[0].replies), vec![8]);
+        assert!(next_cursor.is_none());
+    }
+
+    #[test]
+    fn posts_with_replies_left_out_get_a_cursor() {
+        let mut rows = vec![reply(1, None, 0, REPLIES_PER_POST as i64 + 3), reply(2, None, 0, 1)];
+        rows.extend((0..REPLIES_PER_POST as i64).map(|i| reply(10 + i, Some(1), 1, 0)));
+        // Replies below the last loaded level are counted but not loaded
+        let last = 10 + REPLIES_PER_POST as i64 - 1;
+        rows[REPLIES_PER_POST + 1].reply_count = 4;
+
+        let (posts, _) = build_reply_tree(rows, None, 10);
+
+        let cursor = posts[0].next_cursor.as_ref().expect("cursor for the rest of post 1");
+        assert_eq!((cursor.parent, cursor.after), (Some(1), Some(last)));
+        assert_eq!(posts[0].reply_count, REPLIES_PER_POST as i64 + 3);
+
+        let cursor = posts[0].replies[REPLIES_PER_POST - 1].next_cursor.as_ref().expect("cursor below the last level");
+        assert_eq!((cursor.parent, cursor.after), (Some(last), None));
+
+        assert!(posts[0].replies[0].next_cursor.is_none());
+        let cursor = posts[1].next_cursor.as_ref().expect("cursor for post 2");
+        assert_eq!((cursor.parent, cursor.after), (Some(2), None));
+    }
+
+    #[test]
+    fn a_full_page_gets_a_cursor() {
+        let rows = || vec![reply(1, None, 0, 0), reply(2, None, 0, 0)];
+
+        let (_, next_cursor) = build_reply_tree(rows(), None, 2);
+        let cursor = next_cursor.expect("cursor after a full page");
+        assert_eq!((cursor.parent, cursor.after), (None, Some(2)));
+
+        let (_, next_cursor) = build_reply_tree(rows(), None, 3);
+        assert!(next_cursor.is_none());
+    }
+
+    #[test]
+    fn deleted_posts_keep_their_place() {
+        let mut placeholder = reply(1, None, 0, 1);
+        placeholder.deleted = true;
+
+        let (posts, _) = build_reply_tree(vec![placeholder, reply(2, Some(1), 1, 0)], None, 10);
+
+        assert!(posts[0].deleted);
+        assert!(!posts[0].replies[0].deleted);
+    }
+
+    #[test]
+    fn counts_roll_up_into_the_parent() {
+        let rows = vec![
+            section(1, None, 2, 10, None),
+            section(4, None, 1, 1, None),
+            section(2, Some(1), 3, 20, None),
+            section(3, Some(2), 4, 30, None),
+        ];
+
+        let tree = build_section_tree(rows);
+
+        assert_eq!(tree.iter().map(|n| n.section.id).collect::<Vec<_>>(), vec![1, 4]);
+        assert_eq!((tree[0].thread_count, tree[0].post_count), (9, 60));
+        assert_eq!((tree[0].children[0].thread_count, tree[0].children[0].post_count), (7, 50));
+        assert_eq!((tree[1].thread_count, tree[1].post_count), (1, 1));
+    }
+
+    #[test]
+    fn latest_is_the_newest_below() {
+        let now = Utc::now();
+        let rows = vec![
+            section(1, None, 1, 0, Some(now - Duration::hours(2))),
+            section(2, Some(1), 1, 0, Some(now - Duration::hours(3))),
+            section(3, Some(1), 1, 0, None),
+            section(4, Some(3), 1, 0, Some(now)),
+            section(5, None, 0, 0, None),
+            section(6, Some(5), 1, 0, Some(now - Duration::hours(1))),
+        ];
+
+        let tree = build_section_tree(rows);
+
+        let latest = |node: &forum::SectionNode| node.latest.as_ref().map(|l| l.thread_id);
+        assert_eq!(latest(&tree[0]), Some(400));
+        assert_eq!(latest(&tree[0].children[0]), Some(200));
+        assert_eq!(latest(&tree[0].children[1]), Some(400));
+        assert_eq!(latest(&tree[1]), Some(600));
+    }
+
+    #[test]
+    fn sections_below_a_hidden_parent_are_dropped() {
+        // The user can't see section 9, so it isn't among the rows
+        let rows = vec![
+            section(1, None, 1, 1, None),
+            section(2, Some(9), 5, 5, Some(Utc::now())),
+            section(3, Some(2), 5, 5, None),
+        ];
+
+        let tree = build_section_tree(rows);
+
+        assert_eq!(tree.len(), 1);
+        assert!(tree[0].children.is_empty());
+        assert_eq!((tree[0].thread_count, tree[0].post_count), (1, 1));
+        assert!(tree[0].latest.is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{db::hashtag::HashtagExt,
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    render,
+    AppState};
+
+/// Window of the trending tags unless asked otherwise.
+pub const DEFAULT_TRENDING_DAYS: u32 = 7;
+
+fn no_such_tag() -> HttpError {
+    HttpError::new(ErrorMessage::NoSuchTag.to_string(), StatusCode::NOT_FOUND)
+        .with_code(ErrorMessage::NoSuchTag)
+}
+
+/// A tag in a path or body that could never have been stored can't exist either.
+fn normalize(tag: &str) -> Result<String, HttpError> {
+    render::normalize_hashtag(tag).ok_or_else(no_such_tag)
+}
+
+pub async fn get_tag_threads(
+    Path(tag): Path<String>,
+    Query(query_params): Query<forum::GetThreadsDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let tag = normalize(&tag)?;
+
+    let mut threads = app_state.db_client
+        .get_tag_threads(user.user.id, &tag, query_params.page.unwrap_or(1), query_params.limit.unwrap_or(10))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    for thread in threads.iter_mut() {
+        render::fill_cached(&thread.content, &mut thread.content_html, app_state.env.bbcode);
+    }
+
+    let response = forum::TagThreadsResponseDto {
+        status: "success".to_string(),
+        tag,
+        threads,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_popular_tags(
+    Query(query_params): Query<forum::GetTagStatsDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let tags = app_state.db_client
+        .get_popular_tags(user.user.id, query_params.days, query_params.limit.unwrap_or(50))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::TagCountsResponseDto {
+        status: "success".to_string(),
+        tags,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_trending_tags(
+    Query(query_params): Query<forum::GetTagStatsDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let days = query_params.days.unwrap_or(DEFAULT_TRENDING_DAYS);
+
+    let tags = app_state.db_client
+        .get_trending_tags(user.user.id, days, query_params.limit.unwrap_or(10))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::TrendingTagsResponseDto {
+        status: "success".to_string(),
+        days,
+        tags,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn autocomplete_tags(
+    Query(query_params): Query<forum::AutocompleteTagsDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    // A prefix that can't start a tag simply has no completions
+    let tags = match render::normalize_hashtag(&query_params.prefix) {
+        Some(prefix) => app_state.db_client
+            .autocomplete_tags(user.user.id, &prefix, query_params.limit.unwrap_or(10))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?,
+        None => Vec::new(),
+    };
+
+    let response = forum::TagCountsResponseDto {
+        status: "success".to_string(),
+        tags,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn rename_tag(
+    Path(tag): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::RenameTagDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let from = normalize(&tag)?;
+    let to = normalize(&body.name)?;
+
+    if from == to {
+        return Err(HttpError::bad_request("A tag can't be renamed to itself"));
+    }
+
+    // Renaming onto a tag in use would silently merge the two, that has its own endpoint
+    if app_state.db_client.tag_exists(&to).await.map_err(|e| HttpError::server_error(e.to_string()))? {
+        return Err(HttpError::new(ErrorMessage::TagAlreadyExists.to_string(), StatusCode::CONFLICT)
+            .with_code(ErrorMessage::TagAlreadyExists));
+    }
+
+    let threads = app_state.db_client
+        .merge_tags(&from, &to)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if threads == 0 {
+        return Err(no_such_tag());
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: format!("tag renamed on {} threads", threads),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn merge_tag(
+    Path(tag): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::MergeTagDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let from = normalize(&tag)?;
+    let into = normalize(&body.into)?;
+
+    if from == into {
+        return Err(HttpError::bad_request("A tag can't be merged into itself"));
+    }
+
+    if !app_state.db_client.tag_exists(&into).await.map_err(|e| HttpError::server_error(e.to_string()))? {
+        return Err(no_such_tag());
+    }
+
+    let threads = app_state.db_client
+        .merge_tags(&from, &into)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if threads == 0 {
+        return Err(no_such_tag());
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: format!("tag merged on {} threads", threads),
+    };
+
+    Ok(Json(response))
+}
@@ -1,3 +1,14 @@
 pub mod auth;
 pub mod user;
 pub mod forum;
+pub mod oidc;
+pub mod avatar;
+pub mod account;
+pub mod chat;
+pub mod reaction;
+pub mod hashtag;
+pub mod permission;
+pub mod revision;
+pub mod trash;
+pub mod report;
+pub mod audit;
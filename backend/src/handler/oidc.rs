@@ -0,0 +1,338 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::{header, HeaderMap}, response::{IntoResponse, Redirect}, routing::get, Extension, Json, Router};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use chrono::{Duration, Utc};
+use validator::Validate;
+
+use crate::{db::{oidc::OidcExt, two_factor::TwoFactorExt, user::UserExt},
+    config::OidcProvider,
+    dto::user,
+    error::{ErrorMessage, HttpError},
+    handler::auth::{session_cookies, start_session, TWO_FACTOR_PENDING_MINUTES},
+    middleware::JWTAuthMiddeware,
+    policy,
+    utils::{oidc, password, token}, AppState};
+
+const STATE_LIFETIME_MINUTES: i64 = 10;
+const STATE_COOKIE: &str = "oidc_state";
+
+pub fn oidc_handler() -> Router {
+    Router::new()
+        .route("/providers", get(get_providers))
+        .route("/{provider}/login", get(oidc_login))
+        .route("/{provider}/callback", get(oidc_callback))
+}
+
+fn find_provider<'a>(app_state: &'a AppState, name: &str) -> Result<&'a OidcProvider, HttpError> {
+    app_state.env.oidc_provider(name)
+        .ok_or(HttpError::bad_request(ErrorMessage::UnknownProvider.to_string())
+            .with_code(ErrorMessage::UnknownProvider))
+}
+
+fn redirect_uri(app_state: &AppState, provider: &OidcProvider) -> String {
+    format!("{}/auth/oidc/{}/callback", app_state.env.api_url.trim_end_matches('/'), provider.name)
+}
+
+/// The state also goes into a cookie, so the callback only completes in the browser that started the flow.
+/// Without it anyone could hand their own callback URL to a victim and log them in as, or link, someone else.
+fn state_cookie(state: &str, max_age: time::Duration) -> HeaderMap {
+    let cookie = Cookie::build((STATE_COOKIE, state.to_owned()))
+        .path("/")
+        .max_age(max_age)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .build();
+
+    let mut headers = HeaderMap::new();
+    headers.append(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+    headers
+}
+
+fn check_state_cookie(jar: &CookieJar, state: &str) -> Result<(), HttpError> {
+    match jar.get(STATE_COOKIE) {
+        Some(cookie) if !state.is_empty() && cookie.value() == state => Ok(()),
+        _ => Err(HttpError::bad_request(ErrorMessage::InvalidOidcState.to_string())
+            .with_code(ErrorMessage::InvalidOidcState)),
+    }
+}
+
+/// Stores a fresh state, nonce and PKCE verifier and returns the provider's authorization URL
+/// along with the cookie binding the state to the browser.
+async fn begin_authorization(app_state: &AppState, provider: &OidcProvider, link_user: Option<uuid::Uuid>) -> Result<(String, HeaderMap), HttpError> {
+    let discovery = oidc::discover(&app_state.http_client, provider).await?;
+
+    let state = oidc::random_token();
+    let nonce = oidc::random_token();
+    let pkce = oidc::pkce();
+
+    app_state.db_client
+        .save_oidc_state(&state, &provider.name, &nonce, &pkce.verifier, link_user,
+            Utc::now() + Duration::minutes(STATE_LIFETIME_MINUTES))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let url = oidc::authorization_url(&discovery, provider, &redirect_uri(app_state, provider), &state, &nonce, &pkce.challenge)?;
+
+    Ok((url, state_cookie(&state, time::Duration::minutes(STATE_LIFETIME_MINUTES))))
+}
+
+pub async fn get_providers(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let providers = app_state.env.oidc_providers.iter()
+        .map(|p| p.name.clone())
+        .collect();
+
+    Ok(Json(user::OidcProvidersResponseDto { providers }))
+}
+
+pub async fn oidc_login(
+    Path(provider): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let provider = find_provider(&app_state, &provider)?;
+
+    let (url, cookie) = begin_authorization(&app_state, provider, None).await?;
+
+    Ok((cookie, Redirect::to(&url)))
+}
+
+pub async fn oidc_callback(
+    Path(provider): Path<String>,
+    Query(query_params): Query<user::OidcCallbackQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let provider = find_provider(&app_state, &provider)?;
+
+    // Checked before the state is taken, a forged callback must not use up the real one
+    check_state_cookie(&jar, &query_params.state)?;
+
+    let state = app_state.db_client
+        .take_oidc_state(&query_params.state)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let state = match state {
+        Some(state) if state.provider == provider.name && state.expires_at > Utc::now() => state,
+        _ => return Err(HttpError::bad_request(ErrorMessage::InvalidOidcState.to_string())
+            .with_code(ErrorMessage::InvalidOidcState)),
+    };
+
+    let discovery = oidc::discover(&app_state.http_client, provider).await?;
+    let id_token = oidc::exchange_code(&app_state.http_client, &discovery, provider,
+        &query_params.code, &redirect_uri(&app_state, provider), &state.code_verifier).await?;
+    let claims = oidc::validate_id_token(&app_state.http_client, &discovery, provider, &id_token, &state.nonce).await?;
+
+    let identity = app_state.db_client
+        .get_identity(&provider.name, &claims.sub)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(user_id) = state.link_user {
+        match identity {
+            Some(identity) if identity.user_id != user_id => {
+                return Err(HttpError::unique_constraint_violation(ErrorMessage::IdentityAlreadyLinked.to_string())
+                    .with_code(ErrorMessage::IdentityAlreadyLinked));
+            }
+            Some(_) => (),
+            None => {
+                app_state.db_client
+                    .link_identity(user_id, &provider.name, &claims.sub, claims.email.as_deref())
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?;
+            }
+        }
+
+        return Ok((state_cookie("", time::Duration::ZERO), Redirect::to(&format!("{}/settings", app_state.env.host_url))).into_response());
+    }
+
+    let user_id = match identity {
+        Some(identity) => identity.user_id,
+        None => find_or_create_user(&app_state, provider, &claims).await?,
+    };
+
+    let user = app_state.db_client
+        .get_user(Some(user_id), None, None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(ErrorMessage::NoSuchUser.to_string()))?;
+
+    policy::evaluate(&user, &app_state.env)?;
+
+    // The provider replaces the password, not the second factor
+    let totp = app_state.db_client
+        .get_totp(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if totp.is_some_and(|totp| totp.enabled) {
+        let pending_token = token::create_pending_token(
+            &user.id.to_string(),
+            app_state.env.jwt_secret.as_bytes(),
+            TWO_FACTOR_PENDING_MINUTES
+        )
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        return Ok((state_cookie("", time::Duration::ZERO), Redirect::to(&format!("{}/login/2fa#pending_token={}", app_state.env.host_url, pending_token))).into_response());
+    }
+
+    let (token, refresh_token) = start_session(&app_state, user.id).await?;
+    let headers = session_cookies(&app_state, &token, &refresh_token);
+
+    let mut response = Redirect::to(&app_state.env.host_url).into_response();
+    response.headers_mut().extend(headers);
+    response.headers_mut().extend(state_cookie("", time::Duration::ZERO));
+
+    Ok(response)
+}
+
+/// Links the identity to an account with the same verified email when the provider is trusted,
+/// otherwise registers a new account for it.
+async fn find_or_create_user(app_state: &AppState, provider: &OidcProvider, claims: &oidc::IdTokenClaims) -> Result<uuid::Uuid, HttpError> {
+    let email = claims.email.as_deref()
+        .ok_or(HttpError::bad_request("The provider did not share an email address"))?;
+    let email_verified = claims.email_verified.unwrap_or(false);
+
+    let existing = app_state.db_client
+        .get_user(None, None, Some(email), None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(existing) = existing {
+        if !(provider.trust_email && email_verified) {
+            return Err(HttpError::unique_constraint_violation(ErrorMessage::EmailAlreadyExists.to_string())
+                .with_code(ErrorMessage::EmailAlreadyExists));
+        }
+
+        app_state.db_client
+            .link_identity(existing.id, &provider.name, &claims.sub, Some(email))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        return Ok(existing.id);
+    }
+
+    let name = available_name(app_state, claims, email).await?;
+
+    // Nobody knows this password, the account can set one through forgot-password
+    let hash_password = password::hash(oidc::random_token())
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state.db_client
+        .create_oidc_user(&name, email, &hash_password, email_verified, &provider.name, &claims.sub)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))
+}
+
+async fn available_name(app_state: &AppState, claims: &oidc::IdTokenClaims, email: &str) -> Result<String, HttpError> {
+    let base: String = claims.preferred_username.as_deref()
+        .or(claims.name.as_deref())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email))
+        .chars()
+        .take(90)
+        .collect();
+
+    let mut name = base.clone();
+    for _ in 0..5 {
+        let taken = app_state.db_client
+            .get_user(None, Some(&name), None, None)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        if taken.is_none() {
+            return Ok(name);
+        }
+
+        name = format!("{}_{}", base, &uuid::Uuid::new_v4().simple().to_string()[..6]);
+    }
+
+    Err(HttpError::unique_constraint_violation("Could not find a free user name"))
+}
+
+pub async fn get_identities(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let identities = app_state.db_client
+        .get_user_identities(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(user::UserIdentitiesResponseDto { identities }))
+}
+
+pub async fn link_identity(
+    Path(provider): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let provider = find_provider(&app_state, &provider)?;
+
+    let (authorization_url, cookie) = begin_authorization(&app_state, provider, Some(user.user.id)).await?;
+
+    Ok((cookie, Json(user::OidcLinkResponseDto {
+        status: "success".to_string(),
+        authorization_url,
+    })))
+}
+
+pub async fn unlink_identity(
+    Path(provider): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let removed = app_state.db_client
+        .unlink_identity(user.user.id, &provider)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !removed {
+        return Err(HttpError::bad_request(ErrorMessage::UnknownProvider.to_string())
+            .with_code(ErrorMessage::UnknownProvider));
+    }
+
+    Ok(Json(user::Response {
+        status: "success",
+        message: "provider unlinked".to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jar(cookie: Option<&str>) -> CookieJar {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = cookie {
+            headers.insert(header::COOKIE, format!("{}={}", STATE_COOKIE, value).parse().unwrap());
+        }
+        CookieJar::from_headers(&headers)
+    }
+
+    #[test]
+    fn state_must_match_the_cookie() {
+        assert!(check_state_cookie(&jar(Some("abc")), "abc").is_ok());
+
+        for (cookie, state) in [(None, "abc"), (Some("abc"), "abd"), (Some("other"), "abc"), (Some(""), "")] {
+            let err = check_state_cookie(&jar(cookie), state).unwrap_err();
+            assert_eq!(err.code.as_deref(), Some(ErrorMessage::InvalidOidcState.to_string().as_str()));
+        }
+    }
+
+    #[test]
+    fn state_cookie_is_lax_and_http_only() {
+        let headers = state_cookie("abc", time::Duration::minutes(STATE_LIFETIME_MINUTES));
+        let cookie = Cookie::parse(headers.get(header::SET_COOKIE).unwrap().to_str().unwrap().to_owned()).unwrap();
+
+        assert_eq!(cookie.name(), STATE_COOKIE);
+        assert_eq!(cookie.value(), "abc");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.max_age(), Some(time::Duration::minutes(STATE_LIFETIME_MINUTES)));
+    }
+}
@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{audit::AuditEntry,
+    db::permission::PermissionExt,
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    models::{AuditAction, AuditTarget, UserGroup, UserRole},
+    AppState};
+
+use super::forum::section_info;
+
+async fn group_info(app_state: &AppState, group_id: i32) -> Result<UserGroup, HttpError> {
+    app_state.db_client.get_group(group_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(no_such_group)
+}
+
+fn no_such_group() -> HttpError {
+    HttpError::new(ErrorMessage::NoSuchGroup.to_string(), StatusCode::NOT_FOUND)
+        .with_code(ErrorMessage::NoSuchGroup)
+}
+
+pub async fn get_section_permissions(
+    Path(s_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    let roles = app_state.db_client.get_role_rights(s_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let groups = app_state.db_client.get_group_rights(s_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::SectionPermissionsResponseDto {
+        status: "success".to_string(),
+        roles,
+        groups,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn set_role_permissions(
+    Path((s_id, role)): Path<(i64, UserRole)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::SectionRightsDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    section_info(&app_state, s_id).await?;
+
+    app_state.db_client.set_role_rights(s_id, role, &body.to_rights())
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: "section permissions updated".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+/// Without an entry the role loses every right in the section, viewing included.
+pub async fn remove_role_permissions(
+    Path((s_id, role)): Path<(i64, UserRole)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    let removed = app_state.db_client.remove_role_rights(s_id, role)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: if removed { "section permissions removed" } else { "no permissions for this role" }.to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn set_group_permissions(
+    Path((s_id, group_id)): Path<(i64, i32)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::SectionRightsDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    section_info(&app_state, s_id).await?;
+    group_info(&app_state, group_id).await?;
+
+    app_state.db_client.set_group_rights(s_id, group_id, &body.to_rights())
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: "section permissions updated".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn remove_group_permissions(
+    Path((s_id, group_id)): Path<(i64, i32)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    let removed = app_state.db_client.remove_group_rights(s_id, group_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: if removed { "section permissions removed" } else { "no permissions for this group" }.to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_groups(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let groups = app_state.db_client.get_groups()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::GroupListResponseDto {
+        status: "success".to_string(),
+        groups,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn create_group(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<forum::CreateGroupDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let description = body.description.as_deref().map(str::trim).filter(|d| !d.is_empty());
+
+    let group_id = match app_state.db_client.create_group(body.name.trim(), description).await {
+        Ok(group_id) => group_id,
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return Err(HttpError::unique_constraint_violation(ErrorMessage::GroupAlreadyExists.to_string())
+                .with_code(ErrorMessage::GroupAlreadyExists));
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    let group = group_info(&app_state, group_id).await?;
+
+    Ok((StatusCode::CREATED, Json(forum::GroupResponseDto {
+        status: "success".to_string(),
+        group,
+    })))
+}
+
+pub async fn delete_group(
+    Path(group_id): Path<i32>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state.db_client.delete_group(group_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(no_such_group());
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: "group deleted".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_group_members(
+    Path(group_id): Path<i32>,
+    Query(query_params): Query<forum::GetGroupMembersDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    group_info(&app_state, group_id).await?;
+
+    let members = app_state.db_client
+        .get_group_members(group_id, query_params.page.unwrap_or(1), query_params.limit.unwrap_or(50))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::GroupMembersResponseDto {
+        status: "success".to_string(),
+        members,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn add_group_member(
+    Path((group_id, user_id)): Path<(i32, uuid::Uuid)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    group_info(&app_state, group_id).await?;
+
+    let added = match app_state.db_client.add_group_member(group_id, user_id).await {
+        Ok(added) => added,
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            return Err(HttpError::new(ErrorMessage::NoSuchUser.to_string(), StatusCode::NOT_FOUND)
+                .with_code(ErrorMessage::NoSuchUser));
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    let response = forum::Response {
+        status: "success",
+        message: if added { "member added" } else { "already a member" }.to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn remove_group_member(
+    Path((group_id, user_id)): Path<(i32, uuid::Uuid)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    group_info(&app_state, group_id).await?;
+
+    let removed = app_state.db_client.remove_group_member(group_id, user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: if removed { "member removed" } else { "not a member" }.to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_section_moderators(
+    Path(s_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    let moderators = app_state.db_client.get_section_moderators(s_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::SectionModeratorsResponseDto {
+        status: "success".to_string(),
+        moderators,
+    };
+
+    Ok(Json(response))
+}
+
+/// Moderators get every right in the section, seeing it included, but not in its subsections.
+pub async fn add_section_moderator(
+    Path((s_id, user_id)): Path<(i64, uuid::Uuid)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    let added = match app_state.db_client.add_section_moderator(s_id, user_id, user.user.id).await {
+        Ok(added) => added,
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            return Err(HttpError::new(ErrorMessage::NoSuchUser.to_string(), StatusCode::NOT_FOUND)
+                .with_code(ErrorMessage::NoSuchUser));
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    // Nothing changed when the user already moderated the section
+    let entry = added.then(|| Extension(AuditEntry::new(AuditAction::ModeratorAdd, AuditTarget::Section, s_id)
+        .after(serde_json::json!({ "userId": user_id }))));
+
+    let response = forum::Response {
+        status: "success",
+        message: if added { "moderator added" } else { "already a moderator" }.to_string(),
+    };
+
+    Ok((entry, Json(response)))
+}
+
+pub async fn remove_section_moderator(
+    Path((s_id, user_id)): Path<(i64, uuid::Uuid)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    section_info(&app_state, s_id).await?;
+
+    let removed = app_state.db_client.remove_section_moderator(s_id, user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = removed.then(|| Extension(AuditEntry::new(AuditAction::ModeratorRemove, AuditTarget::Section, s_id)
+        .before(serde_json::json!({ "userId": user_id }))));
+
+    let response = forum::Response {
+        status: "success",
+        message: if removed { "moderator removed" } else { "not a moderator" }.to_string(),
+    };
+
+    Ok((entry, Json(response)))
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{db::{forum::ForumExt, reaction::ReactionExt},
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    models::SectionRight,
+    policy::{self, SectionTarget},
+    AppState};
+
+pub async fn get_reaction_set(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let response = forum::ReactionSetResponseDto {
+        status: "success".to_string(),
+        reactions: app_state.env.reactions.clone(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn add_reaction(
+    Path((post_id, emoji)): Path<(i64, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    if !app_state.env.reactions.contains(&emoji) {
+        return Err(HttpError::bad_request(ErrorMessage::UnknownReaction.to_string())
+            .with_code(ErrorMessage::UnknownReaction)
+            .with_details(serde_json::json!({ "allowed": app_state.env.reactions })));
+    }
+
+    policy::section_access(&app_state, &user.user, SectionTarget::Post(post_id), SectionRight::Reply).await?;
+
+    let author = match app_state.db_client.get_post_author(post_id).await {
+        Ok(author) => author,
+        Err(sqlx::Error::RowNotFound) => return Err(HttpError::new("No such post", StatusCode::NOT_FOUND)),
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    if author == Some(user.user.id) {
+        return Err(HttpError::forbidden(ErrorMessage::OwnPostReaction.to_string())
+            .with_code(ErrorMessage::OwnPostReaction));
+    }
+
+    // Reacting twice is not an error, the second request just changes nothing
+    let added = app_state.db_client
+        .add_reaction(post_id, user.user.id, &emoji)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: if added { "reaction added" } else { "already reacted" }.to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn remove_reaction(
+    Path((post_id, emoji)): Path<(i64, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    policy::section_access(&app_state, &user.user, SectionTarget::Post(post_id), SectionRight::View).await?;
+
+    // Not checked against the configured set, so reactions with a retired emoji can still be taken back
+    let removed = app_state.db_client
+        .remove_reaction(post_id, user.user.id, &emoji)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::Response {
+        status: "success",
+        message: if removed { "reaction removed" } else { "no such reaction" }.to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_post_reactions(
+    Path(post_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    policy::section_access(&app_state, &user.user, SectionTarget::Post(post_id), SectionRight::View).await?;
+
+    let reactions = app_state.db_client
+        .get_reaction_counts(user.user.id, Some(post_id), None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::ReactionCountsResponseDto {
+        status: "success".to_string(),
+        reactions,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_thread_reactions(
+    Path(thread_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    policy::section_access(&app_state, &user.user, SectionTarget::Thread(thread_id), SectionRight::View).await?;
+
+    let reactions = app_state.db_client
+        .get_reaction_counts(user.user.id, None, Some(thread_id))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::ReactionCountsResponseDto {
+        status: "success".to_string(),
+        reactions,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_reactors(
+    Path(post_id): Path<i64>,
+    Query(query_params): Query<forum::GetReactorsDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+    policy::section_access(&app_state, &user.user, SectionTarget::Post(post_id), SectionRight::View).await?;
+
+    let users = app_state.db_client
+        .get_reactors(post_id, query_params.emoji.as_deref(), query_params.page.unwrap_or(1), query_params.limit.unwrap_or(50))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::ReactorsResponseDto {
+        status: "success".to_string(),
+        users,
+    };
+
+    Ok(Json(response))
+}
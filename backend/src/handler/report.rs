@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{audit::AuditEntry,
+    chat::ChatEvent,
+    db::{chat::ChatExt, forum::ForumExt, report::ReportExt, user::UserExt},
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    models::{AuditAction, AuditTarget, Report, ReportStatus, ReportTarget, ReportedContent, SectionRight, User, UserRole},
+    policy::{self, SectionTarget},
+    AppState};
+
+fn no_such_report() -> HttpError {
+    HttpError::new(ErrorMessage::NoSuchReport.to_string(), StatusCode::NOT_FOUND)
+        .with_code(ErrorMessage::NoSuchReport)
+}
+
+/// The target as the reporter can see it, anything they can't see can't be reported either.
+/// Chat messages also come with their room, so hiding one can be announced there.
+async fn reported_content(app_state: &AppState, user: &User, target_type: ReportTarget, target_id: i64) -> Result<(ReportedContent, Option<i32>), HttpError> {
+    let (author, content, room_id) = match target_type {
+        ReportTarget::Post => {
+            policy::section_access(app_state, user, SectionTarget::Post(target_id), SectionRight::View).await?;
+            let post = app_state.db_client.get_post(target_id)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .ok_or_else(|| HttpError::new("No such post", StatusCode::NOT_FOUND))?;
+            (post.author, post.content, None)
+        }
+        ReportTarget::Thread => {
+            policy::section_access(app_state, user, SectionTarget::Thread(target_id), SectionRight::View).await?;
+            let thread = app_state.db_client.get_thread_info(target_id)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::RowNotFound => HttpError::new("No such thread", StatusCode::NOT_FOUND),
+                    e => HttpError::server_error(e.to_string()),
+                })?;
+            (Some(thread.author), format!("{}\n\n{}", thread.title, thread.content), None)
+        }
+        ReportTarget::Chat => {
+            let no_such_message = || HttpError::new("No such message", StatusCode::NOT_FOUND);
+            let post = app_state.db_client.get_chat_post(i32::try_from(target_id).map_err(|_| no_such_message())?)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .ok_or_else(no_such_message)?;
+            app_state.db_client.get_room(user.id, post.room_id)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .ok_or_else(no_such_message)?;
+            (Some(post.author), post.content, Some(post.room_id))
+        }
+        ReportTarget::Pm => {
+            // Only the receiver gets to report a private message
+            let pm = app_state.db_client.get_pm(target_id)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .filter(|pm| pm.receiver == user.id)
+                .ok_or_else(|| HttpError::new("No such message", StatusCode::NOT_FOUND))?;
+            (pm.author, pm.content, None)
+        }
+    };
+
+    Ok((ReportedContent { target_type, target_id, author, content }, room_id))
+}
+
+async fn open_report(app_state: &AppState, report_id: i64) -> Result<Report, HttpError> {
+    let report = app_state.db_client.get_report(report_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(no_such_report)?;
+
+    if report.status != ReportStatus::Open {
+        return Err(HttpError::new(ErrorMessage::ReportClosed.to_string(), StatusCode::CONFLICT)
+            .with_code(ErrorMessage::ReportClosed));
+    }
+
+    Ok(report)
+}
+
+fn report_claimed(report: &Report) -> HttpError {
+    HttpError::new(ErrorMessage::ReportClaimed.to_string(), StatusCode::CONFLICT)
+        .with_code(ErrorMessage::ReportClaimed)
+        .with_details(serde_json::json!({ "claimedBy": report.claimed_by, "claimedByName": report.claimed_by_name }))
+}
+
+pub async fn report_content(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::ReportContentDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let (target, room_id) = reported_content(&app_state, &user.user, body.target_type, body.target_id).await?;
+
+    if target.author == Some(user.user.id) {
+        return Err(HttpError::bad_request(ErrorMessage::OwnContentReport.to_string())
+            .with_code(ErrorMessage::OwnContentReport));
+    }
+
+    let filed = app_state.db_client
+        .create_report(&target, user.user.id, body.reason, body.comment.as_deref(), app_state.env.report_hide_threshold)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::new(ErrorMessage::AlreadyReported.to_string(), StatusCode::CONFLICT)
+            .with_code(ErrorMessage::AlreadyReported))?;
+
+    // Clients drop a hidden chat message the same way as a deleted one
+    if let (true, Some(room_id)) = (filed.hidden, room_id) {
+        app_state.chat.bus
+            .publish(ChatEvent::Deleted { room_id, id: target.target_id as i32 })
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
+    let response = forum::ReportFiledResponseDto {
+        status: "success".to_string(),
+        report_id: filed.report_id,
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn get_reports(
+    Query(query_params): Query<forum::GetReportsDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let reports = app_state.db_client
+        .get_reports(None, &query_params.to_filter(), query_params.page.unwrap_or(1), query_params.limit.unwrap_or(20))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::ReportsResponseDto {
+        status: "success".to_string(),
+        reports,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_report(
+    Path(report_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let report = app_state.db_client.get_report(report_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(no_such_report)?;
+
+    let entries = app_state.db_client.get_report_entries(report_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::ReportResponseDto {
+        status: "success".to_string(),
+        report,
+        entries,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn claim_report(
+    Path(report_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let report = open_report(&app_state, report_id).await?;
+
+    let claimed = app_state.db_client.claim_report(report_id, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !claimed {
+        return Err(report_claimed(&report));
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: "report claimed".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn release_report(
+    Path(report_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let report = open_report(&app_state, report_id).await?;
+
+    let released = app_state.db_client.release_report(report_id, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !released {
+        return Err(HttpError::bad_request("The report is not claimed by you")
+            .with_details(serde_json::json!({ "claimedBy": report.claimed_by })));
+    }
+
+    let response = forum::Response {
+        status: "success",
+        message: "report released".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+/// A report claimed by someone else can only be closed by them or an admin.
+async fn close_report(app_state: &AppState, user: &User, report_id: i64, status: ReportStatus, body: &forum::CloseReportDto) -> Result<Report, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let report = open_report(app_state, report_id).await?;
+
+    if report.claimed_by.is_some_and(|c| c != user.id) && user.role != UserRole::Admin {
+        return Err(report_claimed(&report));
+    }
+
+    if body.warn.is_some() && report.target_author.is_none() {
+        return Err(HttpError::bad_request("The reported content has no author to warn"));
+    }
+
+    let warn = body.warn.as_ref().map(|w| (w.comment.as_deref(), w.banned));
+    let closed = app_state.db_client.close_report(report_id, status, user.id, body.resolution.as_deref(), warn)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Someone else closed it in the meantime
+    if !closed {
+        return Err(HttpError::new(ErrorMessage::ReportClosed.to_string(), StatusCode::CONFLICT)
+            .with_code(ErrorMessage::ReportClosed));
+    }
+
+    Ok(report)
+}
+
+pub async fn resolve_report(
+    Path(report_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::CloseReportDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user = &user.user;
+    let report = close_report(&app_state, user, report_id, ReportStatus::Resolved, &body).await?;
+
+    let entry = AuditEntry::new(AuditAction::ReportResolve, AuditTarget::Report, report_id)
+        .before(serde_json::json!({ "status": report.status, "claimedBy": report.claimed_by }))
+        .after(serde_json::json!({ "status": ReportStatus::Resolved, "resolution": body.resolution, "warn": body.warn }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "report resolved".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+pub async fn dismiss_report(
+    Path(report_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::CloseReportDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    if body.warn.is_some() {
+        return Err(HttpError::bad_request("A dismissed report can't warn anyone"));
+    }
+
+    let report = close_report(&app_state, &user.user, report_id, ReportStatus::Dismissed, &body).await?;
+
+    let entry = AuditEntry::new(AuditAction::ReportDismiss, AuditTarget::Report, report_id)
+        .before(serde_json::json!({ "status": report.status, "claimedBy": report.claimed_by, "hiddenAt": report.hidden_at }))
+        .after(serde_json::json!({ "status": ReportStatus::Dismissed, "resolution": body.resolution }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "report dismissed".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
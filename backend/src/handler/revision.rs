@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{audit::AuditEntry,
+    db::{forum::ForumExt, revision::RevisionExt},
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    models::{AuditAction, AuditTarget, Revision, SectionRight, User},
+    policy::{self, SectionTarget},
+    render,
+    utils::diff,
+    AppState};
+
+fn no_such_revision() -> HttpError {
+    HttpError::new(ErrorMessage::NoSuchRevision.to_string(), StatusCode::NOT_FOUND)
+        .with_code(ErrorMessage::NoSuchRevision)
+}
+
+/// Rolling back to what is already there changes nothing, so there is nothing to log either.
+fn revision_is_current() -> HttpError {
+    HttpError::new(ErrorMessage::RevisionIsCurrent.to_string(), StatusCode::CONFLICT)
+        .with_code(ErrorMessage::RevisionIsCurrent)
+}
+
+/// Edit histories may hold what was edited out on purpose, only the author and moderators see them.
+async fn history_access(app_state: &AppState, user: &User, target: SectionTarget, author: Option<uuid::Uuid>) -> Result<(), HttpError> {
+    let access = policy::section_access(app_state, user, target, SectionRight::View).await?;
+
+    if !access.can_moderate && author != Some(user.id) {
+        return Err(HttpError::forbidden(ErrorMessage::SectionPermissionDenied.to_string())
+            .with_code(ErrorMessage::SectionPermissionDenied)
+            .with_details(serde_json::json!({ "right": SectionRight::Moderate })));
+    }
+
+    if author != Some(user.id) {
+        policy::moderator_action(app_state, user).await?;
+    }
+
+    Ok(())
+}
+
+async fn post_revision(app_state: &AppState, post_id: i64, rev_id: i64) -> Result<Revision, HttpError> {
+    app_state.db_client.get_revision(rev_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|r| r.post_id == Some(post_id))
+        .ok_or_else(no_such_revision)
+}
+
+async fn thread_revision(app_state: &AppState, thread_id: i64, rev_id: i64) -> Result<Revision, HttpError> {
+    app_state.db_client.get_revision(rev_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|r| r.thread_id == Some(thread_id))
+        .ok_or_else(no_such_revision)
+}
+
+async fn load_post(app_state: &AppState, post_id: i64) -> Result<crate::models::Post, HttpError> {
+    app_state.db_client.get_post(post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::new("No such post", StatusCode::NOT_FOUND))
+}
+
+async fn load_thread(app_state: &AppState, thread_id: i64) -> Result<crate::models::Thread, HttpError> {
+    match app_state.db_client.get_thread_info(thread_id).await {
+        Ok(thread) => Ok(thread),
+        Err(sqlx::Error::RowNotFound) => Err(HttpError::new("No such thread", StatusCode::NOT_FOUND)),
+        Err(e) => Err(HttpError::server_error(e.to_string())),
+    }
+}
+
+pub async fn get_post_revisions(
+    Path(post_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let post = load_post(&app_state, post_id).await?;
+    history_access(&app_state, &user.user, SectionTarget::Post(post_id), post.author).await?;
+
+    let revisions = app_state.db_client.get_post_revisions(post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(forum::RevisionsResponseDto {
+        status: "success".to_string(),
+        revisions,
+    }))
+}
+
+pub async fn get_post_diff(
+    Path(post_id): Path<i64>,
+    Query(query_params): Query<forum::GetRevisionDiffDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let post = load_post(&app_state, post_id).await?;
+    history_access(&app_state, &user.user, SectionTarget::Post(post_id), post.author).await?;
+
+    let from = post_revision(&app_state, post_id, query_params.from).await?;
+    let to = match query_params.to {
+        Some(rev_id) => post_revision(&app_state, post_id, rev_id).await?.content,
+        None => post.content,
+    };
+
+    Ok(Json(forum::RevisionDiffResponseDto {
+        status: "success".to_string(),
+        from: query_params.from,
+        to: query_params.to,
+        title_change: None,
+        lines: diff::line_diff(&from.content, &to),
+    }))
+}
+
+/// Rolling back is an edit like any other, the content it replaces is kept as a revision too.
+pub async fn rollback_post(
+    Path((post_id, rev_id)): Path<(i64, i64)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::RollbackRevisionDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    policy::section_access(&app_state, &user.user, SectionTarget::Post(post_id), SectionRight::Moderate).await?;
+
+    let post = load_post(&app_state, post_id).await?;
+    let revision = post_revision(&app_state, post_id, rev_id).await?;
+    let reason = body.reason.unwrap_or_else(|| format!("Rolled back to revision {}", rev_id));
+    let content_html = render::render(&revision.content, app_state.env.bbcode);
+
+    let changed = app_state.db_client.update_post(post_id, &revision.content, &content_html, user.user.id, Some(&reason))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !changed {
+        return Err(revision_is_current());
+    }
+
+    let entry = AuditEntry::new(AuditAction::PostRollback, AuditTarget::Post, post_id)
+        .before(serde_json::json!({ "content": post.content }))
+        .after(serde_json::json!({ "revision": rev_id, "content": revision.content, "reason": reason }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "post rolled back".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+pub async fn get_thread_revisions(
+    Path(thread_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let thread = load_thread(&app_state, thread_id).await?;
+    history_access(&app_state, &user.user, SectionTarget::Thread(thread_id), Some(thread.author)).await?;
+
+    let revisions = app_state.db_client.get_thread_revisions(thread_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(forum::RevisionsResponseDto {
+        status: "success".to_string(),
+        revisions,
+    }))
+}
+
+pub async fn get_thread_diff(
+    Path(thread_id): Path<i64>,
+    Query(query_params): Query<forum::GetRevisionDiffDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let thread = load_thread(&app_state, thread_id).await?;
+    history_access(&app_state, &user.user, SectionTarget::Thread(thread_id), Some(thread.author)).await?;
+
+    let from = thread_revision(&app_state, thread_id, query_params.from).await?;
+    let (to_title, to_content) = match query_params.to {
+        Some(rev_id) => {
+            let to = thread_revision(&app_state, thread_id, rev_id).await?;
+            (to.title.unwrap_or_default(), to.content)
+        }
+        None => (thread.title, thread.content),
+    };
+
+    let from_title = from.title.unwrap_or_default();
+    let title_change = (from_title != to_title).then_some((from_title, to_title));
+
+    Ok(Json(forum::RevisionDiffResponseDto {
+        status: "success".to_string(),
+        from: query_params.from,
+        to: query_params.to,
+        title_change,
+        lines: diff::line_diff(&from.content, &to_content),
+    }))
+}
+
+pub async fn rollback_thread(
+    Path((thread_id, rev_id)): Path<(i64, i64)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<forum::RollbackRevisionDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
+    policy::section_access(&app_state, &user.user, SectionTarget::Thread(thread_id), SectionRight::Moderate).await?;
+
+    let thread = load_thread(&app_state, thread_id).await?;
+    let revision = thread_revision(&app_state, thread_id, rev_id).await?;
+    let title = revision.title.unwrap_or_else(|| thread.title.clone());
+    let reason = body.reason.unwrap_or_else(|| format!("Rolled back to revision {}", rev_id));
+    let content_html = render::render(&revision.content, app_state.env.bbcode);
+
+    let changed = app_state.db_client.update_thread(thread_id, &title, &revision.content, &content_html, user.user.id, Some(&reason))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !changed {
+        return Err(revision_is_current());
+    }
+
+    let entry = AuditEntry::new(AuditAction::ThreadRollback, AuditTarget::Thread, thread_id)
+        .before(serde_json::json!({ "title": thread.title, "content": thread.content }))
+        .after(serde_json::json!({ "revision": rev_id, "title": title, "content": revision.content, "reason": reason }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "thread rolled back".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
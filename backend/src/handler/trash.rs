@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Extension, Json};
+use validator::Validate;
+
+use crate::{audit::AuditEntry,
+    db::trash::TrashExt,
+    dto::forum,
+    error::{ErrorMessage, HttpError},
+    middleware::JWTAuthMiddeware,
+    models::{AuditAction, AuditTarget, SectionRight, TrashKind, User},
+    policy::{self, SectionTarget},
+    AppState};
+
+/// Deleted content can't be reached through its thread or post anymore, rights are checked on its section.
+/// Returns the section.
+async fn trashed_access(app_state: &AppState, user: &User, kind: TrashKind, id: i64) -> Result<i64, HttpError> {
+    let section = app_state.db_client.get_trashed_section(kind, id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::new(ErrorMessage::NotInTrash.to_string(), StatusCode::NOT_FOUND)
+            .with_code(ErrorMessage::NotInTrash))?;
+
+    policy::section_access(app_state, user, SectionTarget::Section(section), SectionRight::Moderate).await?;
+
+    Ok(section)
+}
+
+pub async fn get_trash(
+    Query(query_params): Query<forum::GetTrashDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    // Without a section the list simply covers every section the user moderates
+    if let Some(section) = query_params.section {
+        policy::section_access(&app_state, &user.user, SectionTarget::Section(section), SectionRight::Moderate).await?;
+    }
+
+    let items = app_state.db_client
+        .get_trash(user.user.id, query_params.section, query_params.kind, query_params.page.unwrap_or(1), query_params.limit.unwrap_or(20))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = forum::TrashResponseDto {
+        status: "success".to_string(),
+        retention_days: app_state.env.trash_retention_days,
+        items,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn restore_thread(
+    Path(thread_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let section = trashed_access(&app_state, &user.user, TrashKind::Thread, thread_id).await?;
+
+    app_state.db_client.restore_thread(thread_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = AuditEntry::new(AuditAction::ThreadRestore, AuditTarget::Thread, thread_id)
+        .after(serde_json::json!({ "sectionId": section }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "thread restored".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+pub async fn restore_post(
+    Path(post_id): Path<i64>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let section = trashed_access(&app_state, &user.user, TrashKind::Post, post_id).await?;
+
+    app_state.db_client.restore_post(post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let entry = AuditEntry::new(AuditAction::PostRestore, AuditTarget::Post, post_id)
+        .after(serde_json::json!({ "sectionId": section }));
+
+    let response = forum::Response {
+        status: "success",
+        message: "post restored".to_string(),
+    };
+
+    Ok((Extension(entry), Json(response)))
+}
+
+/// Purges what has been in the trash longer than the retention period, run periodically from `jobs`.
+pub async fn purge_trash(app_state: &AppState) {
+    if let Err(e) = app_state.db_client.purge_trash(app_state.env.trash_retention_days).await {
+        eprintln!("Failed to purge the trash: {}", e);
+    }
+}
@@ -1,24 +1,46 @@
 use std::sync::Arc;
 
-use axum::{extract::{Query, Path}, middleware, response::IntoResponse, routing::{get, put, post}, Extension, Json, Router};
+use axum::{extract::{DefaultBodyLimit, Query, Path}, http::StatusCode, middleware, response::IntoResponse, routing::{delete, get, put, post}, Extension, Json, Router};
+use chrono::{Duration, Utc};
 use validator::Validate;
 use crate::AppState;
-use crate::{db::user::UserExt,
-    models::UserRole,
+use crate::{audit::AuditEntry,
+    db::{email_change::EmailChangeExt, reaction::ReactionExt, session::SessionExt, throttle::ThrottleExt, user::UserExt},
+    models::{AuditAction, AuditTarget, NewEmailChange, ProfileUpdate, UserRole},
     dto::user,
     error::{ErrorMessage, HttpError},
+    handler::{account, auth::{account_throttle_key, ip_throttle_key}, avatar},
+    mail::mails::{send_email_change_confirmation, send_email_change_notice},
     middleware::{role_check, JWTAuthMiddeware},
-    utils::password,
+    utils::{avatar::MAX_AVATAR_BYTES, password},
 };
 
+pub const NAME_CHANGE_COOLDOWN_DAYS: i64 = 30;
+const EMAIL_CONFIRM_HOURS: i64 = 24;
+const EMAIL_REVERT_DAYS: i64 = 7;
+
 pub fn user_handler() -> Router {
     let admin_mod_only = middleware::from_fn(|state, req, next| 
                     role_check(state, req, next, vec![UserRole::Admin, UserRole::Mod]) );
+    let admin_only = middleware::from_fn(|state, req, next| 
+                    role_check(state, req, next, vec![UserRole::Admin]) );
 
     Router::new()
-        .route("/me", get(get_me))
+        .route("/me", get(get_me).patch(update_profile))
+        .route("/me/name", put(update_user_name))
+        .route("/me/password", put(update_user_password))
+        .route("/me/email", post(request_email_change))
+        .route("/me/deletion", get(account::get_deletion).post(account::request_deletion).delete(account::cancel_deletion))
+        .route("/me/export", get(account::export_data))
+        .route("/me/history", get(get_my_history))
+        .route("/me/avatar", put(avatar::upload_avatar).delete(avatar::delete_avatar)
+            // Leaves room for the multipart framing around the file itself.
+            .layer(DefaultBodyLimit::max(MAX_AVATAR_BYTES + 64 * 1024)) )
+        .route("/role", put(update_user_role).layer(admin_only.clone()) )
+        .route("/{user_id}/history", get(get_user_history).layer(admin_mod_only.clone()) )
         .route("/user/{uuid}", get(get_user_data))
         .route("/list", get(get_users))
+        .route("/search", get(search_users).layer(admin_mod_only.clone()) )
         .route("/{user_id}/posts", get(user_posts))
         .route("/{user_id}/threads", get(user_threads))
         .route("/{user_id}/warnings", get(user_warnings))
@@ -26,14 +48,21 @@ pub fn user_handler() -> Router {
         .route("/unban", put(unban_user).layer(admin_mod_only.clone()) )
         .route("/warn", put(warn_user).layer(admin_mod_only.clone()) )
         .route("/pms", get(get_pms))
+        .route("/lockout", delete(clear_lockout).layer(admin_only.clone()) )
+        .route("/me/identities", get(super::oidc::get_identities))
+        .route("/me/identities/{provider}", post(super::oidc::link_identity).delete(super::oidc::unlink_identity))
 }
 
 pub async fn get_me(
-    Extension(_app_state): Extension<Arc<AppState>>,
+    Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddeware>
 ) -> Result<impl IntoResponse, HttpError> {
 
-    let filtered_user = user::FilterUserDto::filter_user(&user.user);
+    let mut filtered_user = user::FilterUserDto::filter_user(&user.user);
+    filtered_user.received_likes = Some(app_state.db_client
+        .get_received_likes(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?);
 
     let response_data = user::UserResponseDto {
         status: "success".to_string(),
@@ -74,11 +103,45 @@ pub async fn get_users(
     Ok(Json(response))
 }
 
+pub async fn search_users(
+    Query(query_params): Query<user::SearchUsersDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if query_params.email.is_some() && user.user.role != UserRole::Admin {
+        return Err(HttpError::forbidden(ErrorMessage::PermissionDenied.to_string()));
+    }
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let (users, total) = app_state.db_client
+        .search_users(&query_params.to_user_search(), page, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let mut users = user::FilterUserDto::filter_users(&users);
+    if user.user.role != UserRole::Admin {
+        users.iter_mut().for_each(|u| u.email.clear());
+    }
+
+    let response = user::UserListResponseDto {
+        status: "success".to_string(),
+        users,
+        results: total,
+    };
+
+    Ok(Json(response))
+}
+
 pub async fn get_user_data(
     Path(uuid) : Path<uuid::Uuid>,
     Extension(app_state): Extension<Arc<AppState>>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let user = app_state.db_client.get_user(Some(uuid), None, None)
+    let user = app_state.db_client.get_user(Some(uuid), None, None, None)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
     let response = user::Response {
@@ -91,6 +154,31 @@ pub async fn get_user_data(
 
 }
     
+pub async fn update_profile(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<user::UpdateProfileDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_id = user.user.id;
+
+    let result = app_state.db_client
+        .update_user_profile(user_id, user_id, &body.to_profile_update())
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response_data = user::UserResponseDto {
+        status: "success".to_string(),
+        data: user::UserData {
+            user: user::FilterUserDto::filter_user(&result),
+        }
+    };
+
+    Ok(Json(response_data))
+}
+
 pub async fn update_user_name(
     Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddeware>,
@@ -100,15 +188,39 @@ pub async fn update_user_name(
        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let user = &user.user;
+    let name = body.name.trim();
 
-    let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
+    if name.is_empty() {
+        return Err(HttpError::bad_request("Name is required"));
+    }
 
-    app_state.db_client.
-        update_user_name(user_id.clone(), &body.name)
-        .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if name != user.name {
+        let last_change = app_state.db_client
+            .last_profile_change(user.id, "name")
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    //let filtered_user = user::FilterUserDto::filter_user(&result);
+        if let Some(allowed_at) = last_change.map(|t| t + Duration::days(NAME_CHANGE_COOLDOWN_DAYS))
+            && allowed_at > Utc::now() {
+            return Err(HttpError::new(ErrorMessage::NameChangeCooldown.to_string(), StatusCode::TOO_MANY_REQUESTS)
+                .with_code(ErrorMessage::NameChangeCooldown)
+                .with_details(serde_json::json!({ "retryAt": allowed_at })));
+        }
+
+        let update = ProfileUpdate {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+
+        match app_state.db_client.update_user_profile(user.id, user.id, &update).await {
+            Ok(_) => {},
+            Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                return Err(HttpError::unique_constraint_violation(ErrorMessage::NameAlreadyExists.to_string())
+                    .with_code(ErrorMessage::NameAlreadyExists));
+            }
+            Err(e) => return Err(HttpError::server_error(e.to_string())),
+        }
+    }
 
     let response = user::Response {
         status: "success",
@@ -126,21 +238,39 @@ pub async fn update_user_role(
     body.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let user = &user.user;
+    let admin_id = user.user.id;
 
-    let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
+    // An admin demoting themselves could leave the forum without any admin.
+    if body.user_id == admin_id {
+        return Err(HttpError::forbidden("You cannot change your own role"));
+    }
 
-    app_state.db_client
-        .update_user_role(user_id.clone(), body.role)
+    let target = app_state.db_client.get_user(Some(body.user_id), None, None, None)
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::NoSuchUser.to_string()))?;
+
+    let update = ProfileUpdate {
+        role: Some(body.role),
+        ..Default::default()
+    };
+
+    match app_state.db_client.update_user_profile(body.user_id, admin_id, &update).await {
+        Ok(_) => {},
+        Err(sqlx::Error::RowNotFound) => return Err(HttpError::bad_request(ErrorMessage::NoSuchUser.to_string())),
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    }
+
+    let entry = AuditEntry::new(AuditAction::UserRole, AuditTarget::User, body.user_id)
+        .before(serde_json::json!({ "role": target.role }))
+        .after(serde_json::json!({ "role": body.role }));
 
     let response = user::Response {
         status: "success",
         message: "role changed".to_string(),
     };
 
-    Ok(Json(response))
+    Ok((Extension(entry), Json(response)))
 }
 
 pub async fn update_user_password(
@@ -156,7 +286,7 @@ pub async fn update_user_password(
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
 
     let result = app_state.db_client
-        .get_user(Some(user_id.clone()), None, None)
+        .get_user(Some(user_id), None, None, None)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -173,7 +303,12 @@ pub async fn update_user_password(
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     app_state.db_client
-        .update_user_password(user_id.clone(), hash_password.as_str())
+        .update_user_password(user_id, hash_password.as_str())
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state.db_client
+        .revoke_user_sessions(user_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -185,6 +320,73 @@ pub async fn update_user_password(
     Ok(Json(response))
 }
 
+pub async fn request_email_change(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+    Json(body): Json<user::EmailChangeRequestDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+       .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = &user.user;
+    let new_email = body.new_email.trim();
+
+    let password_match = password::compare(&body.password, &user.password)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !password_match {
+        return Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()));
+    }
+
+    if new_email.eq_ignore_ascii_case(&user.email) {
+        return Err(HttpError::bad_request("This is already your email address"));
+    }
+
+    let existing = app_state.db_client
+        .get_user(None, None, Some(new_email), None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if existing.is_some() {
+        return Err(HttpError::unique_constraint_violation(ErrorMessage::EmailAlreadyExists.to_string()));
+    }
+
+    let confirm_token = uuid::Uuid::new_v4().to_string();
+    let revert_token = uuid::Uuid::new_v4().to_string();
+
+    app_state.db_client
+        .create_email_change(&NewEmailChange {
+            user_id: user.id,
+            old_email: &user.email,
+            new_email,
+            confirm_token: &confirm_token,
+            revert_token: &revert_token,
+            expires_at: Utc::now() + Duration::hours(EMAIL_CONFIRM_HOURS),
+            revert_expires_at: Utc::now() + Duration::days(EMAIL_REVERT_DAYS),
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let confirm_link = format!("{}/auth/email/confirm?token={}", app_state.env.api_url, confirm_token);
+    let revert_link = format!("{}/auth/email/revert?token={}", app_state.env.api_url, revert_token);
+
+    if let Err(e) = send_email_change_confirmation(new_email, &user.name, &confirm_link).await {
+        eprintln!("Failed to send email change confirmation: {}", e);
+        return Err(HttpError::server_error("Failed to send email".to_string()));
+    }
+
+    if let Err(e) = send_email_change_notice(&user.email, &user.name, new_email, &revert_link).await {
+        eprintln!("Failed to send email change notice: {}", e);
+    }
+
+    let response = user::Response {
+        message: "A confirmation link has been sent to the new address.".to_string(),
+        status: "success",
+    };
+
+    Ok(Json(response))
+}
+
 pub async fn warn_user(
     Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddeware>,
@@ -199,29 +401,39 @@ pub async fn warn_user(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    let entry = AuditEntry::new(AuditAction::UserWarn, AuditTarget::User, body.uuid)
+        .after(serde_json::json!({ "comment": body.comment, "banned": body.banned }));
+
     let response = user::Response {
         message: "User warned".to_string(),
         status: "success",
     };
 
-    Ok(Json(response))
+    Ok((Extension(entry), Json(response)))
 }
 
 pub async fn unban_user(
     Extension(app_state): Extension<Arc<AppState>>,
     Json(body): Json<user::WarnUserDto>,
 ) -> Result<impl IntoResponse, HttpError> {
+    let target = app_state.db_client.get_user(Some(body.uuid), None, None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
     app_state.db_client
         .unban_user(body.uuid)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    let entry = AuditEntry::new(AuditAction::UserUnban, AuditTarget::User, body.uuid)
+        .before(serde_json::json!({ "bannedUntil": target.and_then(|t| t.banned_until) }));
+
     let response = user::Response {
         message: "User warned".to_string(),
         status: "success",
     };
 
-    Ok(Json(response))
+    Ok((Extension(entry), Json(response)))
 }
 
 pub async fn user_posts(
@@ -309,3 +521,68 @@ pub async fn get_pms(
     Ok(Json(response))
 }
 
+pub async fn clear_lockout(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<user::ClearLockoutDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let mut cleared = false;
+
+    if let Some(name) = &body.name {
+        cleared |= app_state.db_client
+            .clear_throttle(&account_throttle_key(name))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
+    if let Some(ip) = body.ip {
+        cleared |= app_state.db_client
+            .clear_throttle(&ip_throttle_key(&ip))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
+    let response = user::Response {
+        message: if cleared { "Lockout cleared" } else { "No lockout found" }.to_string(),
+        status: "success",
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_my_history(
+    Query(query_params): Query<user::RequestQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddeware>,
+) -> Result<impl IntoResponse, HttpError> {
+    profile_history(&app_state, user.user.id, query_params).await
+}
+
+pub async fn get_user_history(
+    Path(user_id) : Path<uuid::Uuid>,
+    Query(query_params): Query<user::RequestQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    profile_history(&app_state, user_id, query_params).await
+}
+
+async fn profile_history(app_state: &AppState, user_id: uuid::Uuid, query_params: user::RequestQueryDto)
+    -> Result<Json<user::ProfileHistoryResponseDto>, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let history = app_state.db_client
+        .get_profile_history(user_id, page as u32, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(user::ProfileHistoryResponseDto {
+        status: "success".to_string(),
+        history,
+    }))
+}
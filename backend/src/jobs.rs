@@ -0,0 +1,26 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{handler::{account, trash}, AppState};
+
+const ACCOUNT_DELETION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts the periodic background tasks, they live as long as the server.
+pub fn spawn(app_state: Arc<AppState>) {
+    let state = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACCOUNT_DELETION_INTERVAL);
+        loop {
+            interval.tick().await;
+            account::purge_deleted_accounts(&state).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            trash::purge_trash(&app_state).await;
+        }
+    });
+}
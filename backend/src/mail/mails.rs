@@ -49,3 +49,65 @@ pub async fn send_forgot_password_email(
     send_email(to_email, subject, template_path, &placeholders).await
 }
 
+
+pub async fn send_account_locked_email(
+    to_email: &str,
+    username: &str,
+    locked_until: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Your account has been locked";
+    let template_path = "src/mail/templates/AccountLocked-email.html";
+    let placeholders = vec![
+        ("{{username}}".to_string(), username.to_string()),
+        ("{{locked_until}}".to_string(), locked_until.to_string())
+    ];
+
+    send_email(to_email, subject, template_path, &placeholders).await
+}
+
+pub async fn send_email_change_confirmation(
+    to_email: &str,
+    username: &str,
+    confirm_link: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Confirm your new email address";
+    let template_path = "src/mail/templates/EmailChange-email.html";
+    let placeholders = vec![
+        ("{{username}}".to_string(), username.to_string()),
+        ("{{confirm_link}}".to_string(), confirm_link.to_string())
+    ];
+
+    send_email(to_email, subject, template_path, &placeholders).await
+}
+
+pub async fn send_email_change_notice(
+    to_email: &str,
+    username: &str,
+    new_email: &str,
+    revert_link: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Your email address is being changed";
+    let template_path = "src/mail/templates/EmailChangeNotice-email.html";
+    let placeholders = vec![
+        ("{{username}}".to_string(), username.to_string()),
+        ("{{new_email}}".to_string(), new_email.to_string()),
+        ("{{revert_link}}".to_string(), revert_link.to_string())
+    ];
+
+    send_email(to_email, subject, template_path, &placeholders).await
+}
+
+pub async fn send_account_deletion_email(
+    to_email: &str,
+    username: &str,
+    delete_after: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Your account is scheduled for deletion";
+    let template_path = "src/mail/templates/AccountDeletion-email.html";
+    let placeholders = vec![
+        ("{{username}}".to_string(), username.to_string()),
+        ("{{delete_after}}".to_string(), delete_after.to_string())
+    ];
+
+    send_email(to_email, subject, template_path, &placeholders).await
+}
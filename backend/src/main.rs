@@ -8,12 +8,12 @@ use std::sync::Arc;
 use axum::{
     Extension, Router, 
     middleware::from_fn,
-    http::{header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE}, HeaderValue, Method},
+    http::{header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE}, Method},
 };
 use axum_server::tls_rustls::RustlsConfig;
 use tower_http::{
     trace::TraceLayer,
-    cors::CorsLayer,
+    cors::{AllowOrigin, CorsLayer},
 };
 use std::net::SocketAddr;
 
@@ -26,18 +26,34 @@ mod error;
 mod mail;
 mod handler;
 mod middleware;
+mod policy;
+mod audit;
+mod storage;
+mod jobs;
+mod chat;
+mod render;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub env: config::Config,
     pub db_client: DBClient,
+    pub http_client: reqwest::Client,
+    pub storage: Arc<dyn storage::Storage>,
+    pub chat: Arc<chat::ChatHub>,
 }
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
     Router::new()
         .nest("/auth", handler::auth::auth_handler())
-        .nest("/users", handler::user::user_handler().layer(from_fn(middleware::auth))) 
-        .nest("/forum", handler::forum::forum_handler().layer(from_fn(middleware::auth))) 
+        .nest("/users", handler::user::user_handler()
+            .layer(from_fn(audit::audited))
+            .layer(from_fn(middleware::access_policy))
+            .layer(from_fn(middleware::auth))) 
+        .nest("/forum", handler::forum::forum_handler()
+            .layer(from_fn(audit::audited))
+            .layer(from_fn(middleware::access_policy))
+            .layer(from_fn(middleware::auth))) 
+        .nest("/avatars", handler::avatar::avatar_handler())
         .layer(TraceLayer::new_for_http())
         .layer(Extension(app_state))
 }
@@ -70,15 +86,20 @@ async fn main() -> Result<()> {
         .allow_origin(AllowOrigin::mirror_request())
         .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE])
         .allow_credentials(true)
-        .allow_methods([Method::GET, Method::POST,Method::PUT,Method::DELETE]);
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE]);
     
     let db_client = DBClient::new(pool);
 
     let app_state = Arc::new(AppState {
         env: config.clone(),
         db_client,
+        http_client: reqwest::Client::new(),
+        storage: storage::from_config(&config.storage),
+        chat: Arc::new(chat::ChatHub::new(Box::new(chat::local::LocalBus::default()))),
     });
 
+    jobs::spawn(app_state.clone());
+
     let a = app_state.clone();
     let app = create_router(a).layer(cors);
 
@@ -86,9 +107,9 @@ async fn main() -> Result<()> {
     if use_https {
         let config = RustlsConfig::from_pem_file("./cert.pem", "./key.pem").await?;
         let addr = SocketAddr::from(([127, 0, 0, 1], port));
-        axum_server::bind_rustls(addr, config).serve(app.into_make_service()).await?;
+        axum_server::bind_rustls(addr, config).serve(app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     } else {
-        axum::serve(listener, app).await.unwrap();
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
     }
 
     Ok(())
@@ -12,9 +12,11 @@ use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    db::user::UserExt,
+    audit,
+    db::{session::SessionExt, user::UserExt},
     error::{ErrorMessage, HttpError},
     models::{User, UserRole},
+    policy,
     utils::token,
     AppState
 };
@@ -22,6 +24,7 @@ use crate::{
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct JWTAuthMiddeware {
     pub user: User,
+    pub session_id: uuid::Uuid,
 }
 
 pub async fn auth(
@@ -37,13 +40,8 @@ pub async fn auth(
                 req.headers()
                     .get(header::AUTHORIZATION)
                     .and_then(|auth_header| auth_header.to_str().ok())
-                    .and_then(|auth_value| {
-                        if auth_value.starts_with("Bearer ") {
-                            Some(auth_value[7..].to_owned())
-                        } else {
-                            None
-                        }
-                    })  
+                    .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+                    .map(str::to_owned)
             });
 
     let token = cookies.ok_or_else(|| {
@@ -58,11 +56,25 @@ pub async fn auth(
             }
         };
 
-    let user_id = uuid::Uuid::parse_str(&token_details.to_string())
+    let user_id = uuid::Uuid::parse_str(&token_details.sub)
             .map_err(|_| {
                 HttpError::unauthorized(ErrorMessage::InvalidToken.to_string())
             })?;
 
+    let session_id = uuid::Uuid::parse_str(&token_details.sid)
+            .map_err(|_| {
+                HttpError::unauthorized(ErrorMessage::InvalidToken.to_string())
+            })?;
+
+    let session = app_state.db_client.get_session(session_id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match session {
+        Some(session) if session.user_id == user_id && session.is_active() => (),
+        _ => return Err(HttpError::unauthorized(ErrorMessage::SessionRevoked.to_string())),
+    }
+
     let user = app_state.db_client.get_user(Some(user_id), None, None, None)
             .await
             .map_err(|_| {
@@ -75,6 +87,7 @@ pub async fn auth(
 
     req.extensions_mut().insert(JWTAuthMiddeware {
         user: user.clone(),
+        session_id,
     });
 
     Ok(next.run(req).await)
@@ -82,8 +95,31 @@ pub async fn auth(
 }
 
 
+/// Applies `policy::evaluate` to the authenticated user, must be layered inside `auth`.
+pub async fn access_policy(
+    Extension(app_state): Extension<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, HttpError> {
+    let user = &req
+            .extensions()
+            .get::<JWTAuthMiddeware>()
+            .ok_or_else(|| {
+                HttpError::unauthorized(ErrorMessage::NotAuthenticated.to_string())
+            })?
+            .user;
+
+    let access = policy::evaluate(user, &app_state.env)?;
+
+    if !access.allows(req.method()) {
+        return Err(policy::ban_error(&app_state.db_client, user).await);
+    }
+
+    Ok(next.run(req).await)
+}
+
 pub async fn role_check(
-    Extension(_app_state): Extension<Arc<AppState>>,
+    Extension(app_state): Extension<Arc<AppState>>,
     req: Request,
     next: Next,
     required_roles: Vec<UserRole>,
@@ -95,10 +131,22 @@ pub async fn role_check(
                 HttpError::unauthorized(ErrorMessage::NotAuthenticated.to_string())
             })?;
     
-    if !required_roles.contains(&user.user.role) {
-        return Err(HttpError::new(ErrorMessage::PermissionDenied.to_string(), StatusCode::FORBIDDEN));
-    }
+    check_roles(&app_state, &user.user, &required_roles).await?;
+
+    audit::mark_privileged();
 
     Ok(next.run(req).await)
 }
 
+/// The checks behind `role_check`, for places that are not a route of their own like WebSocket commands.
+pub async fn check_roles(app_state: &AppState, user: &User, required_roles: &[UserRole]) -> Result<(), HttpError> {
+    if !required_roles.contains(&user.role) {
+        return Err(HttpError::new(ErrorMessage::PermissionDenied.to_string(), StatusCode::FORBIDDEN));
+    }
+
+    if user.role != UserRole::User {
+        policy::require_two_factor(app_state, user).await?;
+    }
+
+    Ok(())
+}
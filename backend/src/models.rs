@@ -11,7 +11,7 @@ pub enum UserRole {
 }
 
 impl UserRole {
-    pub fn to_str(&self) -> String {
+    pub fn to_str(self) -> String {
         match self {
             Self::Admin => "admin".to_string(),
             Self::Mod => "mod".to_string(),
@@ -28,6 +28,18 @@ pub enum UserStatus {
     Banned,
 }
 
+/// Who may read and post in a chat room.
+/// `Section` rooms follow the roles allowed in their section, `Roles` rooms have their own list.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq, Default)]
+#[sqlx(type_name = "forum.chat_room_access", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRoomAccess {
+    #[default]
+    Public,
+    Section,
+    Roles,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
 pub struct User {
     pub id: uuid::Uuid,
@@ -51,6 +63,206 @@ pub struct User {
     pub last_online: Option<DateTime<Utc>>,
 }
 
+impl User {
+    pub fn status(&self) -> UserStatus {
+        match self.banned_until {
+            Some(until) if until > Utc::now() => UserStatus::Banned,
+            _ if !self.verified => UserStatus::Inactive,
+            _ => UserStatus::Active,
+        }
+    }
+}
+
+/// Fields a profile update may touch, `None` leaves a field as it is.
+/// The nullable ones use `Some(None)` to clear the value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProfileUpdate {
+    pub name: Option<String>,
+    pub role: Option<UserRole>,
+    pub description: Option<Option<String>>,
+    pub facebook: Option<Option<String>>,
+    pub x_id: Option<Option<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    #[default]
+    Name,
+    Relevance,
+    Created,
+    LastOnline,
+}
+
+impl UserSort {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Relevance => "relevance",
+            Self::Created => "created",
+            Self::LastOnline => "last_online",
+        }
+    }
+}
+
+/// Filters for the admin user search, every `None` matches all users.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UserSearch {
+    pub name: Option<String>,
+    /// Trigram similarity instead of a prefix match on `name`
+    pub fuzzy: bool,
+    pub email: Option<String>,
+    pub role: Option<UserRole>,
+    pub banned: Option<bool>,
+    pub registered_from: Option<DateTime<Utc>>,
+    pub registered_to: Option<DateTime<Utc>>,
+    pub online_from: Option<DateTime<Utc>>,
+    pub online_to: Option<DateTime<Utc>>,
+    pub sort: UserSort,
+    pub descending: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchScope {
+    #[default]
+    All,
+    Threads,
+    Posts,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Newest,
+}
+
+impl SearchScope {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Threads => "threads",
+            Self::Posts => "posts",
+        }
+    }
+}
+
+impl SearchSort {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Relevance => "relevance",
+            Self::Newest => "newest",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TagCount {
+    pub tag: String,
+    pub threads: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TrendingTag {
+    pub tag: String,
+    /// Threads in the window
+    pub threads: i64,
+    /// Threads in the window of the same length before it
+    pub previous: i64,
+}
+
+/// Filters for the forum search. `query` is a `to_tsquery` expression, see `utils::search::to_tsquery`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ForumSearch {
+    pub query: String,
+    pub scope: SearchScope,
+    pub section_id: Option<i64>,
+    /// Author name, case insensitive
+    pub author: Option<String>,
+    pub tag: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub sort: SearchSort,
+}
+
+/// Position after the last hit of a page, results are ordered by `(key, kind, id)` descending.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SearchCursor {
+    pub key: f64,
+    pub kind: String,
+    pub id: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SearchHit {
+    /// "thread" or "post"
+    pub kind: String,
+    pub id: i64,
+    pub thread_id: i64,
+    pub title: String,
+    pub section_id: i64,
+    pub author: Option<uuid::Uuid>,
+    pub author_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub rank: f32,
+    /// Rank or creation time depending on the sort, only needed for the cursor
+    #[serde(skip)]
+    pub sort_key: f64,
+    pub snippet: String,
+}
+
+impl SearchHit {
+    pub fn cursor(&self) -> SearchCursor {
+        SearchCursor { key: self.sort_key, kind: self.kind.clone(), id: self.id }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ProfileHistory {
+    pub id: i64,
+    pub user_id: uuid::Uuid,
+    pub changed_by: Option<uuid::Uuid>,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct EmailChange {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub old_email: String,
+    pub new_email: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub revert_expires_at: DateTime<Utc>,
+    pub reverted_at: Option<DateTime<Utc>>,
+}
+
+/// A requested address change before it is stored, the tokens go out in the confirm and revert links.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewEmailChange<'a> {
+    pub user_id: uuid::Uuid,
+    pub old_email: &'a str,
+    pub new_email: &'a str,
+    pub confirm_token: &'a str,
+    pub revert_token: &'a str,
+    pub expires_at: DateTime<Utc>,
+    pub revert_expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AccountDeletion {
+    pub user_id: uuid::Uuid,
+    pub requested_at: DateTime<Utc>,
+    pub delete_after: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct ChatPost {
     pub id: i32,
@@ -58,6 +270,28 @@ pub struct ChatPost {
     pub author: uuid::Uuid,
     pub author_name: String,
     pub content: String,
+    pub room_id: i32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ChatRoom {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub access: ChatRoomAccess,
+    pub section_id: Option<i64>,
+    pub allowed_for: Vec<UserRole>,
+    pub created_by: Option<uuid::Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ChatRoomMember {
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub joined_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -65,6 +299,164 @@ pub struct Section {
     pub id: i64,
     pub name: String,
     pub description: Option<String>,
+    pub parent_id: Option<i64>,
+    /// Order among the sections sharing the parent
+    pub position: i32,
+    /// Categories only group other sections and hold no threads themselves
+    pub category: bool,
+}
+
+/// Newest thread or reply in a section.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LatestActivity {
+    pub thread_id: i64,
+    /// Missing when the thread itself is the newest
+    pub post_id: Option<i64>,
+    pub title: String,
+    pub author: Option<uuid::Uuid>,
+    pub author_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Section as loaded for the section tree, counts and activity are its own without subsections.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SectionRow {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub parent_id: Option<i64>,
+    pub position: i32,
+    pub category: bool,
+    pub thread_count: i64,
+    pub post_count: i64,
+    pub latest_thread_id: Option<i64>,
+    pub latest_post_id: Option<i64>,
+    pub latest_title: Option<String>,
+    pub latest_author: Option<uuid::Uuid>,
+    pub latest_author_name: Option<String>,
+    pub latest_at: Option<DateTime<Utc>>,
+}
+
+impl SectionRow {
+    pub fn into_section(self) -> (Section, Option<LatestActivity>) {
+        let latest = match (self.latest_thread_id, self.latest_title, self.latest_at) {
+            (Some(thread_id), Some(title), Some(created_at)) => Some(LatestActivity {
+                thread_id,
+                post_id: self.latest_post_id,
+                title,
+                author: self.latest_author,
+                author_name: self.latest_author_name,
+                created_at,
+            }),
+            _ => None,
+        };
+
+        let section = Section {
+            id: self.id,
+            name: self.name,
+            description: self.description,
+            parent_id: self.parent_id,
+            position: self.position,
+            category: self.category,
+        };
+
+        (section, latest)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionRight {
+    View,
+    CreateThread,
+    Reply,
+    Moderate,
+}
+
+/// What one user may do in one section, combined over their role and groups.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct SectionPermissions {
+    pub section_id: i64,
+    pub can_view: bool,
+    pub can_create_thread: bool,
+    pub can_reply: bool,
+    pub can_moderate: bool,
+}
+
+impl SectionPermissions {
+    /// Every right but viewing needs the section to be visible as well.
+    pub fn allows(&self, right: SectionRight) -> bool {
+        self.can_view && match right {
+            SectionRight::View => true,
+            SectionRight::CreateThread => self.can_create_thread,
+            SectionRight::Reply => self.can_reply,
+            SectionRight::Moderate => self.can_moderate,
+        }
+    }
+}
+
+/// One entry of a section's permission list, for a role or a group.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+pub struct SectionRights {
+    pub can_view: bool,
+    pub can_create_thread: bool,
+    pub can_reply: bool,
+    pub can_moderate: bool,
+}
+
+/// Rights a role gets in a section, a role without an entry can't even see it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RoleSectionRights {
+    pub role: UserRole,
+    pub can_view: bool,
+    pub can_create_thread: bool,
+    pub can_reply: bool,
+    pub can_moderate: bool,
+}
+
+/// Rights the members of a group get in a section, on top of those of their role.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct GroupSectionRights {
+    pub group_id: i32,
+    pub group_name: String,
+    pub can_view: bool,
+    pub can_create_thread: bool,
+    pub can_reply: bool,
+    pub can_moderate: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UserGroup {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub member_count: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UserGroupMember {
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub added_at: DateTime<Utc>,
+}
+
+/// A user moderating one section, with every right in it whatever their role.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SectionModerator {
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub added_by: Option<uuid::Uuid>,
+    pub added_at: DateTime<Utc>,
+}
+
+/// What is left in a section, it can only be deleted right away when both are zero.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct SectionContent {
+    pub children: i64,
+    pub threads: i64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -84,6 +476,23 @@ pub struct Thread {
     pub section_id: i64,
     pub locked: bool,
     pub sticky: bool,
+    /// Rendered `content`, missing for threads written before rendering existed
+    pub content_html: Option<String>,
+    /// Pinned on top of every section
+    pub announcement: bool,
+    /// Set on the stub left behind when a thread is moved or merged, the thread to go to instead
+    pub redirect_to: Option<i64>,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub edit_count: i32,
+}
+
+/// The thread a split created. The post that became its opening post is gone, threads carry no
+/// likes or reactions so those of the post are counted here for the audit log.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SplitThread {
+    pub thread_id: i64,
+    pub dropped_likes: i32,
+    pub dropped_reactions: i64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -102,6 +511,331 @@ pub struct Post {
     pub created_at: DateTime<Utc>,
     pub modified_at: Option<DateTime<Utc>>,
     pub likes: i32,
+    /// Rendered `content`, missing for posts written before rendering existed
+    pub content_html: Option<String>,
+    pub edit_count: i32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ReactionCount {
+    pub post_id: i64,
+    pub emoji: String,
+    pub count: i64,
+    /// Whether the user asking is one of them
+    pub reacted: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Reactor {
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub emoji: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Post as loaded for the reply tree, `depth` counts from the posts the tree was started at.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ReplyRow {
+    pub id: i64,
+    pub content: String,
+    pub author: Option<uuid::Uuid>,
+    pub topic: i64,
+    pub comments: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub likes: i32,
+    pub content_html: Option<String>,
+    pub edit_count: i32,
+    pub deleted: bool,
+    pub depth: i32,
+    pub reply_count: i64,
+}
+
+impl ReplyRow {
+    pub fn into_post(self) -> Post {
+        Post {
+            id: self.id,
+            content: self.content,
+            author: self.author,
+            topic: self.topic,
+            comments: self.comments,
+            created_at: self.created_at,
+            modified_at: self.modified_at,
+            likes: self.likes,
+            content_html: self.content_html,
+            edit_count: self.edit_count,
+        }
+    }
+}
+
+/// Content of a post or thread as it was before an edit, with who made that edit, when and why.
+/// `title` is only kept for threads.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Revision {
+    pub id: i64,
+    pub post_id: Option<i64>,
+    pub thread_id: Option<i64>,
+    pub title: Option<String>,
+    pub content: String,
+    pub editor: Option<uuid::Uuid>,
+    pub editor_name: Option<String>,
+    pub edited_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line of a diff, numbered in the old and the new text where it appears there.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+/// What a trash entry is, threads take their posts along when deleted.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Thread,
+    Post,
+}
+
+impl TrashKind {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Thread => "thread",
+            Self::Post => "post",
+        }
+    }
+}
+
+/// A deleted thread or post waiting to be restored or purged. `title` is the thread's, for posts too.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub id: i64,
+    pub thread_id: i64,
+    pub section_id: i64,
+    pub title: String,
+    pub content: String,
+    pub author: Option<uuid::Uuid>,
+    pub author_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub deleted_at: DateTime<Utc>,
+    pub deleted_by: Option<uuid::Uuid>,
+    pub deleted_by_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "forum.report_target", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTarget {
+    Post,
+    Thread,
+    Chat,
+    Pm,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "forum.report_reason", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Spam,
+    Harassment,
+    Hate,
+    Illegal,
+    OffTopic,
+    Other,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "forum.report_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Open,
+    Resolved,
+    Dismissed,
+}
+
+/// Everything reported about one target while it is open, `content` is the target as it was when first reported.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Report {
+    pub id: i64,
+    pub target_type: ReportTarget,
+    pub target_id: i64,
+    pub target_author: Option<uuid::Uuid>,
+    pub target_author_name: Option<String>,
+    pub content: String,
+    pub status: ReportStatus,
+    pub created_at: DateTime<Utc>,
+    pub report_count: i64,
+    pub reasons: Vec<ReportReason>,
+    pub last_reported_at: Option<DateTime<Utc>>,
+    pub claimed_by: Option<uuid::Uuid>,
+    pub claimed_by_name: Option<String>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<uuid::Uuid>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution: Option<String>,
+    pub hidden_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ReportEntry {
+    pub reporter: uuid::Uuid,
+    pub reporter_name: String,
+    pub reason: ReportReason,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What the queue is filtered by, every field left out matches anything.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReportFilter {
+    pub status: Option<ReportStatus>,
+    pub target_type: Option<ReportTarget>,
+    pub reason: Option<ReportReason>,
+    pub claimed_by: Option<uuid::Uuid>,
+    pub unclaimed: bool,
+}
+
+/// The reported thread, post, chat or private message as it is when the report comes in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportedContent {
+    pub target_type: ReportTarget,
+    pub target_id: i64,
+    pub author: Option<uuid::Uuid>,
+    pub content: String,
+}
+
+/// Outcome of filing a report, `hidden` is set when this report pushed the target over the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReportFiled {
+    pub report_id: i64,
+    pub hidden: bool,
+}
+
+/// What a moderator did. `Other` is what the `audit::audited` layer writes for a privileged route that didn't describe itself.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    ThreadLock,
+    ThreadDelete,
+    ThreadEdit,
+    ThreadSticky,
+    ThreadAnnounce,
+    ThreadMove,
+    ThreadMerge,
+    ThreadSplit,
+    ThreadRestore,
+    ThreadRollback,
+    PostEdit,
+    PostDelete,
+    PostRestore,
+    PostRollback,
+    UserWarn,
+    UserUnban,
+    UserRole,
+    ReportResolve,
+    ReportDismiss,
+    ModeratorAdd,
+    ModeratorRemove,
+    ChatDelete,
+    Other,
+}
+
+impl AuditAction {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::ThreadLock => "thread_lock",
+            Self::ThreadDelete => "thread_delete",
+            Self::ThreadEdit => "thread_edit",
+            Self::ThreadSticky => "thread_sticky",
+            Self::ThreadAnnounce => "thread_announce",
+            Self::ThreadMove => "thread_move",
+            Self::ThreadMerge => "thread_merge",
+            Self::ThreadSplit => "thread_split",
+            Self::ThreadRestore => "thread_restore",
+            Self::ThreadRollback => "thread_rollback",
+            Self::PostEdit => "post_edit",
+            Self::PostDelete => "post_delete",
+            Self::PostRestore => "post_restore",
+            Self::PostRollback => "post_rollback",
+            Self::UserWarn => "user_warn",
+            Self::UserUnban => "user_unban",
+            Self::UserRole => "user_role",
+            Self::ReportResolve => "report_resolve",
+            Self::ReportDismiss => "report_dismiss",
+            Self::ModeratorAdd => "moderator_add",
+            Self::ModeratorRemove => "moderator_remove",
+            Self::ChatDelete => "chat_delete",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// What an audit entry is about, `Route` holds the request path for `AuditAction::Other`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AuditTarget {
+    Thread,
+    Post,
+    User,
+    Report,
+    Section,
+    Chat,
+    Route,
+}
+
+impl AuditTarget {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Thread => "thread",
+            Self::Post => "post",
+            Self::User => "user",
+            Self::Report => "report",
+            Self::Section => "section",
+            Self::Chat => "chat",
+            Self::Route => "route",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub actor: uuid::Uuid,
+    pub actor_name: String,
+    pub action: AuditAction,
+    pub target_type: AuditTarget,
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What the audit log is filtered by, every field left out matches anything.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AuditFilter {
+    pub actor: Option<uuid::Uuid>,
+    pub action: Option<AuditAction>,
+    pub target_type: Option<AuditTarget>,
+    pub target_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -128,3 +862,159 @@ pub struct PrivateMessage {
     pub receiver: uuid::Uuid,
     pub content: String,
 }
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Session {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UserTotp {
+    pub user_id: uuid::Uuid,
+    pub secret: String,
+    pub enabled: bool,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RecoveryCode {
+    pub id: i64,
+    pub user_id: uuid::Uuid,
+    pub code_hash: String,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct OidcState {
+    pub state: String,
+    pub provider: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub link_user: Option<uuid::Uuid>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UserIdentity {
+    pub id: i64,
+    pub user_id: uuid::Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub linked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LoginThrottle {
+    pub key: String,
+    pub failures: i32,
+    pub last_failure: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl LoginThrottle {
+    /// How long the key has to wait at `now` before the next attempt, given the failures before backoff starts.
+    /// The delay doubles with every failure from there, up to `max_delay_secs`.
+    pub fn retry_after(&self, now: DateTime<Utc>, backoff_after: i32, max_delay_secs: i64) -> Option<chrono::Duration> {
+        if let Some(until) = self.locked_until && until > now {
+            return Some(until - now);
+        }
+
+        if self.failures < backoff_after {
+            return None;
+        }
+
+        let exponent = (self.failures - backoff_after).min(16) as u32;
+        let delay = chrono::Duration::seconds((1i64 << exponent).min(max_delay_secs));
+        let next_attempt = self.last_failure + delay;
+
+        (next_attempt > now).then(|| next_attempt - now)
+    }
+
+    /// A key is locked once when it reaches `lock_after` failures, `record_failure` starts a fresh count after the lock.
+    pub fn should_lock(&self, lock_after: i32) -> bool {
+        self.failures >= lock_after && self.locked_until.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn throttle(failures: i32, last_failure: DateTime<Utc>, locked_until: Option<DateTime<Utc>>) -> LoginThrottle {
+        LoginThrottle { key: "account:test".to_string(), failures, last_failure, locked_until }
+    }
+
+    #[test]
+    fn no_wait_before_backoff_starts() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(throttle(0, now, None).retry_after(now, 3, 300), None);
+        assert_eq!(throttle(2, now, None).retry_after(now, 3, 300), None);
+    }
+
+    #[test]
+    fn backoff_doubles_per_failure() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        for (failures, secs) in [(3, 1), (4, 2), (5, 4), (8, 32), (11, 256)] {
+            assert_eq!(throttle(failures, now, None).retry_after(now, 3, 300), Some(Duration::seconds(secs)), "{} failures", failures);
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(throttle(12, now, None).retry_after(now, 3, 300), Some(Duration::seconds(300)));
+        // The exponent is capped as well, so a huge count can't overflow the shift
+        assert_eq!(throttle(i32::MAX, now, None).retry_after(now, 3, 300), Some(Duration::seconds(300)));
+    }
+
+    #[test]
+    fn backoff_counts_from_the_last_failure() {
+        let last = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        // 8 seconds after the 6th failure
+        assert_eq!(throttle(6, last, None).retry_after(last + Duration::seconds(3), 3, 300), Some(Duration::seconds(5)));
+        assert_eq!(throttle(6, last, None).retry_after(last + Duration::seconds(8), 3, 300), None);
+        assert_eq!(throttle(6, last, None).retry_after(last + Duration::seconds(60), 3, 300), None);
+    }
+
+    #[test]
+    fn lock_overrides_backoff_until_it_expires() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let until = now + Duration::minutes(15);
+
+        assert_eq!(throttle(10, now, Some(until)).retry_after(now, 3, 300), Some(Duration::minutes(15)));
+        assert_eq!(throttle(1, now, Some(until)).retry_after(now + Duration::minutes(5), 3, 300), Some(Duration::minutes(10)));
+        // Once the lock is over only the backoff is left
+        assert_eq!(throttle(1, now, Some(until)).retry_after(until, 3, 300), None);
+        assert_eq!(throttle(10, until, Some(until)).retry_after(until, 3, 300), Some(Duration::seconds(128)));
+    }
+
+    #[test]
+    fn locks_once_at_the_threshold() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+
+        assert!(!throttle(9, now, None).should_lock(10));
+        assert!(throttle(10, now, None).should_lock(10));
+        assert!(throttle(11, now, None).should_lock(10));
+        assert!(!throttle(11, now, Some(now + Duration::minutes(15))).should_lock(10));
+    }
+}
@@ -0,0 +1,129 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use crate::{
+    audit,
+    config::Config,
+    db::{permission::PermissionExt, two_factor::TwoFactorExt, user::UserExt, DBClient},
+    error::{ErrorMessage, HttpError},
+    models::{SectionPermissions, SectionRight, User, UserStatus},
+    AppState,
+};
+
+/// What an authenticated account is allowed to do right now.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Full,
+    ReadOnly,
+}
+
+impl Access {
+    pub fn allows(&self, method: &Method) -> bool {
+        match self {
+            Access::Full => true,
+            Access::ReadOnly => method.is_safe(),
+        }
+    }
+}
+
+/// Single place deciding whether an account may use the API, shared by the
+/// login flow and the `/users` and `/forum` routers.
+pub fn evaluate(user: &User, env: &Config) -> Result<Access, HttpError> {
+    if env.email_verification && !user.verified {
+        return Err(HttpError::forbidden(ErrorMessage::AccountNotVerified.to_string())
+            .with_code(ErrorMessage::AccountNotVerified));
+    }
+
+    match user.status() {
+        UserStatus::Banned => Ok(Access::ReadOnly),
+        _ => Ok(Access::Full),
+    }
+}
+
+/// Builds the error returned to a banned user trying to write, with the ban expiry and reason.
+pub async fn ban_error(db_client: &DBClient, user: &User) -> HttpError {
+    let reason = db_client.get_ban_reason(user.id)
+        .await
+        .unwrap_or_default();
+
+    HttpError::forbidden(ErrorMessage::AccountBanned.to_string())
+        .with_code(ErrorMessage::AccountBanned)
+        .with_details(json!({
+            "bannedUntil": user.banned_until,
+            "reason": reason,
+        }))
+}
+
+/// What a section permission is checked on, threads and posts stand for the section they are in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectionTarget {
+    Section(i64),
+    Thread(i64),
+    Post(i64),
+}
+
+/// Single place deciding what a user may do in a forum section, every `/forum` route touching a
+/// section, thread or post goes through it. Whatever the user can't view is reported as missing,
+/// so hidden sections don't give away what they contain.
+pub async fn section_access(app_state: &AppState, user: &User, target: SectionTarget, right: SectionRight) -> Result<SectionPermissions, HttpError> {
+    let (section_id, thread_id, post_id) = match target {
+        SectionTarget::Section(id) => (Some(id), None, None),
+        SectionTarget::Thread(id) => (None, Some(id), None),
+        SectionTarget::Post(id) => (None, None, Some(id)),
+    };
+
+    let permissions = app_state.db_client.get_section_permissions(user.id, section_id, thread_id, post_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|p| p.can_view);
+
+    let Some(permissions) = permissions else {
+        return Err(match target {
+            SectionTarget::Section(_) => HttpError::new(ErrorMessage::NoSuchSection.to_string(), StatusCode::NOT_FOUND)
+                .with_code(ErrorMessage::NoSuchSection),
+            SectionTarget::Thread(_) => HttpError::new("No such thread", StatusCode::NOT_FOUND),
+            SectionTarget::Post(_) => HttpError::new("No such post", StatusCode::NOT_FOUND),
+        });
+    };
+
+    if !permissions.allows(right) {
+        return Err(HttpError::forbidden(ErrorMessage::SectionPermissionDenied.to_string())
+            .with_code(ErrorMessage::SectionPermissionDenied)
+            .with_details(json!({ "right": right })));
+    }
+
+    if right == SectionRight::Moderate {
+        moderator_action(app_state, user).await?;
+    }
+
+    Ok(permissions)
+}
+
+/// Checks an action only `can_moderate` allows, for handlers that find that out after a weaker
+/// `section_access` check, like editing someone else's post.
+pub async fn moderator_action(app_state: &AppState, user: &User) -> Result<(), HttpError> {
+    require_two_factor(app_state, user).await?;
+    audit::mark_privileged();
+
+    Ok(())
+}
+
+/// With `REQUIRE_2FA_PRIVILEGED` set, moderating takes an enabled second factor, whether the right
+/// comes from the user's role or from a section's permissions.
+pub async fn require_two_factor(app_state: &AppState, user: &User) -> Result<(), HttpError> {
+    if !app_state.env.require_2fa_privileged {
+        return Ok(());
+    }
+
+    let enrolled = app_state.db_client.get_totp(user.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .is_some_and(|totp| totp.enabled);
+
+    if !enrolled {
+        return Err(HttpError::forbidden(ErrorMessage::TwoFactorRequired.to_string())
+            .with_code(ErrorMessage::TwoFactorRequired));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+/// Deeper nesting than this is left as written.
+const MAX_DEPTH: usize = 32;
+
+/// A tag that was opened and is waiting for its closing tag.
+struct Open<'a> {
+    name: String,
+    arg: Option<&'a str>,
+    /// The opening tag as written, put back when it is never closed
+    raw: &'a str,
+    inner: String,
+}
+
+/// Rewrites the usual BBCode tags as Markdown, so the rest of the pipeline only deals with one syntax.
+/// Unknown or unclosed tags are left as they were written.
+pub fn to_markdown(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut open: Vec<Open> = Vec::new();
+    // Once a [code] has no closing tag, no later one has either
+    let mut code_unclosed = false;
+    let mut rest = source;
+
+    while let Some(start) = rest.find('[') {
+        target(&mut out, &mut open).push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(']') else { break };
+        let raw = &rest[..=end];
+        let tag = &rest[1..end];
+        let after = &rest[end + 1..];
+
+        if let Some(closing) = tag.strip_prefix('/') {
+            if let Some(at) = open.iter().rposition(|o| o.name.eq_ignore_ascii_case(closing)) {
+                // Tags opened inside it that are still open were never closed
+                put_back(&mut open, &mut out, at + 1);
+                let tag = open.pop().expect("position is on the stack");
+                wrap(&tag.name, tag.arg, &tag.inner, target(&mut out, &mut open));
+                rest = after;
+                continue;
+            }
+        } else {
+            let (name, arg) = match tag.split_once('=') {
+                Some((name, arg)) => (name, Some(arg.trim().trim_matches('"'))),
+                None => (tag, None),
+            };
+            let name = name.trim().to_ascii_lowercase();
+
+            match name.as_str() {
+                // Code is taken as written, tags inside it included
+                "code" if !code_unclosed => match find_closing(after, "code") {
+                    Some((content, tail)) => {
+                        wrap("code", arg, content, target(&mut out, &mut open));
+                        rest = tail;
+                        continue;
+                    }
+                    None => code_unclosed = true,
+                },
+                "b" | "i" | "u" | "s" | "url" | "img" | "quote" if open.len() < MAX_DEPTH => {
+                    open.push(Open { name, arg, raw, inner: String::new() });
+                    rest = after;
+                    continue;
+                }
+                _ => (),
+            }
+        }
+
+        target(&mut out, &mut open).push('[');
+        rest = &rest[1..];
+    }
+
+    target(&mut out, &mut open).push_str(rest);
+    put_back(&mut open, &mut out, 0);
+    out
+}
+
+/// Where converted text goes: the innermost open tag, or the output once every tag is closed.
+fn target<'s>(out: &'s mut String, open: &'s mut [Open]) -> &'s mut String {
+    match open.last_mut() {
+        Some(tag) => &mut tag.inner,
+        None => out,
+    }
+}
+
+/// Puts the tags above `depth` back as they were written, with their content.
+fn put_back(open: &mut Vec<Open>, out: &mut String, depth: usize) {
+    while open.len() > depth {
+        let Some(tag) = open.pop() else { break };
+        let target = target(out, open);
+        target.push_str(tag.raw);
+        target.push_str(&tag.inner);
+    }
+}
+
+fn find_closing<'a>(text: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    let closing = format!("[/{}]", name);
+    let at = text
+        .match_indices("[/")
+        .map(|(at, _)| at)
+        .find(|&at| text.as_bytes()[at..].get(..closing.len()).is_some_and(|t| t.eq_ignore_ascii_case(closing.as_bytes())))?;
+    Some((&text[..at], &text[at + closing.len()..]))
+}
+
+fn wrap(name: &str, arg: Option<&str>, inner: &str, out: &mut String) {
+    match (name, arg) {
+        ("b", _) => out.push_str(&format!("**{}**", inner)),
+        ("i", _) => out.push_str(&format!("*{}*", inner)),
+        ("s", _) => out.push_str(&format!("~~{}~~", inner)),
+        ("u", _) => out.push_str(&format!("<u>{}</u>", inner)),
+        ("url", Some(href)) => out.push_str(&format!("[{}](<{}>)", inner, href)),
+        ("url", None) => out.push_str(&format!("<{}>", inner.trim())),
+        ("img", _) => out.push_str(&format!("![](<{}>)", inner.trim())),
+        ("code", _) => out.push_str(&format!("\n```\n{}\n```\n", inner.trim_matches('\n'))),
+        ("quote", author) => {
+            out.push_str("\n\n");
+            if let Some(author) = author {
+                out.push_str(&format!("> **{}** wrote:\n>\n", author));
+            }
+            for line in inner.trim().lines() {
+                out.push_str("> ");
+                out.push_str(line);
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+        _ => out.push_str(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_simple_tags() {
+        assert_eq!(to_markdown("[b]bold[/b] and [i]it[/i]"), "**bold** and *it*");
+        assert_eq!(to_markdown("[S]gone[/s] [u]under[/U]"), "~~gone~~ <u>under</u>");
+        assert_eq!(to_markdown("[url=\"https://a.example\"]site[/url]"), "[site](<https://a.example>)");
+        assert_eq!(to_markdown("[url] https://a.example [/url]"), "<https://a.example>");
+        assert_eq!(to_markdown("[img]https://a.example/x.png[/img]"), "![](<https://a.example/x.png>)");
+    }
+
+    #[test]
+    fn converts_nested_tags() {
+        assert_eq!(to_markdown("[b][i]both[/i] bold[/b]"), "***both* bold**");
+        assert_eq!(
+            to_markdown("[quote=alice]hi [b]there[/b]\nsecond[/quote]"),
+            "\n\n> **alice** wrote:\n>\n> hi **there**\n> second\n\n"
+        );
+    }
+
+    #[test]
+    fn code_keeps_tags_as_written() {
+        assert_eq!(to_markdown("[code][b]x[/b][/CODE]"), "\n```\n[b]x[/b]\n```\n");
+        assert_eq!(to_markdown("[b][code]x[/code][/b]"), "**\n```\nx\n```\n**");
+    }
+
+    #[test]
+    fn leaves_unknown_and_unclosed_tags() {
+        assert_eq!(to_markdown("[size=3]x[/size]"), "[size=3]x[/size]");
+        assert_eq!(to_markdown("[b]open"), "[b]open");
+        assert_eq!(to_markdown("[code]open [b]x[/b]"), "[code]open **x**");
+        assert_eq!(to_markdown("[b]x[/i]"), "[b]x[/i]");
+        assert_eq!(to_markdown("a [ b ] c ["), "a [ b ] c [");
+    }
+
+    #[test]
+    fn closing_an_outer_tag_puts_inner_ones_back() {
+        assert_eq!(to_markdown("[b][i]x[/b]"), "**[i]x**");
+        assert_eq!(to_markdown("[b][i]x[/b][/i]"), "**[i]x**[/i]");
+    }
+
+    #[test]
+    fn pathological_input_stays_linear() {
+        let unclosed = "[b]".repeat(40);
+        assert_eq!(to_markdown(&unclosed), unclosed);
+
+        let mixed = "[b][i][quote][url][code]".repeat(2000);
+        assert_eq!(to_markdown(&mixed), mixed);
+
+        let closers = "[/b]".repeat(10_000);
+        assert_eq!(to_markdown(&closers), closers);
+    }
+
+    #[test]
+    fn caps_the_nesting_depth() {
+        let depth = MAX_DEPTH + 8;
+        let source = format!("{}x{}", "[i]".repeat(depth), "[/i]".repeat(depth));
+        let converted = to_markdown(&source);
+
+        // The innermost tags beyond the cap stay as written, so do their surplus closing tags
+        assert_eq!(converted.matches("[i]").count(), 8);
+        assert_eq!(converted.matches("[/i]").count(), 8);
+        assert!(converted.starts_with(&"*".repeat(MAX_DEPTH)));
+    }
+}
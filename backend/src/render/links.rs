@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+
+use pulldown_cmark::{CowStr, Event};
+
+/// Frontend routes the generated links point at.
+pub const MENTION_PATH: &str = "/users/by-name/";
+pub const HASHTAG_PATH: &str = "/tags/";
+pub const POST_PATH: &str = "/posts/";
+
+/// Same limit as forum.hashtags.tag
+const MAX_HASHTAG_LEN: usize = 25;
+
+/// `>>123` at the start of a line would otherwise be read as a nested blockquote.
+pub fn escape_quote_refs(source: &str) -> Cow<'_, str> {
+    let mut in_fence = false;
+    let mut escaped: Option<String> = None;
+
+    for (i, line) in source.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+
+        let is_ref = !in_fence
+            && trimmed.strip_prefix(">>").is_some_and(|r| r.starts_with(|c: char| c.is_ascii_digit()));
+
+        if is_ref && escaped.is_none() {
+            let done: usize = source.split_inclusive('\n').take(i).map(str::len).sum();
+            escaped = Some(source[..done].to_string());
+        }
+
+        if let Some(out) = escaped.as_mut() {
+            if is_ref {
+                out.push_str(&line[..line.len() - trimmed.len()]);
+                out.push('\\');
+                out.push_str(trimmed);
+            } else {
+                out.push_str(line);
+            }
+        }
+    }
+
+    escaped.map_or(Cow::Borrowed(source), Cow::Owned)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The tag as stored in forum.hashtags: lower case, without the `#`.
+/// `None` for anything `linkify` would not turn into a hashtag link.
+pub fn normalize_hashtag(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag).to_lowercase();
+
+    let valid = tag.starts_with(char::is_alphabetic)
+        && tag.chars().all(is_tag_char)
+        && tag.chars().count() <= MAX_HASHTAG_LEN;
+
+    valid.then_some(tag)
+}
+
+/// Length of the @mention, #hashtag or >>reference starting at the beginning of `rest`, with its link.
+fn match_link(rest: &str) -> Option<(usize, String)> {
+    if let Some(name) = rest.strip_prefix('@') {
+        let len = name.find(|c| !is_name_char(c)).unwrap_or(name.len());
+        // A sentence can end right after a name
+        let name = name[..len].trim_end_matches('.');
+        if name.is_empty() {
+            return None;
+        }
+        let html = format!(r#"<a class="mention" href="{MENTION_PATH}{name}">@{name}</a>"#);
+        return Some((name.len() + 1, html));
+    }
+
+    if let Some(tag) = rest.strip_prefix('#') {
+        if !tag.starts_with(char::is_alphabetic) {
+            return None;
+        }
+        let len = tag.find(|c| !is_tag_char(c)).unwrap_or(tag.len());
+        let tag = &tag[..len];
+        if tag.chars().count() > MAX_HASHTAG_LEN {
+            return None;
+        }
+        let html = format!(r#"<a class="hashtag" href="{HASHTAG_PATH}{}">#{tag}</a>"#, tag.to_lowercase());
+        return Some((len + 1, html));
+    }
+
+    if let Some(id) = rest.strip_prefix(">>") {
+        let len = id.find(|c: char| !c.is_ascii_digit()).unwrap_or(id.len());
+        let id = id[..len].parse::<i64>().ok()?;
+        let html = format!(r#"<a class="quote-ref" href="{POST_PATH}{id}">&gt;&gt;{id}</a>"#);
+        return Some((len + 2, html));
+    }
+
+    None
+}
+
+/// Splits a text node around mentions, hashtags and quote references, which become inline links.
+/// Names and tags are limited to characters that are safe in an attribute, so nothing needs escaping.
+pub fn linkify<'a>(text: &CowStr<'a>) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+    let mut plain_from = 0;
+    let mut prev: Option<char> = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        // Only at the start of a word, so e-mail addresses and anchors in URLs stay alone
+        let at_word_start = prev.is_none_or(|p| !is_name_char(p) && p != '@' && p != '#' && p != '&');
+        prev = Some(c);
+
+        if !at_word_start || !matches!(c, '@' | '#' | '>') {
+            continue;
+        }
+
+        let Some((len, html)) = match_link(&text[i..]) else { continue };
+
+        if plain_from < i {
+            events.push(Event::Text(CowStr::from(text[plain_from..i].to_string())));
+        }
+        events.push(Event::InlineHtml(CowStr::from(html)));
+        plain_from = i + len;
+
+        while chars.peek().is_some_and(|(j, _)| *j < plain_from) {
+            prev = chars.next().map(|(_, c)| c);
+        }
+    }
+
+    if plain_from == 0 {
+        return vec![Event::Text(text.clone())];
+    }
+    if plain_from < text.len() {
+        events.push(Event::Text(CowStr::from(text[plain_from..].to_string())));
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linkified(text: &str) -> Vec<Event<'static>> {
+        linkify(&CowStr::from(text.to_string()))
+    }
+
+    #[test]
+    fn escapes_quote_refs_at_line_start() {
+        assert_eq!(escape_quote_refs(">>12 agreed\n  >>3\n"), "\\>>12 agreed\n  \\>>3\n");
+        assert_eq!(escape_quote_refs("see >>12\n> quote\n>>x"), "see >>12\n> quote\n>>x");
+        assert!(matches!(escape_quote_refs("plain\n>>x"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn leaves_quote_refs_in_code_fences() {
+        let source = "```\n>>12\n```\n>>13";
+        assert_eq!(escape_quote_refs(source), "```\n>>12\n```\n\\>>13");
+    }
+
+    #[test]
+    fn links_mentions_hashtags_and_refs() {
+        let events = linkified("hi @bob.smith. #Rust >>42");
+        assert_eq!(events, vec![
+            Event::Text("hi ".into()),
+            Event::InlineHtml(r#"<a class="mention" href="/users/by-name/bob.smith">@bob.smith</a>"#.into()),
+            Event::Text(". ".into()),
+            Event::InlineHtml(r##"<a class="hashtag" href="/tags/rust">#Rust</a>"##.into()),
+            Event::Text(" ".into()),
+            Event::InlineHtml(r#"<a class="quote-ref" href="/posts/42">&gt;&gt;42</a>"#.into()),
+        ]);
+    }
+
+    #[test]
+    fn skips_text_that_only_looks_like_a_link() {
+        for text in ["mail bob@example.com", "page#anchor", "# heading", "#1st", "@ alone", ">> 12", "a>>12"] {
+            assert_eq!(linkified(text), vec![Event::Text(text.into())], "{}", text);
+        }
+
+        let long = format!("#{}", "a".repeat(MAX_HASHTAG_LEN + 1));
+        assert_eq!(linkified(&long), vec![Event::Text(long.clone().into())]);
+    }
+
+    #[test]
+    fn normalizes_hashtags() {
+        assert_eq!(normalize_hashtag(" #Rust_Lang ").as_deref(), Some("rust_lang"));
+        assert_eq!(normalize_hashtag("rust").as_deref(), Some("rust"));
+        assert_eq!(normalize_hashtag("#1st"), None);
+        assert_eq!(normalize_hashtag("rust-lang"), None);
+        assert_eq!(normalize_hashtag(&"a".repeat(MAX_HASHTAG_LEN + 1)), None);
+    }
+}
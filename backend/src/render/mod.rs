@@ -0,0 +1,98 @@
+use std::{borrow::Cow, sync::LazyLock};
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd, TextMergeStream};
+
+mod bbcode;
+mod links;
+
+pub use links::normalize_hashtag;
+
+/// Everything the renderer emits goes through this allow-list, user written HTML included.
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_allowed_classes("a", &["mention", "hashtag", "quote-ref"])
+        .link_rel(Some("noopener noreferrer nofollow"));
+    builder
+});
+
+/// Renders post or thread content to sanitized HTML.
+/// With `bbcode` set the common BBCode tags are understood as well, on top of Markdown.
+pub fn render(source: &str, bbcode: bool) -> String {
+    let source = if bbcode { Cow::Owned(bbcode::to_markdown(source)) } else { Cow::Borrowed(source) };
+    let source = links::escape_quote_refs(&source);
+
+    let mut code_depth = 0;
+    let mut link_depth = 0;
+
+    let parser = TextMergeStream::new(Parser::new_ext(&source, Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES));
+    let events = parser.flat_map(|event| {
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => code_depth += 1,
+            Event::End(TagEnd::CodeBlock) => code_depth -= 1,
+            Event::Start(Tag::Link { .. }) | Event::Start(Tag::Image { .. }) => link_depth += 1,
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => link_depth -= 1,
+            // Mentions inside code or an existing link stay plain text
+            Event::Text(text) if code_depth == 0 && link_depth == 0 => return links::linkify(text),
+            _ => (),
+        }
+
+        vec![event]
+    });
+
+    let mut unsafe_html = String::with_capacity(source.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, events);
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+/// Fills in the cached HTML of rows written before content was rendered.
+pub fn fill_cached(source: &str, html: &mut Option<String>, bbcode: bool) {
+    if html.is_none() {
+        *html = Some(render(source, bbcode));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_scripts_and_handlers() {
+        let html = render("<script>alert(1)</script><img src=x onerror=alert(1)><a href=\"javascript:alert(1)\">x</a>", false);
+        assert!(!html.contains("script"), "{}", html);
+        assert!(!html.contains("onerror"), "{}", html);
+        assert!(!html.contains("javascript:"), "{}", html);
+    }
+
+    #[test]
+    fn keeps_only_allowed_classes() {
+        let html = render(r#"<a class="mention evil" href="/x">x</a> <span class="hashtag">y</span>"#, false);
+        assert!(html.contains(r#"class="mention""#), "{}", html);
+        assert!(!html.contains("evil"), "{}", html);
+        assert!(!html.contains(r#"<span class="hashtag">"#), "{}", html);
+    }
+
+    #[test]
+    fn links_get_rel_and_generated_links_survive() {
+        let html = render("[site](https://a.example) @bob #rust\n\n>>7", false);
+        assert!(html.contains(r#"rel="noopener noreferrer nofollow""#), "{}", html);
+        assert!(html.contains(r#"<a class="mention" href="/users/by-name/bob""#), "{}", html);
+        assert!(html.contains(r#"<a class="hashtag" href="/tags/rust""#), "{}", html);
+        assert!(html.contains(r#"<a class="quote-ref" href="/posts/7""#), "{}", html);
+    }
+
+    #[test]
+    fn leaves_mentions_in_code_alone() {
+        let html = render("`@bob` and\n\n```\n#rust\n```", false);
+        assert!(!html.contains("mention"), "{}", html);
+        assert!(!html.contains("hashtag"), "{}", html);
+    }
+
+    #[test]
+    fn renders_bbcode_only_when_asked() {
+        assert!(render("[b]x[/b]", true).contains("<strong>x</strong>"));
+        assert!(render("[b]x[/b]", false).contains("[b]x[/b]"));
+    }
+}
@@ -0,0 +1,46 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::config::StorageConfig;
+
+pub mod local;
+pub mod s3;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "storage io error: {}", e),
+            StorageError::Backend(e) => write!(f, "storage backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+/// Blob store for user uploads, keys are `/` separated relative paths like `avatars/<id>/64.webp`.
+#[async_trait]
+pub trait Storage: fmt::Debug + Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+pub fn from_config(config: &StorageConfig) -> Arc<dyn Storage> {
+    match config {
+        StorageConfig::Local { path } => Arc::new(local::LocalStorage::new(path)),
+        StorageConfig::S3 { .. } => Arc::new(s3::S3Storage::new(config)),
+    }
+}
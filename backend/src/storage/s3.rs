@@ -0,0 +1,222 @@
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    config::{Builder, Credentials, Region},
+    primitives::ByteStream,
+    Client,
+};
+
+use crate::config::StorageConfig;
+use super::{Storage, StorageError};
+
+/// Works with AWS as well as S3 compatible servers such as MinIO when an endpoint is set.
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+}
+
+impl S3Storage {
+    pub fn new(config: &StorageConfig) -> Self {
+        let StorageConfig::S3 { bucket, region, endpoint, access_key, secret_key, path_style } = config else {
+            panic!("S3Storage needs an S3 storage config");
+        };
+
+        let mut builder = Builder::new()
+            .region(Region::new(region.clone()))
+            .credentials_provider(Credentials::new(access_key, secret_key, None, None, "forum"))
+            .force_path_style(*path_style);
+
+        if let Some(endpoint) = endpoint {
+            builder = builder.endpoint_url(endpoint);
+        }
+
+        S3Storage {
+            client: Client::from_conf(builder.build()),
+            bucket: bucket.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        self.client.put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .map_err(|e| StorageError::Backend(e.into_service_error().to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let output = match self.client.get_object().bucket(&self.bucket).key(key).send().await {
+            Ok(output) => output,
+            Err(e) => {
+                let e = e.into_service_error();
+                if e.is_no_such_key() {
+                    return Ok(None);
+                }
+                return Err(StorageError::Backend(e.to_string()));
+            }
+        };
+
+        let data = output.body.collect()
+            .await
+            .map_err(|e| StorageError::Backend(e.to_string()))?;
+
+        Ok(Some(data.into_bytes().to_vec()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.client.delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| StorageError::Backend(e.into_service_error().to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Runs against an in-memory stand-in for the three S3 calls used here. Set `S3_TEST_ENDPOINT`
+/// (with `S3_TEST_BUCKET`, `S3_TEST_ACCESS_KEY` and `S3_TEST_SECRET_KEY`) and run with `--ignored`
+/// to go through the same steps against a real server such as MinIO.
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+    use axum::{
+        body::Bytes,
+        extract::{Path, State},
+        http::{header, HeaderMap, StatusCode},
+        response::IntoResponse,
+        routing::put,
+        Router,
+    };
+
+    use super::*;
+
+    type Objects = Arc<Mutex<HashMap<String, (String, Vec<u8>)>>>;
+
+    const NO_SUCH_KEY: &str = r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>"#;
+
+    /// Strips `aws-chunked` framing, the SDK uses it to send checksums as a trailer.
+    fn decode_body(headers: &HeaderMap, body: &[u8]) -> Vec<u8> {
+        let chunked = headers.get(header::CONTENT_ENCODING)
+            .is_some_and(|v| v.to_str().unwrap_or_default().contains("aws-chunked"));
+        if !chunked {
+            return body.to_vec();
+        }
+
+        let mut data = Vec::new();
+        let mut rest = body;
+        loop {
+            let line_end = rest.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size_field = std::str::from_utf8(&rest[..line_end]).unwrap();
+            let size = usize::from_str_radix(size_field.split(';').next().unwrap(), 16).unwrap();
+            if size == 0 {
+                return data;
+            }
+            data.extend_from_slice(&rest[line_end + 2..line_end + 2 + size]);
+            rest = &rest[line_end + 2 + size + 2..];
+        }
+    }
+
+    async fn put_object(State(objects): State<Objects>, Path((bucket, key)): Path<(String, String)>, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+        objects.lock().unwrap().insert(format!("{}/{}", bucket, key), (content_type, decode_body(&headers, &body)));
+        StatusCode::OK
+    }
+
+    async fn get_object(State(objects): State<Objects>, Path((bucket, key)): Path<(String, String)>) -> impl IntoResponse {
+        match objects.lock().unwrap().get(&format!("{}/{}", bucket, key)) {
+            Some((content_type, data)) => (StatusCode::OK, [(header::CONTENT_TYPE, content_type.clone())], data.clone()).into_response(),
+            None => (StatusCode::NOT_FOUND, [(header::CONTENT_TYPE, "application/xml".to_string())], NO_SUCH_KEY).into_response(),
+        }
+    }
+
+    async fn delete_object(State(objects): State<Objects>, Path((bucket, key)): Path<(String, String)>) -> impl IntoResponse {
+        objects.lock().unwrap().remove(&format!("{}/{}", bucket, key));
+        StatusCode::NO_CONTENT
+    }
+
+    async fn start_stand_in() -> (String, Objects) {
+        let objects = Objects::default();
+        let app = Router::new()
+            .route("/{bucket}/{*key}", put(put_object).get(get_object).delete(delete_object))
+            .with_state(objects.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (endpoint, objects)
+    }
+
+    fn storage(endpoint: &str, bucket: &str, access_key: &str, secret_key: &str) -> S3Storage {
+        S3Storage::new(&StorageConfig::S3 {
+            bucket: bucket.to_string(),
+            region: "us-east-1".to_string(),
+            endpoint: Some(endpoint.to_string()),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            path_style: true,
+        })
+    }
+
+    async fn round_trip(storage: &S3Storage) {
+        let key = format!("avatars/{}/64.webp", uuid::Uuid::new_v4().simple());
+
+        assert_eq!(storage.get(&key).await.unwrap(), None);
+
+        storage.put(&key, b"first".to_vec(), "image/webp").await.unwrap();
+        assert_eq!(storage.get(&key).await.unwrap().as_deref(), Some(&b"first"[..]));
+
+        storage.put(&key, b"second".to_vec(), "image/webp").await.unwrap();
+        assert_eq!(storage.get(&key).await.unwrap().as_deref(), Some(&b"second"[..]));
+
+        storage.delete(&key).await.unwrap();
+        assert_eq!(storage.get(&key).await.unwrap(), None);
+
+        // Deleting what isn't there is not an error, the same as with local storage
+        storage.delete(&key).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stores_and_deletes_objects() {
+        let (endpoint, objects) = start_stand_in().await;
+        let storage = storage(&endpoint, "forum", "key", "secret");
+
+        round_trip(&storage).await;
+
+        storage.put("avatars/abc/128.webp", vec![1, 2, 3], "image/webp").await.unwrap();
+        let stored = objects.lock().unwrap().get("forum/avatars/abc/128.webp").cloned();
+        assert_eq!(stored, Some(("image/webp".to_string(), vec![1, 2, 3])));
+    }
+
+    #[tokio::test]
+    async fn reports_backend_errors() {
+        // Nothing listens on the port any more once the listener is dropped
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let storage = storage(&endpoint, "forum", "key", "secret");
+        assert!(matches!(storage.get("avatars/abc/64.webp").await, Err(StorageError::Backend(_))));
+        assert!(matches!(storage.put("avatars/abc/64.webp", vec![1], "image/webp").await, Err(StorageError::Backend(_))));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3 compatible server, see the module docs"]
+    async fn stores_and_deletes_objects_on_a_real_server() {
+        let var = |name: &str| std::env::var(name).unwrap_or_else(|_| panic!("{} must be set", name));
+        let storage = storage(&var("S3_TEST_ENDPOINT"), &var("S3_TEST_BUCKET"), &var("S3_TEST_ACCESS_KEY"), &var("S3_TEST_SECRET_KEY"));
+
+        round_trip(&storage).await;
+    }
+}
@@ -0,0 +1,213 @@
+use std::{fmt, io::Cursor};
+
+use image::{
+    imageops::FilterType, metadata::Orientation, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits,
+};
+
+/// Edge lengths of the square thumbnails generated for each upload, largest first.
+pub const AVATAR_SIZES: [u32; 3] = [256, 128, 64];
+pub const MAX_AVATAR_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_AVATAR_DIMENSION: u32 = 4096;
+const MAX_DECODE_ALLOC: u64 = 128 * 1024 * 1024;
+
+pub const AVATAR_CONTENT_TYPE: &str = "image/webp";
+
+#[derive(Debug)]
+pub enum AvatarError {
+    UnsupportedFormat,
+    FileTooLarge,
+    TooLarge,
+    Invalid(String),
+}
+
+impl fmt::Display for AvatarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AvatarError::UnsupportedFormat => write!(f, "Avatar must be a PNG, JPEG, GIF or WebP image"),
+            AvatarError::FileTooLarge => write!(f, "Avatar may be at most {} bytes", MAX_AVATAR_BYTES),
+            AvatarError::TooLarge => write!(f, "Avatar may be at most {0}x{0} pixels", MAX_AVATAR_DIMENSION),
+            AvatarError::Invalid(e) => write!(f, "Avatar could not be decoded: {}", e),
+        }
+    }
+}
+
+impl From<ImageError> for AvatarError {
+    fn from(e: ImageError) -> Self {
+        match e {
+            ImageError::Limits(_) => AvatarError::TooLarge,
+            ImageError::Unsupported(_) => AvatarError::UnsupportedFormat,
+            e => AvatarError::Invalid(e.to_string()),
+        }
+    }
+}
+
+/// Storage key of one thumbnail, `version` is the value kept in `users.avatar`.
+pub fn avatar_key(version: &str, size: u32) -> String {
+    format!("avatars/{}/{}.webp", version, size)
+}
+
+/// Uploaded avatars are named by a simple uuid, anything else is a legacy or default value.
+pub fn is_uploaded(version: &str) -> bool {
+    version.len() == 32 && version.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Detects the format from the magic bytes, never from the client supplied name or type,
+/// and returns a WebP thumbnail for every entry of `AVATAR_SIZES`.
+/// Re-encoding the decoded pixels leaves EXIF and any other metadata behind,
+/// the orientation tag is applied first so photos are not left rotated.
+pub fn process(data: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, AvatarError> {
+    if data.len() > MAX_AVATAR_BYTES {
+        return Err(AvatarError::FileTooLarge);
+    }
+
+    let format = image::guess_format(data).map_err(|_| AvatarError::UnsupportedFormat)?;
+    if !matches!(format, ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP) {
+        return Err(AvatarError::UnsupportedFormat);
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_AVATAR_DIMENSION);
+    limits.max_image_height = Some(MAX_AVATAR_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    // Cropped to a square before scaling, `resize_to_fill` would first scale a long thin image
+    // up to cover the thumbnail and allocate far beyond the decode limits
+    let side = image.width().min(image.height());
+    let square = image.crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side);
+
+    AVATAR_SIZES.iter()
+        .map(|&size| {
+            let thumbnail = square.resize_exact(size, size, FilterType::Lanczos3).to_rgba8();
+            let mut out = Cursor::new(Vec::new());
+            thumbnail.write_to(&mut out, ImageFormat::WebP)?;
+            Ok((size, out.into_inner()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    const RED: Rgb<u8> = Rgb([255, 0, 0]);
+    const BLUE: Rgb<u8> = Rgb([0, 0, 255]);
+
+    /// Red on the left half, blue on the right.
+    fn halves(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, _| if x < width / 2 { RED } else { BLUE })
+    }
+
+    fn encode(image: &RgbImage, format: ImageFormat) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    /// Puts an EXIF segment with just the orientation tag right after the JPEG start marker.
+    fn with_exif_orientation(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\x00\x2a\x00\x00\x00\x08".to_vec();
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        // Tag 0x0112, type SHORT, one value, padded to four bytes
+        tiff.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        let mut segment = b"Exif\x00\x00".to_vec();
+        segment.extend_from_slice(&tiff);
+
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xff, 0xe1]);
+        out.extend_from_slice(&((segment.len() + 2) as u16).to_be_bytes());
+        out.extend_from_slice(&segment);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    fn decode(webp: &[u8]) -> image::RgbaImage {
+        image::load_from_memory_with_format(webp, ImageFormat::WebP).unwrap().to_rgba8()
+    }
+
+    fn is_reddish(pixel: &image::Rgba<u8>) -> bool {
+        pixel[0] > 200 && pixel[2] < 60
+    }
+
+    fn is_bluish(pixel: &image::Rgba<u8>) -> bool {
+        pixel[2] > 200 && pixel[0] < 60
+    }
+
+    #[test]
+    fn makes_a_webp_thumbnail_per_size() {
+        for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif, ImageFormat::WebP] {
+            let thumbnails = process(&encode(&halves(300, 200), format)).unwrap();
+
+            assert_eq!(thumbnails.iter().map(|(size, _)| *size).collect::<Vec<_>>(), AVATAR_SIZES, "{:?}", format);
+            for (size, data) in thumbnails {
+                assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::WebP);
+                assert_eq!(decode(&data).dimensions(), (size, size));
+            }
+        }
+    }
+
+    #[test]
+    fn detects_the_format_from_magic_bytes() {
+        assert!(matches!(process(b"definitely not an image"), Err(AvatarError::UnsupportedFormat)));
+        assert!(matches!(process(b""), Err(AvatarError::UnsupportedFormat)));
+
+        // Valid images in formats avatars don't allow
+        assert!(matches!(process(b"BM\x3a\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00"), Err(AvatarError::UnsupportedFormat)));
+        assert!(matches!(process(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Err(AvatarError::UnsupportedFormat)));
+
+        // The right magic bytes with nothing decodable behind them
+        let mut png = encode(&halves(8, 8), ImageFormat::Png);
+        png.truncate(20);
+        assert!(matches!(process(&png), Err(AvatarError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_too_many_pixels() {
+        // Long and thin is fine as long as neither side is over the limit
+        assert!(process(&encode(&halves(MAX_AVATAR_DIMENSION, 2), ImageFormat::Png)).is_ok());
+        assert!(matches!(process(&encode(&halves(MAX_AVATAR_DIMENSION + 1, 2), ImageFormat::Png)), Err(AvatarError::TooLarge)));
+        assert!(matches!(process(&encode(&halves(2, MAX_AVATAR_DIMENSION + 1), ImageFormat::Png)), Err(AvatarError::TooLarge)));
+    }
+
+    #[test]
+    fn rejects_too_many_bytes() {
+        let mut data = encode(&halves(8, 8), ImageFormat::Png);
+        data.resize(MAX_AVATAR_BYTES + 1, 0);
+
+        assert!(matches!(process(&data), Err(AvatarError::FileTooLarge)));
+    }
+
+    #[test]
+    fn applies_and_strips_exif() {
+        let jpeg = with_exif_orientation(&encode(&halves(64, 32), ImageFormat::Jpeg), 6);
+        assert!(jpeg.windows(4).any(|w| w == b"Exif"));
+
+        for (_, data) in process(&jpeg).unwrap() {
+            assert!(!data.windows(4).any(|w| w == b"Exif" || w == b"EXIF"));
+
+            // Orientation 6 turns the picture a quarter clockwise, the red left half ends up on top
+            let image = decode(&data);
+            let (width, height) = image.dimensions();
+            assert!(is_reddish(image.get_pixel(width / 2, height / 8)));
+            assert!(is_bluish(image.get_pixel(width / 2, height - 1 - height / 8)));
+        }
+
+        // Without the tag the halves stay side by side
+        let (_, data) = &process(&encode(&halves(64, 32), ImageFormat::Jpeg)).unwrap()[0];
+        let image = decode(data);
+        assert!(is_reddish(image.get_pixel(0, 128)));
+        assert!(is_bluish(image.get_pixel(255, 128)));
+    }
+}
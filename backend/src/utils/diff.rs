@@ -0,0 +1,60 @@
+use similar::{ChangeTag, TextDiff};
+
+use crate::models::{DiffLine, DiffOp};
+
+/// Line by line difference between two versions of a post, trailing newlines are not part of the text.
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(op: DiffOp, old_line: Option<usize>, new_line: Option<usize>, text: &str) -> DiffLine {
+        DiffLine { op, old_line, new_line, text: text.to_string() }
+    }
+
+    #[test]
+    fn numbers_lines_on_each_side() {
+        let diff = line_diff("one\ntwo\nthree\n", "one\n2\nthree\nfour");
+
+        assert_eq!(diff, vec![
+            line(DiffOp::Equal, Some(1), Some(1), "one"),
+            line(DiffOp::Delete, Some(2), None, "two"),
+            line(DiffOp::Insert, None, Some(2), "2"),
+            line(DiffOp::Equal, Some(3), Some(3), "three"),
+            line(DiffOp::Insert, None, Some(4), "four"),
+        ]);
+    }
+
+    #[test]
+    fn deleted_lines_shift_the_new_numbers() {
+        let diff = line_diff("a\r\nb\r\nc", "a\r\nc");
+
+        assert_eq!(diff, vec![
+            line(DiffOp::Equal, Some(1), Some(1), "a"),
+            line(DiffOp::Delete, Some(2), None, "b"),
+            line(DiffOp::Equal, Some(3), Some(2), "c"),
+        ]);
+    }
+
+    #[test]
+    fn handles_empty_sides() {
+        assert!(line_diff("", "").is_empty());
+        assert_eq!(line_diff("", "new"), vec![line(DiffOp::Insert, None, Some(1), "new")]);
+        assert_eq!(line_diff("old\n", ""), vec![line(DiffOp::Delete, Some(1), None, "old")]);
+    }
+}
@@ -1,5 +1,10 @@
 pub mod token;
 pub mod password;
+pub mod totp;
+pub mod oidc;
+pub mod avatar;
+pub mod search;
+pub mod diff;
 
 #[macro_export]
 macro_rules! make_enum {
@@ -0,0 +1,197 @@
+use data_encoding::BASE64URL_NOPAD;
+
+use crate::models::SearchCursor;
+
+/// Marks around matched words in `ts_headline` output, replaced by `<mark>` once the snippet is escaped.
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_STOP: char = '\u{3}';
+
+/// More terms than this are dropped, a query that long would only slow the index scan down.
+const MAX_TERMS: usize = 32;
+
+/// Turns what the user typed into a `to_tsquery` expression.
+/// Words are ANDed, `"quoted words"` must follow each other, `word*` matches a prefix,
+/// `-word` excludes and `OR` between two terms matches either.
+/// Returns `None` when nothing searchable is left.
+pub fn to_tsquery(input: &str) -> Option<String> {
+    // Each group is ANDed with the others, terms within a group are ORed
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut or_next = false;
+    let mut rest = input.trim_start();
+
+    while !rest.is_empty() && groups.iter().map(Vec::len).sum::<usize>() < MAX_TERMS {
+        let negated = rest.starts_with('-');
+        if negated {
+            rest = &rest[1..];
+        }
+
+        let raw;
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            raw = &quoted[..end];
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            raw = &rest[..end];
+            rest = &rest[end..];
+            if !negated && raw == "OR" {
+                or_next = !groups.is_empty();
+                rest = rest.trim_start();
+                continue;
+            }
+        }
+        rest = rest.trim_start();
+
+        let Some(term) = phrase(raw) else { continue };
+        let term = if negated { format!("!{}", term) } else { term };
+
+        match groups.last_mut() {
+            Some(group) if or_next => group.push(term),
+            _ => groups.push(vec![term]),
+        }
+        or_next = false;
+    }
+
+    if groups.is_empty() {
+        return None;
+    }
+
+    let groups: Vec<String> = groups.into_iter()
+        .map(|group| match group.len() {
+            1 => group.into_iter().next().unwrap_or_default(),
+            _ => format!("({})", group.join(" | ")),
+        })
+        .collect();
+
+    Some(groups.join(" & "))
+}
+
+/// Words of one term joined by the followed-by operator, with a trailing `*` kept as a prefix match.
+/// Only letters and digits make it into a lexeme, so the result needs no further escaping.
+fn phrase(raw: &str) -> Option<String> {
+    let words: Vec<&str> = raw.split(|c: char| !c.is_alphanumeric() && c != '*')
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .collect();
+
+    let lexemes: Vec<String> = words.iter()
+        .map(|word| {
+            let prefix = word.ends_with('*');
+            let lexeme: String = word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect();
+            if prefix { format!("'{}':*", lexeme) } else { format!("'{}'", lexeme) }
+        })
+        .collect();
+
+    match lexemes.len() {
+        0 => None,
+        1 => lexemes.into_iter().next(),
+        _ => Some(format!("({})", lexemes.join(" <-> "))),
+    }
+}
+
+/// Escapes a `ts_headline` snippet and turns its highlight marks into `<mark>` tags.
+pub fn highlight(snippet: &str) -> String {
+    let mut out = String::with_capacity(snippet.len() + 16);
+    for c in snippet.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            HIGHLIGHT_START => out.push_str("<mark>"),
+            HIGHLIGHT_STOP => out.push_str("</mark>"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn encode_cursor(cursor: &SearchCursor) -> String {
+    BASE64URL_NOPAD.encode(&serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+pub fn decode_cursor(cursor: &str) -> Option<SearchCursor> {
+    let bytes = BASE64URL_NOPAD.decode(cursor.as_bytes()).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ands_plain_words() {
+        assert_eq!(to_tsquery("Rust  async").as_deref(), Some("'rust' & 'async'"));
+        assert_eq!(to_tsquery("don't").as_deref(), Some("('don' <-> 't')"));
+    }
+
+    #[test]
+    fn quoted_words_follow_each_other() {
+        assert_eq!(to_tsquery(r#""borrow checker" rules"#).as_deref(), Some("('borrow' <-> 'checker') & 'rules'"));
+        assert_eq!(to_tsquery(r#""unterminated quote"#).as_deref(), Some("('unterminated' <-> 'quote')"));
+    }
+
+    #[test]
+    fn star_matches_a_prefix() {
+        assert_eq!(to_tsquery("async* rust").as_deref(), Some("'async':* & 'rust'"));
+        assert_eq!(to_tsquery(r#""tokio run*""#).as_deref(), Some("('tokio' <-> 'run':*)"));
+        assert_eq!(to_tsquery("*"), None);
+    }
+
+    #[test]
+    fn minus_negates() {
+        assert_eq!(to_tsquery("rust -java").as_deref(), Some("'rust' & !'java'"));
+        assert_eq!(to_tsquery(r#"rust -"old news""#).as_deref(), Some("'rust' & !('old' <-> 'news')"));
+        assert_eq!(to_tsquery("rust -OR").as_deref(), Some("'rust' & !'or'"));
+    }
+
+    #[test]
+    fn or_joins_neighbouring_terms() {
+        assert_eq!(to_tsquery("rust OR go async").as_deref(), Some("('rust' | 'go') & 'async'"));
+        assert_eq!(to_tsquery("a OR b OR c").as_deref(), Some("('a' | 'b' | 'c')"));
+        // Nothing to join with at the start, and a lowercase or is a word
+        assert_eq!(to_tsquery("OR rust").as_deref(), Some("'rust'"));
+        assert_eq!(to_tsquery("this or that").as_deref(), Some("'this' & 'or' & 'that'"));
+    }
+
+    #[test]
+    fn drops_everything_unsearchable() {
+        assert_eq!(to_tsquery(""), None);
+        assert_eq!(to_tsquery("  - \"\" OR ' & | ! <->"), None);
+        assert_eq!(to_tsquery("a'); DROP TABLE x; --").as_deref(), Some("'a' & 'drop' & 'table' & 'x'"));
+    }
+
+    #[test]
+    fn caps_the_number_of_terms() {
+        let input: Vec<String> = (0..MAX_TERMS + 10).map(|i| format!("w{}", i)).collect();
+        let query = to_tsquery(&input.join(" ")).unwrap_or_default();
+
+        assert_eq!(query.split(" & ").count(), MAX_TERMS);
+        assert!(query.ends_with(&format!("'w{}'", MAX_TERMS - 1)));
+    }
+
+    #[test]
+    fn highlight_escapes_the_snippet() {
+        let snippet = format!("<b>\"a\" & 'b'</b> {}match{}", HIGHLIGHT_START, HIGHLIGHT_STOP);
+        assert_eq!(
+            highlight(&snippet),
+            "&lt;b&gt;&quot;a&quot; &amp; &#39;b&#39;&lt;/b&gt; <mark>match</mark>"
+        );
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = SearchCursor { key: 0.125, kind: "post".to_string(), id: 42 };
+        let encoded = encode_cursor(&cursor);
+
+        assert!(encoded.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(decode_cursor(&encoded), Some(cursor));
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        assert_eq!(decode_cursor("not base64!"), None);
+        assert_eq!(decode_cursor(&BASE64URL_NOPAD.encode(b"{\"key\":1}")), None);
+        assert_eq!(decode_cursor(""), None);
+    }
+}
@@ -10,18 +10,21 @@ use jsonwebtoken::{
     Validation
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::error::{ErrorMessage, HttpError};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims{
     pub sub: String,
+    pub sid: String,
     pub iat: usize,
     pub exp: usize,
 }
 
 pub fn create_token(
     user_id: &str,
+    session_id: &str,
     secret: &[u8],
     expires_in_seconds: i64,
 ) -> Result<String, jsonwebtoken::errors::Error> {
@@ -34,6 +37,7 @@ pub fn create_token(
     let exp = (now + Duration::minutes(expires_in_seconds)).timestamp() as usize;
     let claims = TokenClaims {
         sub: user_id.to_string(),
+        sid: session_id.to_string(),
         iat,
         exp,
     };
@@ -48,7 +52,7 @@ pub fn create_token(
 pub fn decode_token<T: Into<String>>(
     token: T,
     secret: &[u8]
-) -> Result<String, HttpError> {
+) -> Result<TokenClaims, HttpError> {
     let decode = decode::<TokenClaims>(
         &token.into(), 
         &DecodingKey::from_secret(secret), 
@@ -56,8 +60,64 @@ pub fn decode_token<T: Into<String>>(
     );
 
     match decode {
-        Ok(token) => Ok(token.claims.sub),
+        Ok(token) => Ok(token.claims),
         Err(_) => Err(HttpError::new(ErrorMessage::InvalidToken.to_string(), StatusCode::UNAUTHORIZED))
     }
 }
 
+const TWO_FACTOR_PURPOSE: &str = "2fa";
+
+/// Claims of the short lived token issued between the password and the 2FA step.
+/// It has no `sid`, so it never passes as an access token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingClaims {
+    pub sub: String,
+    pub purpose: String,
+    pub iat: usize,
+    pub exp: usize,
+}
+
+pub fn create_pending_token(
+    user_id: &str,
+    secret: &[u8],
+    expires_in_minutes: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    if user_id.is_empty() {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidSubject.into());
+    }
+
+    let now = Utc::now();
+    let claims = PendingClaims {
+        sub: user_id.to_string(),
+        purpose: TWO_FACTOR_PURPOSE.to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::minutes(expires_in_minutes)).timestamp() as usize,
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret))
+}
+
+pub fn decode_pending_token<T: Into<String>>(
+    token: T,
+    secret: &[u8]
+) -> Result<String, HttpError> {
+    let decode = decode::<PendingClaims>(
+        &token.into(),
+        &DecodingKey::from_secret(secret),
+        &Validation::new(Algorithm::HS256),
+    );
+
+    match decode {
+        Ok(token) if token.claims.purpose == TWO_FACTOR_PURPOSE => Ok(token.claims.sub),
+        _ => Err(HttpError::new(ErrorMessage::InvalidToken.to_string(), StatusCode::UNAUTHORIZED))
+    }
+}
+
+/// Opaque refresh token handed to the client, only its hash is stored.
+pub fn create_refresh_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
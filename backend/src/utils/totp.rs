@@ -0,0 +1,185 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha1::Sha1;
+
+use crate::error::ErrorMessage;
+use crate::utils::password;
+
+const SECRET_LENGTH: usize = 20;
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+// Accept one step of clock drift either way
+const ALLOWED_DRIFT: i64 = 1;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC).to_string();
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer, account, secret, issuer, DIGITS, STEP_SECONDS
+    )
+}
+
+/// RFC 4226 HOTP value for the given counter.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    binary % 10u32.pow(DIGITS)
+}
+
+pub fn current_step(unix_time: i64) -> i64 {
+    unix_time / STEP_SECONDS
+}
+
+/// Checks a code against the RFC 6238 steps around `unix_time`.
+/// Returns the matched step, steps at or before `last_used_step` are rejected so a code works only once.
+pub fn verify(secret: &str, code: &str, unix_time: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+
+    let now = current_step(unix_time);
+    (now - ALLOWED_DRIFT..=now + ALLOWED_DRIFT)
+        .filter(|step| *step >= 0 && last_used_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&secret, *step as u64) == code)
+}
+
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_LENGTH];
+            OsRng.fill_bytes(&mut bytes);
+            let code = BASE32_NOPAD.encode(&bytes)[..RECOVERY_CODE_LENGTH].to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are stored like passwords, typed in they may come with other casing or surrounding space.
+pub fn hash_recovery_code(code: &str) -> Result<String, ErrorMessage> {
+    password::hash(normalize_recovery_code(code))
+}
+
+pub fn verify_recovery_code(code: &str, code_hash: &str) -> bool {
+    password::compare(&normalize_recovery_code(code), code_hash).unwrap_or(false)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B, SHA-1 with the ASCII key "12345678901234567890". The RFC lists
+    // 8 digit values, the last 6 digits are what a 6 digit code shows.
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    const RFC_VECTORS: [(i64, &str); 6] = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    fn code_at(unix_time: i64) -> String {
+        let secret = BASE32_NOPAD.decode(RFC_SECRET.as_bytes()).unwrap();
+        format!("{:06}", hotp(&secret, current_step(unix_time) as u64))
+    }
+
+    #[test]
+    fn rfc6238_vectors() {
+        for (time, code) in RFC_VECTORS {
+            assert_eq!(code_at(time), code, "at {}", time);
+            assert_eq!(verify(RFC_SECRET, code, time, None), Some(current_step(time)), "at {}", time);
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_drift() {
+        let time = 1111111111;
+        let step = current_step(time);
+
+        assert_eq!(verify(RFC_SECRET, &code_at(time - STEP_SECONDS), time, None), Some(step - 1));
+        assert_eq!(verify(RFC_SECRET, &code_at(time + STEP_SECONDS), time, None), Some(step + 1));
+        assert_eq!(verify(RFC_SECRET, &code_at(time - 2 * STEP_SECONDS), time, None), None);
+        assert_eq!(verify(RFC_SECRET, &code_at(time + 2 * STEP_SECONDS), time, None), None);
+    }
+
+    #[test]
+    fn code_works_once() {
+        let time = 1234567890;
+        let step = current_step(time);
+        let code = code_at(time);
+
+        assert_eq!(verify(RFC_SECRET, &code, time, Some(step - 1)), Some(step));
+        assert_eq!(verify(RFC_SECRET, &code, time, Some(step)), None);
+        // A later code is still fine after an earlier one was used
+        assert_eq!(verify(RFC_SECRET, &code_at(time + STEP_SECONDS), time, Some(step)), Some(step + 1));
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        let time = 59;
+        assert_eq!(verify(RFC_SECRET, " 287082 ", time, None), Some(1));
+        assert_eq!(verify(RFC_SECRET, "28708", time, None), None);
+        assert_eq!(verify(RFC_SECRET, "2870822", time, None), None);
+        assert_eq!(verify(RFC_SECRET, "28708a", time, None), None);
+        assert_eq!(verify(RFC_SECRET, "+87082", time, None), None);
+        assert_eq!(verify("not base32!", "287082", time, None), None);
+    }
+
+    #[test]
+    fn generated_secret_round_trips() {
+        let secret = generate_secret();
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), SECRET_LENGTH);
+
+        let time = 1_700_000_000;
+        let bytes = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        let code = format!("{:06}", hotp(&bytes, current_step(time) as u64));
+        assert_eq!(verify(&secret, &code, time, None), Some(current_step(time)));
+    }
+
+    #[test]
+    fn recovery_codes() {
+        let codes = generate_recovery_codes(10);
+        assert_eq!(codes.len(), 10);
+        for code in &codes {
+            assert_eq!(code.len(), RECOVERY_CODE_LENGTH + 1);
+            assert_eq!(&code[5..6], "-");
+            assert_eq!(code, &code.to_lowercase());
+        }
+
+        let hash = hash_recovery_code(&codes[0]).unwrap_or_else(|e| panic!("{}", e));
+        assert_ne!(hash, codes[0]);
+        assert!(verify_recovery_code(&codes[0], &hash));
+        assert!(verify_recovery_code(&format!("  {} ", codes[0].to_uppercase()), &hash));
+        assert!(!verify_recovery_code(&codes[1], &hash));
+        assert!(!verify_recovery_code("", &hash));
+    }
+}